@@ -1,64 +1,562 @@
 use anyhow::{anyhow, Result};
-use base64;
-use clap::Parser;
+use clap::builder::{PossibleValue, PossibleValuesParser};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use clap_complete::Shell;
 use colored::Colorize;
-use dirs;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{debug, error, info, warn, LevelFilter};
+use notify::{EventKind, RecursiveMode, Watcher};
 use once_cell::sync::Lazy;
 use prettytable::{format, Attr, Cell, Row, Table};
+use rusqlite::OptionalExtension;
 use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::hash::{Hash, Hasher};
-use std::io::{self, Write};
+use std::hash::Hasher;
+use std::io::{BufRead, IsTerminal, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
-use std::time::{Instant, SystemTime};
-use toml;
+use std::time::{Duration, Instant, SystemTime};
 use twox_hash::XxHash64;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 use walkdir::WalkDir;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 struct Config {
     #[serde(default)]
     aliases: HashMap<String, String>,
+    /// Default for --dates (relative, iso)
+    dates: Option<String>,
+    /// Per-path scan settings, keyed by path prefix
+    #[serde(default)]
+    profiles: HashMap<String, ScanProfile>,
+    /// Default for --cache-backend (json, sqlite)
+    cache_backend: Option<String>,
+    /// Default for --columns
+    columns: Option<Vec<String>>,
+    /// Default for --cache-mode (central, local)
+    cache_mode: Option<String>,
+    /// Default for --backend (auto, ffprobe, native)
+    backend: Option<String>,
+    /// Default for --sort
+    sort: Option<String>,
+    /// Default for --direction (asc, desc)
+    direction: Option<String>,
+    /// Default for --filename-length
+    filename_length: Option<usize>,
+    /// Filters applied when no --filter is given
+    #[serde(default)]
+    filters: Vec<String>,
+    /// Default for --cache-dir
+    cache_dir: Option<PathBuf>,
+    /// Default for --jobs
+    jobs: Option<usize>,
+    /// Default for `watch --exec`
+    watch_exec: Option<String>,
+    /// Default for `watch --feed`
+    watch_feed: Option<PathBuf>,
+    /// Default for --plain
+    #[serde(default)]
+    plain: bool,
+    /// Table colors used with --color
+    #[serde(default)]
+    highlight: Highlight,
+}
+
+/// `[highlight]` config: bitrates outside the bounds (in Mbps) are shown in
+/// red, and files matching every `target` filter get a green filename.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Highlight {
+    bitrate_below: Option<f64>,
+    bitrate_above: Option<f64>,
+    #[serde(default)]
+    target: Vec<String>,
+}
+
+/// Overrides for files under a path prefix, e.g. fewer jobs and a longer
+/// timeout for a NAS mount than for a local SSD.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ScanProfile {
+    jobs: Option<usize>,
+    timeout: Option<u64>,
+    signature: Option<String>,
+    xattr: Option<bool>,
+    #[serde(default)]
+    exclude: Vec<String>,
 }
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, disable_version_flag = true)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// Media files or directories to analyze
-    #[arg(required_unless_present = "cached")]
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Media files or directories to analyze ('-' reads from stdin)
+    #[arg(required_unless_present_any = ["cached", "from_json", "list_columns", "version", "files_from"])]
     paths: Vec<PathBuf>,
 
-    /// Sort by column (filename, size, duration, fps, bitrate, resolution, format, profile, depth, audio)
-    #[arg(short, long, default_value = "bitrate", value_parser = ["filename", "size", "duration", "fps", "bitrate", "resolution", "format", "profile", "depth", "audio"])]
+    /// Also analyze the paths listed in FILE, one per line ('-' reads the list from stdin)
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+
+    /// Paths in --files-from are separated by NUL bytes, as written by `find -print0` or `fd -0`
+    #[arg(short = '0', long, requires = "files_from")]
+    null: bool,
+
+    /// Sort by column
+    #[arg(short, long, default_value = "bitrate", value_parser = parse_column_arg)]
     sort: String,
 
     /// Sort direction (asc, desc)
     #[arg(short = 'd', long, default_value = "desc", value_parser = ["asc", "desc"])]
     direction: String,
 
-    /// Filter results (format: column:operator:value, e.g., 'bitrate:>:5' for bitrate > 5 Mbps)
+    /// Filter results (format: column:operator:value or column=value, e.g. 'bitrate:>:5' for bitrate > 5 Mbps; comma lists match any value, a leading '!' negates)
     #[arg(short, long)]
     filter: Vec<String>,
 
-    /// Maximum length for filenames (default: 65)
+    /// Keep rows matching at least one of these filters (OR), in addition to all --filter ones
+    #[arg(long)]
+    filter_any: Vec<String>,
+
+    /// Exit with status 3 if any displayed file matches one of these filters (same syntax as --filter), for use as a quality gate
+    #[arg(long, value_name = "FILTER")]
+    fail_if: Vec<String>,
+
+    /// Exit with status 4 if no file is left to display
+    #[arg(long)]
+    fail_if_empty: bool,
+
+    /// Match filename filters ignoring accents and character width ("Amelie" matches "Amélie")
+    #[arg(long)]
+    ignore_diacritics: bool,
+
+    /// Maximum length for filenames in the table (default: 65); json, csv and tsv output always has full names
     #[arg(short = 'l', long, default_value = "65")]
     filename_length: usize,
 
+    /// Split the table into one section per directory, video codec, resolution class or bit depth, each with subtotals
+    #[arg(long, value_parser = ["dir", "codec", "resolution", "depth"])]
+    group_by: Option<String>,
+
     /// Show only cached entries
     #[arg(long)]
     cached: bool,
 
-    /// Skip cache and force ffprobe (but update cache with results)
-    #[arg(long)]
+    /// Directory for the cache files [default: $XDG_CACHE_HOME/mediainfo or ~/.cache/mediainfo]
+    #[arg(long, env = "MEDIAINFO_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Directory with images from `mediainfo thumbs`, for the thumbnail column [default: <cache dir>/thumbs]
+    #[arg(long, value_name = "DIR")]
+    thumbs_dir: Option<PathBuf>,
+
+    /// Where cache entries are kept: the central cache directory, or a .mediainfo-cache.json inside each scanned root that travels with portable drives
+    #[arg(long, value_parser = ["central", "local"])]
+    cache_mode: Option<String>,
+
+    /// Cache storage: a single JSON file, or an indexed SQLite database (cache.db)
+    #[arg(long, value_parser = ["json", "sqlite"])]
+    cache_backend: Option<String>,
+
+    /// Probing backend: ffprobe, or the built-in parser for MP4/MOV, Matroska/WebM and common audio files (duration, resolution, codecs and bitrate only). auto uses ffprobe when it is installed
+    #[arg(long, value_parser = ["auto", "ffprobe", "native"])]
+    backend: Option<String>,
+
+    /// Skip cache and force ffprobe (but update cache with results), reporting fields that changed since the cached probe
+    #[arg(long, visible_alias = "refresh")]
     no_cache: bool,
 
+    /// Only walk the paths and report file counts and total size per extension and directory
+    #[arg(long, conflicts_with_all = ["cached", "from_json"])]
+    count: bool,
+
+    /// Output format (table, json, csv, tsv, markdown, html)
+    #[arg(short = 'o', long, default_value = "table", value_parser = ["table", "json", "csv", "tsv", "markdown", "html"])]
+    output: String,
+
+    /// Screen-reader friendly output: one "Column: value" line per field instead of a table, tables without box drawing, no progress bars
+    #[arg(long, global = true)]
+    plain: bool,
+
+    /// Only print warnings and errors on stderr, no status messages
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also print cache and probing details on stderr (-vv for more)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Write json/csv/tsv output to a file instead of stdout
+    #[arg(long)]
+    out_file: Option<PathBuf>,
+
+    /// Print one line per file from FORMAT instead of a table, e.g. "{filename}\t{bitrate_bps}". Placeholders are column ids (formatted values) and the raw path, size_bytes, duration_s, bitrate_bps, width and height
+    #[arg(long, conflicts_with_all = ["group_by", "count"])]
+    template: Option<String>,
+
+    /// Header of table/csv/tsv/markdown output: a normal header row, a '#'-prefixed comment line (not for markdown), or none
+    #[arg(long, default_value = "normal", value_parser = ["normal", "comment", "none"])]
+    header: String,
+
+    /// Leave out the header row (same as --header none)
+    #[arg(long, conflicts_with = "header")]
+    no_header: bool,
+
+    /// Add raw numeric companion columns (size_bytes, duration_seconds, bitrate_bps) to json/csv/tsv output
+    #[arg(long)]
+    raw_columns: bool,
+
+    /// Print size, duration and bitrate as plain integers (bytes, seconds, bps) in every output format
+    #[arg(long)]
+    raw: bool,
+
+    /// Re-render a result set previously exported with --output json instead of scanning
+    #[arg(long, conflicts_with = "cached")]
+    from_json: Option<PathBuf>,
+
+    /// Emit clickable file:// hyperlinks for filenames (auto, always, never)
+    #[arg(long, default_value = "auto", value_parser = ["auto", "always", "never"])]
+    hyperlinks: String,
+
+    /// Color table values: out-of-range bitrates, 8-bit HEVC, files matching the target profile (auto, always, never)
+    #[arg(long, default_value = "auto", value_parser = ["auto", "always", "never"])]
+    color: String,
+
+    /// Show Modified/Created date columns, as relative ("3 days ago") or ISO timestamps
+    #[arg(long, value_parser = ["relative", "iso"])]
+    dates: Option<String>,
+
+    /// Columns to show, in order (comma-separated, e.g. filename,size,bitrate), replacing the default layout
+    #[arg(long, value_delimiter = ',', value_parser = parse_column_arg)]
+    columns: Vec<String>,
+
+    /// Print version and environment details: ffprobe, cache, config and features
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// List every column's stable id, header and aliases, then exit
+    #[arg(long)]
+    list_columns: bool,
+
+    /// Append optional columns to the table (comma-separated, e.g. rating,modified)
+    #[arg(long, value_delimiter = ',', value_parser = parse_column_arg)]
+    add_columns: Vec<String>,
+
+    /// Group multi-part files (file.001.mkv, VOB segments, GoPro chapters) into one item
+    #[arg(long)]
+    stitch: bool,
+
+    /// Compare size/bitrate against a previous --output json scan, matched by relative path
+    #[arg(long)]
+    reference: Option<PathBuf>,
+
+    /// Map a path prefix used in the cache or an exported result set to where it is mounted here (FROM=TO, repeatable)
+    #[arg(long, value_name = "FROM=TO", value_parser = parse_path_map)]
+    map: Vec<(PathBuf, PathBuf)>,
+
     /// Use a predefined alias from config file
     #[arg(short = 'a', long)]
     alias: Option<String>,
+
+    /// Treat inputs as capture device identifiers and list their supported modes
+    #[arg(long)]
+    device: bool,
+
+    /// Capture API used with --device (v4l2, avfoundation, dshow; default: platform native)
+    #[arg(long, value_parser = ["v4l2", "avfoundation", "dshow"])]
+    device_format: Option<String>,
+
+    /// Skip files and directories matching a glob, by name (@eaDir, *sample*) or by path relative to the scanned root (extras/**); repeatable
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Only scan files with these extensions (comma-separated, e.g. mp4,mkv), instead of every known media extension
+    #[arg(long, value_delimiter = ',')]
+    ext: Vec<String>,
+
+    /// Descend at most this many directory levels below each scanned root (1 = only files directly in it)
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Also probe unfinished downloads (movie.mkv.part, .crdownload, ...), which are skipped by default
+    #[arg(long)]
+    include_partial: bool,
+
+    /// Descend into symlinked directories, which are skipped by default
+    #[arg(long, conflicts_with = "no_follow_symlinks")]
+    follow_symlinks: bool,
+
+    /// Skip symlinks altogether, including symlinked files
+    #[arg(long)]
+    no_follow_symlinks: bool,
+
+    /// Number of files to probe in parallel [default: number of CPUs]
+    #[arg(short = 'j', long)]
+    jobs: Option<usize>,
+
+    /// Abort a single probe after this many seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Seconds of a network stream (rtsp://, udp://, ...) to read when detecting its codecs and bitrate
+    #[arg(long, value_name = "SECS", default_value = "5")]
+    stream_window: u64,
+
+    /// Stop probing once this much time has passed (e.g. 10m, 1h30m) and show what completed
+    #[arg(long, value_name = "DURATION", value_parser = parse_max_runtime)]
+    max_runtime: Option<Duration>,
+
+    /// How cache entries are validated: size+mtime, size only, or a partial content hash
+    #[arg(long, default_value = "mtime", value_parser = ["mtime", "size", "hash"])]
+    signature: String,
+
+    /// Also record cache signatures in extended file attributes, so moved or copied files keep their cache entries
+    #[arg(long)]
+    xattr: bool,
+
+    /// Number of times to retry probes that fail with transient I/O errors
+    #[arg(long, default_value = "2")]
+    retries: u32,
+
+    /// Initial delay in milliseconds before retrying, doubled after each attempt
+    #[arg(long, default_value = "500")]
+    retry_delay: u64,
+
+    /// Count files deleted or moved between scanning and probing as errors instead of reporting them separately
+    #[arg(long)]
+    vanished_as_errors: bool,
+
+    /// Print a one-line JSON summary of the run (files, cache hits, errors, filtered, total size) to stderr, stdout or a file descriptor number
+    #[arg(long, value_name = "TARGET", num_args = 0..=1, require_equals = true, default_missing_value = "stderr")]
+    summary_json: Option<String>,
+
+    /// Print totals after the results: file count, size, duration, average bitrate and a per-codec breakdown
+    #[arg(long)]
+    summary: bool,
+
+    /// Instead of the results, list per audio language how many of the displayed files have an audio track in it, and which files lack any of the given languages
+    #[arg(long, value_name = "LANGS", num_args = 0..=1, require_equals = true, value_delimiter = ',')]
+    audio_languages: Option<Vec<String>>,
+
+    /// Like --audio-languages, for subtitle tracks and sidecar subtitle files
+    #[arg(long, value_name = "LANGS", num_args = 0..=1, require_equals = true, value_delimiter = ',', conflicts_with = "audio_languages")]
+    subtitle_languages: Option<Vec<String>>,
+
+    /// Write a structured JSON-lines record of the run (files, timings, cache hits, errors)
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Print a shell completion script, including column names for --sort, --columns and --filter
+    Completions {
+        /// Shell to generate the script for
+        shell: Shell,
+    },
+    /// Merge result sets exported with --output json into a single one
+    Merge {
+        /// Result files to merge (later files win on duplicates)
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Deduplicate entries by path or by content fingerprint (size, duration, streams)
+        #[arg(long, default_value = "path", value_parser = ["path", "content"])]
+        dedupe: String,
+    },
+    /// Watch directories and probe new or modified media files as they appear, keeping the cache warm
+    Watch {
+        /// Directories to watch (recursively)
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+
+        /// Print a tab-separated row for every probed file
+        #[arg(long)]
+        print: bool,
+
+        /// Seconds a file must go unchanged before it is probed, so downloads and copies can finish
+        #[arg(long, default_value = "2")]
+        settle: u64,
+
+        /// Shell command to run for every probed file, with its details in MEDIAINFO_* environment variables and as JSON on stdin
+        #[arg(long, value_name = "COMMAND")]
+        exec: Option<String>,
+
+        /// Keep an Atom feed of the newest probed files at this path, for services that poll
+        #[arg(long, value_name = "FILE")]
+        feed: Option<PathBuf>,
+
+        /// Number of files the feed keeps
+        #[arg(long, default_value = "50", value_name = "N")]
+        feed_size: usize,
+    },
+    /// Import MediaInfo XML or JSON exports into the cache, so files catalogued with MediaInfo aren't probed again
+    Import {
+        /// Exports written by `mediainfo --Output=XML` or `--Output=JSON`
+        #[arg(required = true)]
+        exports: Vec<PathBuf>,
+
+        /// Also print the imported entries as a result set (like --output json)
+        #[arg(long)]
+        print: bool,
+
+        /// Translate paths from the machine the exports were made on (repeatable)
+        #[arg(long, value_name = "FROM=TO", value_parser = parse_path_map)]
+        map: Vec<(PathBuf, PathBuf)>,
+    },
+    /// Look up cached files by the IDs from the id column or JSON output
+    Show {
+        /// File IDs; a unique prefix is enough
+        #[arg(required = true)]
+        ids: Vec<String>,
+
+        /// Only print the paths, one per line
+        #[arg(long)]
+        path: bool,
+    },
+    /// Tag files with a triage decision (keep, delete, re-encode or any other word), shown in the tags column
+    Tag {
+        /// Files to tag
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Tag to add, e.g. keep, delete or re-encode
+        tag: String,
+
+        /// Remove the tag instead of adding it
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Run a command on every file carrying a tag, e.g. `apply --tag delete --exec trash`
+    Apply {
+        /// Only files under these paths [default: all tagged files]
+        paths: Vec<PathBuf>,
+
+        /// Tag the files must carry
+        #[arg(long, required = true)]
+        tag: String,
+
+        /// Shell command to run for each file; the path replaces {} or is appended, and is in MEDIAINFO_PATH
+        #[arg(long, required_unless_present = "dry_run", value_name = "COMMAND")]
+        exec: Option<String>,
+
+        /// Only list the files the command would run on
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Remove the tag from files the command succeeded on
+        #[arg(long)]
+        untag: bool,
+    },
+    /// Print library analytics: codec, resolution, bit depth and container breakdowns and a bitrate histogram
+    Stats {
+        /// Files or directories to analyze
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Output format
+        #[arg(short = 'o', long, default_value = "table", value_parser = ["table", "json"])]
+        output: String,
+
+        /// Write the report to a file instead of stdout
+        #[arg(long)]
+        out_file: Option<PathBuf>,
+    },
+    /// Find folders or TV shows whose files differ in resolution, video codec or audio setup
+    Consistency {
+        /// Files or directories to check
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Compare files per folder, or per show parsed from S01E02-style names
+        #[arg(long, default_value = "dir", value_parser = ["dir", "show"])]
+        by: String,
+
+        /// Output format
+        #[arg(short = 'o', long, default_value = "table", value_parser = ["table", "json"])]
+        output: String,
+
+        /// Write the report to a file instead of stdout
+        #[arg(long)]
+        out_file: Option<PathBuf>,
+    },
+    /// Estimate how much space re-encoding each video would save, largest savings first
+    Savings {
+        /// Files or directories to analyze
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Codec to re-encode to, which sets the bits per pixel a good encode needs
+        #[arg(long, default_value = "hevc", value_parser = ["h264", "hevc", "av1"])]
+        target: String,
+
+        /// Bits per pixel of the re-encoded video, instead of the target codec's default
+        #[arg(long)]
+        bpp: Option<f64>,
+
+        /// Output format
+        #[arg(short = 'o', long, default_value = "table", value_parser = ["table", "json"])]
+        output: String,
+
+        /// Write the report to a file instead of stdout
+        #[arg(long)]
+        out_file: Option<PathBuf>,
+    },
+    /// Extract a thumbnail or contact sheet per video with ffmpeg, named after the file ID
+    Thumbs {
+        /// Files or directories to make thumbnails for
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Directory to write the images to [default: <cache dir>/thumbs]
+        #[arg(long, value_name = "DIR")]
+        out: Option<PathBuf>,
+
+        /// Make a contact sheet of COLSxROWS frames spread over the video instead of a single frame
+        #[arg(long, value_name = "COLSxROWS", value_parser = parse_grid)]
+        grid: Option<(u32, u32)>,
+
+        /// Width of each frame in pixels
+        #[arg(long, default_value = "320")]
+        width: u32,
+
+        /// Parallel ffmpeg processes [default: CPU count]
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Recreate images that already exist
+        #[arg(long)]
+        force: bool,
+    },
+    /// Decode files completely with ffmpeg to find corrupt or truncated ones
+    Verify {
+        /// Files or directories to verify
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Output format
+        #[arg(short = 'o', long, default_value = "table", value_parser = ["table", "json"])]
+        output: String,
+
+        /// Parallel ffmpeg processes [default: CPU count]
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Only list files that failed to decode
+        #[arg(long)]
+        failed: bool,
+
+        /// Decode every file again, even if it passed before and hasn't changed
+        #[arg(long)]
+        no_cache: bool,
+    },
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -69,47 +567,376 @@ struct FFProbeOutput {
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct Stream {
+    index: Option<i32>,
     codec_type: String,
     codec_name: Option<String>,
+    codec_tag_string: Option<String>,
     profile: Option<String>,
     width: Option<i32>,
     height: Option<i32>,
     r_frame_rate: Option<String>,
     avg_frame_rate: Option<String>,
+    duration: Option<String>,
     display_aspect_ratio: Option<String>,
     bit_rate: Option<String>,
     pix_fmt: Option<String>,
     color_space: Option<String>,
     color_range: Option<String>,
+    color_transfer: Option<String>,
+    color_primaries: Option<String>,
     channels: Option<i32>,
+    channel_layout: Option<String>,
     sample_rate: Option<String>,
+    bits_per_sample: Option<i32>,
+    bits_per_raw_sample: Option<String>,
     tags: Option<HashMap<String, String>>,
+    side_data_list: Option<Vec<serde_json::Value>>,
+    disposition: Option<HashMap<String, i32>>,
+    /// Every other ffprobe field, kept so JSON output has the full stream details
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct Format {
     filename: String,
+    // Missing when probing from a pipe
+    #[serde(default)]
     size: String,
+    #[serde(default)]
     duration: String,
     bit_rate: Option<String>,
+    tags: Option<HashMap<String, String>>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+struct ColumnDef {
+    /// Stable identifier used in flags, config, JSON keys and CSV headers;
+    /// unlike the header text it never changes
+    id: &'static str,
+    header: &'static str,
+    /// prettytable alignment spec (l, r, c)
+    align: &'static str,
+}
+
+/// All known columns. Optional ones are only shown when enabled.
+#[rustfmt::skip]
+const COLUMN_DEFS: &[ColumnDef] = &[
+    ColumnDef { id: "filename", header: "Filename", align: "l" },
+    ColumnDef { id: "duration", header: "Duration", align: "r" },
+    ColumnDef { id: "fps", header: "FPS", align: "r" },
+    ColumnDef { id: "size", header: "Size", align: "r" },
+    ColumnDef { id: "bitrate", header: "Bitrate", align: "r" },
+    ColumnDef { id: "resolution", header: "Resolution", align: "l" },
+    ColumnDef { id: "aspect", header: "Aspect", align: "r" },
+    ColumnDef { id: "orientation", header: "Orientation", align: "l" },
+    ColumnDef { id: "format", header: "Format", align: "l" },
+    ColumnDef { id: "profile", header: "Profile", align: "l" },
+    ColumnDef { id: "depth", header: "Depth", align: "c" },
+    ColumnDef { id: "color", header: "Color", align: "l" },
+    ColumnDef { id: "hdr", header: "HDR", align: "l" },
+    ColumnDef { id: "audio", header: "Audio", align: "l" },
+    ColumnDef { id: "audio_tracks", header: "Audio #", align: "r" },
+    ColumnDef { id: "audio_codec", header: "Audio Codec", align: "l" },
+    ColumnDef { id: "sample_rate", header: "Sample Rate", align: "r" },
+    ColumnDef { id: "channels", header: "Channels", align: "l" },
+    ColumnDef { id: "audio_depth", header: "Audio Depth", align: "c" },
+    ColumnDef { id: "audio_bitrate", header: "Audio Bitrate", align: "r" },
+    ColumnDef { id: "audio_streams", header: "Audio Streams", align: "l" },
+    ColumnDef { id: "audio_lang", header: "Audio Lang", align: "l" },
+    ColumnDef { id: "forced_subs", header: "Forced Subs", align: "c" },
+    ColumnDef { id: "subtitles", header: "Subtitles", align: "l" },
+    ColumnDef { id: "events", header: "Events", align: "r" },
+    ColumnDef { id: "encoder", header: "Encoder", align: "l" },
+    ColumnDef { id: "timecode", header: "Timecode", align: "r" },
+    ColumnDef { id: "telemetry", header: "Telemetry", align: "l" },
+    ColumnDef { id: "stereo", header: "3D", align: "l" },
+    ColumnDef { id: "cover", header: "Cover Art", align: "l" },
+    ColumnDef { id: "segments", header: "Parts", align: "r" },
+    ColumnDef { id: "rating", header: "Rating", align: "l" },
+    ColumnDef { id: "tags", header: "Tags", align: "l" },
+    ColumnDef { id: "title", header: "Title", align: "l" },
+    ColumnDef { id: "thumbnail", header: "Thumbnail", align: "l" },
+    ColumnDef { id: "modified", header: "Modified", align: "r" },
+    ColumnDef { id: "created", header: "Created", align: "r" },
+    ColumnDef { id: "first_seen", header: "First Seen", align: "r" },
+    ColumnDef { id: "last_seen", header: "Last Seen", align: "r" },
+    ColumnDef { id: "cache", header: "Cache", align: "l" },
+    ColumnDef { id: "id", header: "ID", align: "l" },
+    ColumnDef { id: "reencode", header: "Re-encode", align: "r" },
+    ColumnDef { id: "bpp", header: "BPP", align: "r" },
+    ColumnDef { id: "share", header: "% of Total", align: "r" },
+    ColumnDef { id: "size_delta", header: "Size Δ", align: "r" },
+    ColumnDef { id: "bitrate_delta", header: "Bitrate Δ", align: "r" },
+];
+
+/// Short names accepted wherever a column is named (--sort, --filter,
+/// --add-columns), mapped to their column id.
+const COLUMN_ALIASES: [(&str, &str); 6] = [
+    ("res", "resolution"),
+    ("br", "bitrate"),
+    ("len", "duration"),
+    ("dur", "duration"),
+    ("codec", "format"),
+    ("priority", "reencode"),
+];
+
+/// Columns shown by default, in display order.
+const DEFAULT_COLUMNS: [&str; 11] = [
+    "filename",
+    "duration",
+    "fps",
+    "size",
+    "bitrate",
+    "resolution",
+    "format",
+    "profile",
+    "depth",
+    "color",
+    "audio",
+];
+
+/// Default columns when none of the files has a video stream (music libraries).
+const AUDIO_DEFAULT_COLUMNS: [&str; 9] = [
+    "filename",
+    "duration",
+    "size",
+    "bitrate",
+    "audio_codec",
+    "sample_rate",
+    "channels",
+    "audio_depth",
+    "audio_bitrate",
+];
+
+/// Default columns when every input is a standalone subtitle file.
+const SUBTITLE_DEFAULT_COLUMNS: [&str; 5] = ["filename", "duration", "size", "subtitles", "events"];
+
+/// Settings that affect how probe data is turned into column values.
+struct DisplayOptions {
+    /// Columns being displayed, so costly values are only computed when needed
+    columns: Vec<&'static str>,
+    /// Date display mode (relative, iso)
+    dates: String,
+    reference: Option<ReferenceSet>,
+}
+
+/// A previous scan to compare against. Both sides are keyed by their path
+/// relative to the common directory of their set, so scans of the same
+/// library mounted elsewhere still line up.
+struct ReferenceSet {
+    entries: HashMap<PathBuf, FFProbeOutput>,
+    current_root: PathBuf,
+}
+
+/// Columns rendered as plain integers with `--raw`
+const RAW_VALUE_COLUMNS: [&str; 3] = ["size", "duration", "bitrate"];
+/// Raw numeric companions of the formatted size, duration and bitrate columns,
+/// so spreadsheets and scripts can sort without parsing "1.23 GB".
+const RAW_COLUMN_NAMES: [&str; 3] = ["size_bytes", "duration_seconds", "bitrate_bps"];
+/// Unformatted --template placeholders, usable in calculations as is
+const TEMPLATE_RAW_PLACEHOLDERS: [&str; 6] = [
+    "path",
+    "size_bytes",
+    "duration_s",
+    "bitrate_bps",
+    "width",
+    "height",
+];
+
+struct ResultRow {
+    path: PathBuf,
+    probe: FFProbeOutput,
+    /// Formatted values keyed by column id
+    values: HashMap<&'static str, String>,
+    /// Exact numbers behind the NUMERIC_VALUE_COLUMNS, see `numeric_values`;
+    /// `share` is added once the displayed set is known
+    numbers: HashMap<&'static str, f64>,
+}
+
+/// Columns that sort and filter on the probed number rather than the
+/// rounded display text ("1.23 GB", "23.98")
+const NUMERIC_VALUE_COLUMNS: [&str; 11] = [
+    "size",
+    "duration",
+    "bitrate",
+    "fps",
+    "resolution",
+    "aspect",
+    "audio_bitrate",
+    "reencode",
+    "bpp",
+    "created",
+    "share",
+];
+
+/// An entry of a previously exported `--output json` result set.
+#[derive(Debug, Deserialize)]
+struct ExportedEntry {
+    path: PathBuf,
+    probe: FFProbeOutput,
+}
+
+struct RetryPolicy {
+    retries: u32,
+    base_delay: Duration,
+}
+
+/// Effective probe settings for one scanned root.
+struct ScanSettings {
+    jobs: usize,
+    timeout: Option<Duration>,
+    /// Cache signature mode (mtime, size, hash)
+    signature: String,
+    /// Store signatures in extended attributes next to the central cache
+    xattr: bool,
+    exclude: GlobSet,
+    /// Extensions to scan (lowercase, without the dot); empty scans every media extension
+    extensions: Vec<String>,
+    max_depth: Option<usize>,
+    /// Probe unfinished downloads too
+    include_partial: bool,
+    /// Some(true) descends into symlinked directories, Some(false) skips all
+    /// symlinks; by default symlinked files are scanned but not directories
+    follow_symlinks: Option<bool>,
+    retry: RetryPolicy,
+    /// Probing backend (ffprobe, native)
+    backend: String,
+    /// How much of a network stream ffprobe reads
+    stream_window: Duration,
+}
+
+/// Exit status when a `--fail-if` filter matched a displayed file
+const EXIT_FAIL_IF: i32 = 3;
+/// Exit status for `--fail-if-empty` when nothing was left to display
+const EXIT_NO_FILES: i32 = 4;
+/// Exit status for `verify` when a file failed to decode
+const EXIT_VERIFY_FAILED: i32 = 5;
+
+/// Scan health reported by `--summary-json`
+#[derive(Default, Serialize)]
+struct RunSummary {
+    files: usize,
+    cache_hits: usize,
+    errors: usize,
+    vanished: usize,
+    /// Files left unprobed when --max-runtime ran out
+    unprobed: usize,
+    filtered: usize,
+    displayed: usize,
+    total_size_bytes: u64,
+    elapsed_secs: f64,
+}
+
+struct ProbeProgress {
+    total: usize,
+    processed: usize,
+    cached: usize,
+    failed: usize,
+    /// Files that disappeared between the walk and their probe
+    vanished: Vec<PathBuf>,
+    start: Instant,
+    /// Files answered from the cache, for the cache column
+    cache_hits: HashSet<PathBuf>,
+    bar: ProgressBar,
+    /// When --max-runtime runs out; no new probes are started after it
+    deadline: Option<Instant>,
+    unprobed: usize,
+}
+
+#[derive(Debug)]
+struct DeviceMode {
+    pixel_format: String,
+    resolution: String,
+    fps: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 struct CacheEntry {
     signature: String,
     probe_data: FFProbeOutput,
+    /// Unix time the file was first probed
+    #[serde(default)]
+    first_seen: Option<i64>,
+    /// Unix time the file was last encountered in a scan
+    #[serde(default)]
+    last_seen: Option<i64>,
+    /// Unix time the stored probe data was produced by ffprobe
+    #[serde(default)]
+    probed_at: Option<i64>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// How old an entry's last_seen may get before a scan that reads it bumps it,
+/// so fully cached runs don't rewrite the cache just to record the visit
+const LAST_SEEN_RESOLUTION_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 struct Cache {
     entries: HashMap<String, CacheEntry>,
+    /// Set when entries changed without being written back (e.g. last_seen updates)
+    #[serde(skip)]
+    dirty: bool,
+}
+
+/// Just the probe results of a cache file. Signatures and timestamps are
+/// skipped while parsing, which keeps `--cached` on a huge cache lean.
+#[derive(Deserialize)]
+struct CachedProbes {
+    entries: HashMap<String, CachedProbe>,
+}
+
+#[derive(Deserialize)]
+struct CachedProbe {
+    probe_data: FFProbeOutput,
 }
 
 static CACHE: Lazy<Mutex<Option<Cache>>> = Lazy::new(|| Mutex::new(None));
+static SESSION_LOG: Lazy<Mutex<Option<fs::File>>> = Lazy::new(|| Mutex::new(None));
+/// Open when `--cache-backend sqlite` is used; CACHE stays unused then
+static SQLITE_CACHE: Lazy<Mutex<Option<rusqlite::Connection>>> = Lazy::new(|| Mutex::new(None));
+static CACHE_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+static THUMBS_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+/// Caches stored inside scan roots (`--cache-mode local`), keyed by root.
+/// Entries are keyed by their path relative to the root, so the cache stays
+/// valid wherever the drive is mounted.
+static LOCAL_CACHES: Lazy<Mutex<HashMap<PathBuf, Cache>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+/// `--map` rules as (prefix used in the cache, prefix on this machine)
+static PATH_MAPS: Lazy<Mutex<Vec<(PathBuf, PathBuf)>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static XATTR_WARNED: AtomicBool = AtomicBool::new(false);
+/// `--plain`: records instead of the result table, borderless tables and no
+/// progress bars, for screen readers
+static PLAIN: AtomicBool = AtomicBool::new(false);
+/// `--header`: how result tables and csv/tsv output start (normal, comment, none)
+static HEADER_STYLE: Lazy<Mutex<String>> = Lazy::new(|| Mutex::new("normal".to_string()));
+/// `tags.json` from the cache directory, loaded on first use
+static TAGS: Lazy<Mutex<Option<Tags>>> = Lazy::new(|| Mutex::new(None));
+/// `--color`: the config's highlight rules, or None when the table is uncolored
+static HIGHLIGHT: Lazy<Mutex<Option<Highlight>>> = Lazy::new(|| Mutex::new(None));
+/// Whether report output goes to a terminal, so tables keep their bold headers
+/// (false with --out-file or when piped)
+static STYLED_OUTPUT: AtomicBool = AtomicBool::new(false);
+/// The progress bar or spinner currently drawn on stderr, if any
+static ACTIVE_PROGRESS: Lazy<Mutex<Option<ProgressBar>>> = Lazy::new(|| Mutex::new(None));
+
+const XATTR_SIGNATURE: &str = "user.mediainfo.signature";
+const XATTR_PROBE_HASH: &str = "user.mediainfo.probe_hash";
 
+/// `~/.config/mediainfo/config.toml` (or under `$XDG_CONFIG_HOME`). The
+/// older `~/.mediainfo/config.toml` is still read while it is the only one.
 fn get_config_file() -> Result<PathBuf> {
     let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not find home directory"))?;
-    Ok(home.join(".mediainfo").join("config.toml"))
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home.join(".config"));
+    let config_file = config_home.join("mediainfo").join("config.toml");
+    let legacy_file = home.join(".mediainfo").join("config.toml");
+    if !config_file.exists() && legacy_file.exists() {
+        return Ok(legacy_file);
+    }
+    Ok(config_file)
 }
 
 fn load_config() -> Result<Config> {
@@ -118,15 +945,53 @@ fn load_config() -> Result<Config> {
         let content = fs::read_to_string(&config_path)?;
         Ok(toml::from_str(&content)?)
     } else {
-        Ok(Config {
-            aliases: HashMap::new(),
-        })
+        Ok(Config::default())
+    }
+}
+
+/// Fills in defaults from the config file for options that were not given
+/// on the command line.
+fn apply_config_defaults(
+    args: &mut Args,
+    matches: &clap::ArgMatches,
+    config: &Config,
+) -> Result<()> {
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+    if let Some(sort) = config.sort.as_deref().filter(|_| !from_cli("sort")) {
+        args.sort =
+            parse_column_arg(sort).map_err(|e| anyhow!("Invalid sort setting in config: {}", e))?;
+    }
+    if let Some(direction) = config.direction.clone().filter(|_| !from_cli("direction")) {
+        if direction != "asc" && direction != "desc" {
+            return Err(anyhow!(
+                "Invalid direction setting in config: {} (expected asc or desc)",
+                direction
+            ));
+        }
+        args.direction = direction;
+    }
+    if let Some(length) = config
+        .filename_length
+        .filter(|_| !from_cli("filename_length"))
+    {
+        args.filename_length = length;
+    }
+    if args.filter.is_empty() {
+        args.filter = config.filters.clone();
     }
+    if args.cache_dir.is_none() {
+        args.cache_dir = config.cache_dir.clone();
+    }
+    if args.jobs.is_none() {
+        args.jobs = config.jobs;
+    }
+    args.plain |= config.plain;
+    Ok(())
 }
 
-fn apply_alias(args: &mut Args) -> Result<()> {
+fn apply_alias(args: &mut Args, config: &Config) -> Result<()> {
     if let Some(alias_name) = &args.alias {
-        let config = load_config()?;
         if let Some(alias_args) = config.aliases.get(alias_name) {
             // Split the alias string into arguments
             let mut parts = Vec::new();
@@ -158,7 +1023,7 @@ fn apply_alias(args: &mut Args) -> Result<()> {
                 match parts[i].as_str() {
                     "--sort" | "-s" => {
                         if i + 1 < parts.len() {
-                            args.sort = parts[i + 1].clone();
+                            args.sort = resolve_column(&parts[i + 1]).to_string();
                             i += 2;
                         } else {
                             i += 1;
@@ -181,6 +1046,25 @@ fn apply_alias(args: &mut Args) -> Result<()> {
                             i += 1;
                         }
                     }
+                    "--columns" => {
+                        if i + 1 < parts.len() {
+                            args.columns = parts[i + 1]
+                                .split(',')
+                                .map(|c| resolve_column(c.trim()).to_string())
+                                .collect();
+                            i += 2;
+                        } else {
+                            i += 1;
+                        }
+                    }
+                    "--filter-any" => {
+                        if i + 1 < parts.len() {
+                            args.filter_any.push(parts[i + 1].clone());
+                            i += 2;
+                        } else {
+                            i += 1;
+                        }
+                    }
                     "--filename-length" | "-l" => {
                         if i + 1 < parts.len() {
                             if let Ok(len) = parts[i + 1].parse() {
@@ -195,152 +1079,630 @@ fn apply_alias(args: &mut Args) -> Result<()> {
                 }
             }
         } else {
-            eprintln!("Warning: Alias '{}' not found in config file", alias_name);
+            warn!("Alias '{}' not found in config file", alias_name);
         }
     }
     Ok(())
 }
 
 fn main() -> Result<()> {
-    let mut args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches)?;
+    if let Some(Commands::Completions { shell }) = &args.command {
+        print_completions(*shell);
+        return Ok(());
+    }
+    init_logging(args.quiet, args.verbose);
+    // Read once, so a broken config fails the run before any work is done
+    let config = load_config()?;
+    apply_config_defaults(&mut args, &matches, &config)?;
+    *CACHE_DIR.lock().unwrap() = args.cache_dir.clone();
+    PLAIN.store(args.plain, Ordering::Relaxed);
+    if args.no_header {
+        args.header = "none".to_string();
+    }
+    *HEADER_STYLE.lock().unwrap() = args.header.clone();
+    *THUMBS_DIR.lock().unwrap() = match &args.command {
+        Some(Commands::Thumbs { out, .. }) => out.clone(),
+        _ => args.thumbs_dir.clone(),
+    };
 
-    // Apply alias settings if specified
-    apply_alias(&mut args)?;
+    if let Some(Commands::Merge { files, dedupe }) = &args.command {
+        return merge_results(files, dedupe);
+    }
+    if let Some(Commands::Tag { files, tag, remove }) = &args.command {
+        return tag_files(files, tag, *remove);
+    }
+    if let Some(Commands::Apply {
+        paths,
+        tag,
+        exec,
+        dry_run,
+        untag,
+    }) = &args.command
+    {
+        let exec = exec.as_deref().unwrap_or_default();
+        return apply_tag(paths, tag, exec, *dry_run, *untag);
+    }
 
-    let files = if args.cached {
-        // Get files from cache
-        let cached_files = get_cached_files()?;
-        if cached_files.is_empty() {
-            eprintln!("No cached entries found!");
-            return Ok(());
-        }
-        cached_files
-    } else {
-        // Collect all media files
-        let media_files = collect_media_files(args.paths);
-        if media_files.is_empty() {
-            eprintln!("No media files found!");
-            return Ok(());
-        }
+    // Stats scans like a normal run and only swaps the output stage
+    if let Some(
+        Commands::Stats { paths, output, .. }
+        | Commands::Consistency { paths, output, .. }
+        | Commands::Savings { paths, output, .. },
+    ) = &args.command
+    {
+        args.paths = paths.clone();
+        args.output = output.clone();
+    }
+    if let Some(
+        Commands::Stats { out_file, .. }
+        | Commands::Consistency { out_file, .. }
+        | Commands::Savings { out_file, .. },
+    ) = &args.command
+    {
+        args.out_file = out_file.clone();
+    }
+    if let Some(Commands::Import { map, .. }) = &args.command {
+        args.map = map.clone();
+    }
+    if let Some(Commands::Verify { paths, jobs, .. } | Commands::Thumbs { paths, jobs, .. }) =
+        &args.command
+    {
+        args.paths = paths.clone();
+        args.jobs = jobs.or(args.jobs);
+    }
 
-        let process_start = Instant::now();
-        let total_files = media_files.len();
-        let mut processed = 0;
-        let mut cached = 0;
-        let mut processed_files = Vec::new();
+    if args.list_columns {
+        print_column_list();
+        return Ok(());
+    }
 
-        // Process each file
-        for file in media_files {
-            let is_cached = !args.no_cache && get_cached_probe(&file).ok().flatten().is_some();
-            if is_cached {
-                cached += 1;
-            }
-            match process_file(&file, args.filename_length, args.no_cache) {
-                Ok(probe) => {
-                    processed += 1;
-                    eprint!(
-                        "\x1B[2K\rProcessing: {}/{} files ({} from cache) ({})",
-                        processed,
-                        total_files,
-                        cached,
-                        format_elapsed(process_start.elapsed().as_secs_f64())
-                    );
-                    processed_files.push((file, probe));
-                }
-                Err(e) => {
-                    processed += 1;
-                    eprint!(
-                        "\x1B[2K\rProcessing: {}/{} files ({} from cache) ({})",
-                        processed,
-                        total_files,
-                        cached,
-                        format_elapsed(process_start.elapsed().as_secs_f64())
-                    );
-                    eprintln!("\nError processing {}: {}", file.display(), e);
-                }
-            }
+    if args.version {
+        return print_version(&args, &config);
+    }
+
+    // Apply alias settings if specified
+    apply_alias(&mut args, &config)?;
+    if let Some(list) = &args.files_from {
+        if list == Path::new("-") && args.paths.iter().any(|path| path == Path::new("-")) {
+            return Err(anyhow!(
+                "stdin can't hold both media ('-') and the --files-from list"
+            ));
         }
-        eprintln!();
-        processed_files
+        let paths = read_path_list(list, args.null)?;
+        args.paths.extend(paths);
+    }
+    validate_filters(&args.filter)?;
+    validate_filters(&args.filter_any)?;
+    validate_filters(&args.fail_if)?;
+    let color = match args.color.as_str() {
+        "always" => true,
+        "never" => false,
+        _ => std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
     };
+    if color {
+        let highlight = config.highlight.clone();
+        validate_filters(&highlight.target)
+            .map_err(|e| anyhow!("Invalid highlight target in config: {}", e))?;
+        colored::control::set_override(true);
+        *HIGHLIGHT.lock().unwrap() = Some(highlight);
+    }
+    if let Some(template) = &args.template {
+        template_placeholders(template)?;
+    }
 
-    // Create rows for table
-    let mut rows: Vec<(Vec<String>, Row)> = Vec::new();
-    for (file, probe) in files {
-        let fields = format_probe_output(&file, &probe, args.filename_length)?;
+    if let Some(log_path) = &args.log_file {
+        open_session_log(log_path)?;
+        log_event(
+            "start",
+            serde_json::json!({
+                "paths": args.paths,
+                "cached": args.cached,
+                "no_cache": args.no_cache,
+                "filters": args.filter,
+                "filters_any": args.filter_any,
+            }),
+        );
+    }
+    let run_start = Instant::now();
+    if args.group_by.is_some() && args.output != "table" {
+        return Err(anyhow!("--group-by only applies to --output table"));
+    }
+    // Markdown tables have no comment syntax for a header row
+    if args.header == "comment" && args.output == "markdown" {
+        return Err(anyhow!(
+            "--header comment doesn't apply to --output markdown (use normal or none)"
+        ));
+    }
+    // Reports are written to --out-file as tables too
+    let report = matches!(args.command, Some(Commands::Stats { .. }))
+        || args.audio_languages.is_some()
+        || args.subtitle_languages.is_some()
+        || matches!(
+            args.command,
+            Some(Commands::Consistency { .. } | Commands::Savings { .. })
+        );
+    if args.out_file.is_some() && args.output == "table" && args.template.is_none() && !report {
+        return Err(anyhow!(
+            "--out-file requires --output json, csv, tsv, markdown or html, or --template"
+        ));
+    }
+    *PATH_MAPS.lock().unwrap() = args.map.clone();
+    let cache_backend = match args.cache_backend.clone() {
+        Some(backend) => Some(backend),
+        None => config.cache_backend.clone(),
+    };
+    match cache_backend.as_deref() {
+        None | Some("json") => {}
+        Some("sqlite") => open_sqlite_cache()?,
+        Some(backend) => {
+            return Err(anyhow!(
+                "Invalid cache_backend setting in config: {} (expected json or sqlite)",
+                backend
+            ))
+        }
+    }
 
-        // Apply filters if specified
-        if !args.filter.is_empty() {
-            if !should_include_row(&fields, &args.filter)? {
-                continue;
+    let cache_mode = match args.cache_mode.clone() {
+        Some(mode) => mode,
+        None => config
+            .cache_mode
+            .clone()
+            .unwrap_or_else(|| "central".to_string()),
+    };
+    let roots = match &args.command {
+        Some(Commands::Watch { dirs, .. }) => dirs,
+        _ => &args.paths,
+    };
+    match cache_mode.as_str() {
+        // A drive that carries its own cache keeps using it
+        "central" => {
+            for root in roots.iter().filter(|root| !is_stream_input(root)) {
+                if let Some(dir) = find_local_cache(root) {
+                    open_local_cache(&dir)?;
+                }
             }
         }
-
-        let mut row_cells: Vec<Cell> = Vec::new();
-        for (i, field) in fields.iter().enumerate() {
-            let cell = match i {
-                1 => Cell::new(field).style_spec("r"), // Size
-                2 => Cell::new(field).style_spec("r"), // Duration
-                3 => Cell::new(field).style_spec("r"), // FPS
-                4 => Cell::new(field).style_spec("r"), // Bitrate
-                8 => Cell::new(field).style_spec("c"), // Depth
-                _ => Cell::new(field),                 // Others left-aligned
-            };
-            row_cells.push(cell);
+        "local" => {
+            for root in roots.iter().filter(|root| !is_stream_input(root)) {
+                open_local_cache(root)?;
+            }
+        }
+        _ => {
+            return Err(anyhow!(
+                "Invalid cache_mode setting in config: {} (expected central or local)",
+                cache_mode
+            ))
         }
-        rows.push((fields, Row::new(row_cells)));
     }
 
-    // Sort rows
-    let sort_index = match args.sort.as_str() {
-        "filename" => 0,
-        "duration" => 1,
-        "fps" => 2,
-        "size" => 3,
-        "bitrate" => 4,
-        "resolution" => 5,
-        "format" => 6,
-        "profile" => 7,
-        "depth" => 8,
-        "audio" => 9,
-        _ => 4, // default to bitrate
+    // Fall back to the built-in parser when ffprobe isn't installed
+    let backend = match args.backend.clone() {
+        Some(backend) => backend,
+        None => config.backend.clone().unwrap_or_else(|| "auto".to_string()),
     };
-
-    let ascending = args.direction == "asc";
-    rows.sort_by(|a, b| {
-        let cmp = match sort_index {
-            1 => {
-                // Duration
-                let a_secs = parse_duration_to_secs(&a.0[sort_index]);
-                let b_secs = parse_duration_to_secs(&b.0[sort_index]);
-                a_secs
-                    .partial_cmp(&b_secs)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            }
-            2 => {
-                // FPS
-                let a_fps = a.0[sort_index].parse::<f64>().unwrap_or(0.0);
-                let b_fps = b.0[sort_index].parse::<f64>().unwrap_or(0.0);
-                a_fps
-                    .partial_cmp(&b_fps)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            }
-            3 => {
-                // Size
-                let a_bytes = parse_size(&a.0[sort_index]);
-                let b_bytes = parse_size(&b.0[sort_index]);
-                a_bytes.cmp(&b_bytes)
-            }
-            4 => {
-                // Bitrate
-                let a_bitrate = parse_bitrate(&a.0[sort_index]).unwrap_or(0.0);
-                let b_bitrate = parse_bitrate(&b.0[sort_index]).unwrap_or(0.0);
-                a_bitrate
-                    .partial_cmp(&b_bitrate)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            }
-            _ => a.0[sort_index].cmp(&b.0[sort_index]),
+    args.backend = Some(match backend.as_str() {
+        "auto" if find_in_path("ffprobe").is_some() => "ffprobe".to_string(),
+        "auto" => {
+            let probing = !matches!(
+                args.command,
+                Some(Commands::Import { .. } | Commands::Show { .. } | Commands::Verify { .. })
+            );
+            if args.from_json.is_none() && !args.cached && probing {
+                warn!("ffprobe not found in PATH, using the built-in parser (duration, resolution, codecs and bitrate only)");
+            }
+            "native".to_string()
+        }
+        "ffprobe" | "native" => backend,
+        _ => {
+            return Err(anyhow!(
+                "Invalid backend setting in config: {} (expected auto, ffprobe or native)",
+                backend
+            ))
+        }
+    });
+
+    if let Some(Commands::Verify {
+        output,
+        failed,
+        no_cache,
+        ..
+    }) = &args.command
+    {
+        return verify_files(&args, &config, output, *failed, *no_cache);
+    }
+    if let Some(Commands::Thumbs {
+        grid, width, force, ..
+    }) = &args.command
+    {
+        return make_thumbnails(&args, &config, *grid, *width, *force);
+    }
+
+    if let Some(Commands::Watch {
+        dirs,
+        print,
+        settle,
+        exec,
+        feed,
+        feed_size,
+    }) = &args.command
+    {
+        let exec = exec.clone().or(config.watch_exec.clone());
+        let feed = feed.clone().or(config.watch_feed.clone());
+        return watch_dirs(
+            dirs,
+            *print,
+            Duration::from_secs(*settle),
+            exec.as_deref(),
+            feed.as_deref().map(|path| (path, *feed_size)),
+            &args,
+            &config,
+        );
+    }
+
+    if let Some(Commands::Import { exports, print, .. }) = &args.command {
+        return import_mediainfo(exports, *print, &args);
+    }
+
+    if let Some(Commands::Show { ids, path }) = &args.command {
+        return show_files(ids, *path);
+    }
+
+    if args.device {
+        return print_device_modes(&args.paths, args.device_format.as_deref());
+    }
+
+    let mut summary = RunSummary::default();
+    let mut lost_roots: Vec<(PathBuf, usize)> = Vec::new();
+    let mut cache_hits: HashSet<PathBuf> = HashSet::new();
+    // Files worked on and their total size, for the throughput line; None when nothing was probed
+    let mut probed: Option<(usize, u64)> = None;
+    let files = if let Some(json_path) = &args.from_json {
+        // Re-render a previously exported result set without touching the filesystem
+        load_json_results(json_path)?
+    } else if args.cached {
+        // Get files from cache
+        let cached_files = get_cached_files()?;
+        if cached_files.is_empty() {
+            warn!("No cached entries found!");
+            return write_summary(&args, summary, run_start);
+        }
+        cached_files
+    } else {
+        // Collect all media files, each root with its own profile settings
+        let roots = args
+            .paths
+            .iter()
+            .map(|path| {
+                Ok((
+                    path.clone(),
+                    resolve_scan_settings(path, &args, &config.profiles)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let media_files = collect_media_files(&roots);
+        log_event(
+            "scan",
+            serde_json::json!({
+                "media_files": media_files.len(),
+                "elapsed_secs": run_start.elapsed().as_secs_f64(),
+            }),
+        );
+        if media_files.is_empty() {
+            warn!("No media files found!");
+            write_summary(&args, summary, run_start)?;
+            if args.fail_if_empty {
+                std::process::exit(EXIT_NO_FILES);
+            }
+            return Ok(());
+        }
+        if args.count {
+            return print_counts(&media_files, &args.output, args.raw);
+        }
+        // Filters on the name, size or modification time don't need a probe.
+        // Stitched recordings are filtered as a whole, so not part by part.
+        let found = media_files.len();
+        let media_files = if args.stitch {
+            media_files
+        } else {
+            let mut kept = Vec::with_capacity(media_files.len());
+            for (file, root_index) in media_files {
+                if !excluded_before_probe(
+                    &file,
+                    &args.filter,
+                    &args.filter_any,
+                    args.ignore_diacritics,
+                )? {
+                    kept.push((file, root_index));
+                }
+            }
+            kept
+        };
+        let prefiltered = found - media_files.len();
+        summary.filtered += prefiltered;
+        if prefiltered > 0 {
+            info!("{} files left out by filters before probing", prefiltered);
+        }
+
+        let progress = Mutex::new(ProbeProgress {
+            total: media_files.len(),
+            processed: 0,
+            cached: 0,
+            failed: 0,
+            vanished: Vec::new(),
+            start: Instant::now(),
+            cache_hits: HashSet::new(),
+            bar: start_progress(
+                ProgressBar::new(media_files.len() as u64)
+                    .with_style(
+                        ProgressStyle::with_template(
+                            "Probing {bar:30} {pos}/{len} files ({msg}) {per_sec} ETA {eta}",
+                        )
+                        .unwrap(),
+                    )
+                    .with_message("0 from cache"),
+            ),
+            deadline: args.max_runtime.map(|budget| run_start + budget),
+            unprobed: 0,
+        });
+        let mut processed_files = Vec::new();
+        for (root_index, (root, settings)) in roots.iter().enumerate() {
+            let mut files: Vec<PathBuf> = media_files
+                .iter()
+                .filter(|(_, index)| *index == root_index)
+                .map(|(file, _)| file.clone())
+                .collect();
+            loop {
+                let (probed, remaining) =
+                    probe_files(files, root, settings, args.no_cache, &progress);
+                processed_files.extend(probed);
+                if remaining.is_empty() {
+                    break;
+                }
+                // Out of time: don't wait for a lost root to come back
+                let mut state = progress.lock().unwrap();
+                if state.deadline.is_some_and(|d| Instant::now() >= d) {
+                    state.unprobed += remaining.len();
+                    break;
+                }
+                drop(state);
+                // Save what we have before waiting on the user
+                flush_cache()?;
+                let bar = progress.lock().unwrap().bar.clone();
+                if !bar.suspend(|| wait_for_root(root, remaining.len())) {
+                    log_event(
+                        "root_lost",
+                        serde_json::json!({
+                            "root": root,
+                            "unprobed": remaining.len(),
+                        }),
+                    );
+                    lost_roots.push((root.clone(), remaining.len()));
+                    break;
+                }
+                files = remaining;
+            }
+        }
+        finish_progress();
+        flush_cache()?;
+        let mut progress = progress.into_inner().unwrap();
+        progress.vanished.sort();
+        log_event(
+            "probe",
+            serde_json::json!({
+                "files": progress.total,
+                "succeeded": processed_files.len(),
+                "failed": progress.failed,
+                "vanished": progress.vanished.len(),
+                "cache_hits": progress.cached,
+                "elapsed_secs": progress.start.elapsed().as_secs_f64(),
+            }),
+        );
+        summary.files = progress.total + prefiltered;
+        summary.cache_hits = progress.cached;
+        summary.errors = progress.failed;
+        summary.vanished = progress.vanished.len();
+        summary.unprobed = progress.unprobed;
+        if args.vanished_as_errors {
+            summary.errors += progress.vanished.len();
+        }
+        report_vanished(&progress.vanished);
+        cache_hits = progress.cache_hits;
+        probed = Some((
+            progress.processed,
+            processed_files
+                .iter()
+                .filter_map(|(_, probe)| probe.format.size.parse::<u64>().ok())
+                .sum(),
+        ));
+        processed_files
+    };
+    if args.from_json.is_some() || args.cached {
+        summary.files = files.len();
+    }
+
+    // Group multi-part recordings into one logical item
+    let mut segment_counts: HashMap<PathBuf, usize> = HashMap::new();
+    let files = if args.stitch {
+        stitch_segments(files, &mut segment_counts)
+    } else {
+        files
+    };
+
+    let dates = match args.dates.clone() {
+        Some(dates) => Some(dates),
+        None => config.dates.clone(),
+    };
+    if let Some(dates) = &dates {
+        if dates != "relative" && dates != "iso" {
+            return Err(anyhow!(
+                "Invalid dates setting in config: {} (expected relative or iso)",
+                dates
+            ));
+        }
+    }
+    let selected_columns = if args.columns.is_empty() {
+        config.columns.clone().unwrap_or_default()
+    } else {
+        args.columns.clone()
+    };
+    // Video columns would all be empty for a music library
+    let audio_only = !files.is_empty() && files.iter().all(|(_, probe)| !has_video(probe));
+    let mut columns: Vec<&'static str> = if !selected_columns.is_empty() {
+        // An explicit layout is shown as given, without implied extra columns
+        selected_columns
+            .iter()
+            .map(|name| {
+                column_def(resolve_column(name))
+                    .map(|def| def.id)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Invalid columns setting in config: unknown column '{}'",
+                            name
+                        )
+                    })
+            })
+            .collect::<Result<_>>()?
+    } else if !files.is_empty() && files.iter().all(|(_, probe)| is_subtitle_probe(probe)) {
+        SUBTITLE_DEFAULT_COLUMNS.to_vec()
+    } else if audio_only {
+        AUDIO_DEFAULT_COLUMNS.to_vec()
+    } else {
+        DEFAULT_COLUMNS.to_vec()
+    };
+    if selected_columns.is_empty() {
+        if dates.is_some() {
+            columns.extend(["modified", "created"]);
+        }
+        if args.cached {
+            columns.extend(["first_seen", "last_seen"]);
+        }
+        if args.stitch {
+            columns.push("segments");
+        }
+    }
+    for column in &args.add_columns {
+        if let Some(def) = column_def(column) {
+            if !columns.contains(&def.id) {
+                columns.push(def.id);
+            }
+        }
+    }
+    // Some columns are only computed when shown
+    if let Some(template) = &args.template {
+        for name in template_placeholders(template)? {
+            if let Some(def) = column_def(resolve_column(&name)) {
+                if !columns.contains(&def.id) {
+                    columns.push(def.id);
+                }
+            }
+        }
+    }
+    let reference = match &args.reference {
+        Some(path) => {
+            if selected_columns.is_empty() {
+                columns.extend(["size_delta", "bitrate_delta"]);
+            }
+            let entries = load_json_results(path)?;
+            let reference_root = common_ancestor(entries.iter().map(|(p, _)| p.as_path()));
+            Some(ReferenceSet {
+                entries: entries
+                    .into_iter()
+                    .map(|(p, probe)| (relative_to(&p, &reference_root), probe))
+                    .collect(),
+                current_root: common_ancestor(files.iter().map(|(p, _)| p.as_path())),
+            })
+        }
+        None => None,
+    };
+    // Tags are only looked up when shown or filtered on
+    let mut format_columns = columns.clone();
+    if filters_on(&args, "tags") && !format_columns.contains(&"tags") {
+        format_columns.push("tags");
+    }
+    let options = DisplayOptions {
+        columns: format_columns,
+        dates: dates.unwrap_or_else(|| "iso".to_string()),
+        reference,
+    };
+
+    // Create rows for table
+    let mut rows: Vec<ResultRow> = Vec::new();
+    // Files may sit on storage that is gone with --from-json or --cached,
+    // so they are only stat'ed again when their modification time is used
+    let stat_modified = args.sort == "modified" || filters_on(&args, "modified");
+    for (file, probe) in files {
+        let mut values = format_probe_output(&file, &probe, &options)?;
+        let segments = segment_counts.get(&file).copied().unwrap_or(1);
+        values.insert("segments", segments.to_string());
+        // Exported result sets carry no cache information
+        if columns.contains(&"cache") && args.from_json.is_none() {
+            let hit = args.cached || cache_hits.contains(&file);
+            values.insert("cache", cache_status(&file, hit));
+        }
+
+        let mut numbers = numeric_values(&probe);
+        if let Some(modified) = stat_modified.then(|| file_modified_secs(&file)).flatten() {
+            numbers.insert("modified", modified as f64);
+        }
+        let row = ResultRow {
+            path: file,
+            numbers,
+            probe,
+            values,
+        };
+
+        // Apply filters if specified
+        if (!args.filter.is_empty() || !args.filter_any.is_empty())
+            && !should_include_row(
+                &row,
+                &args.filter,
+                &args.filter_any,
+                args.ignore_diacritics,
+                audio_only,
+            )?
+        {
+            summary.filtered += 1;
+            continue;
+        }
+
+        rows.push(row);
+    }
+
+    // Share of the displayed files' total size, only known once filtering is done
+    let total_size: f64 = rows.iter().filter_map(|row| row.numbers.get("size")).sum();
+    for row in &mut rows {
+        let size = row.numbers.get("size").copied().unwrap_or(0.0);
+        let share = match total_size > 0.0 {
+            true => size / total_size * 100.0,
+            false => 0.0,
         };
+        row.numbers.insert("share", share);
+        row.values.insert("share", format!("{:.1}%", share));
+    }
+
+    // Sort rows
+    let sort_column = match args.sort.as_str() {
+        // Container Mbps means little for music, sort by the audio stream's kbps
+        "bitrate" if audio_only => "audio_bitrate",
+        sort => sort,
+    };
+    let ascending = args.direction == "asc";
+    // Look the cache dates up once rather than on every comparison
+    if sort_column == "first_seen" || sort_column == "last_seen" {
+        for row in &mut rows {
+            let (first_seen, last_seen) = cache_seen_times(&row.path);
+            if let Some(secs) = first_seen {
+                row.numbers.insert("first_seen", secs as f64);
+            }
+            if let Some(secs) = last_seen {
+                row.numbers.insert("last_seen", secs as f64);
+            }
+        }
+    }
+    rows.sort_by(|a, b| {
+        let cmp = compare_rows(a, b, sort_column);
         if ascending {
             cmp
         } else {
@@ -348,676 +1710,8041 @@ fn main() -> Result<()> {
         }
     });
 
+    // Stable, so rows keep the sort order within each section
+    if let Some(group_by) = &args.group_by {
+        rows.sort_by_cached_key(|row| group_key(row, group_by).1);
+    }
+
+    if args.raw {
+        rows.iter_mut().for_each(apply_raw_values);
+    }
+
+    // Badly cut files (often TS) report a container duration that doesn't
+    // match the video, which breaks players' seek bars
+    let mismatches: Vec<(&Path, f64, f64)> = rows
+        .iter()
+        .filter(|row| segment_counts.get(&row.path).copied().unwrap_or(1) == 1)
+        .filter_map(|row| {
+            let (container, video) = duration_mismatch(&row.probe)?;
+            Some((row.path.as_path(), container, video))
+        })
+        .collect();
+    // Renamed files whose data is another container trip up some players
+    let misnamed: Vec<(&Path, (&str, &str, String))> = rows
+        .iter()
+        .filter_map(|row| {
+            Some((
+                row.path.as_path(),
+                extension_mismatch(&row.path, &row.probe)?,
+            ))
+        })
+        .collect();
+
+    let displayed = rows.len();
+    let consistency_by = match &args.command {
+        Some(Commands::Consistency { by, .. }) => Some(by.as_str()),
+        _ => None,
+    };
+    let savings_target = match &args.command {
+        Some(Commands::Savings { target, bpp, .. }) => Some((target.as_str(), *bpp)),
+        _ => None,
+    };
+    let mut out: Box<dyn Write> = match &args.out_file {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            fs::File::create(path)
+                .map_err(|e| anyhow!("Failed to create {}: {}", path.display(), e))?,
+        )),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    STYLED_OUTPUT.store(
+        args.out_file.is_none() && std::io::stdout().is_terminal(),
+        Ordering::Relaxed,
+    );
+    match args.output.as_str() {
+        _ if matches!(args.command, Some(Commands::Stats { .. })) => {
+            print_stats(&rows, &args.output, args.raw, &mut out)?
+        }
+        _ if args.audio_languages.is_some() => {
+            let wanted = args.audio_languages.as_deref().unwrap_or_default();
+            print_language_coverage(&rows, "audio", wanted, &args.output, &mut out)?
+        }
+        _ if args.subtitle_languages.is_some() => {
+            let wanted = args.subtitle_languages.as_deref().unwrap_or_default();
+            print_language_coverage(&rows, "subtitle", wanted, &args.output, &mut out)?
+        }
+        _ if consistency_by.is_some() => {
+            let by = consistency_by.unwrap_or_default();
+            print_consistency(&rows, by, &args.output, args.filename_length, &mut out)?
+        }
+        _ if savings_target.is_some() => {
+            let (target, bpp) = savings_target.unwrap_or_default();
+            print_savings(
+                &rows,
+                target,
+                bpp,
+                &args.output,
+                args.filename_length,
+                args.raw,
+                &mut out,
+            )?
+        }
+        _ if args.template.is_some() => print_template(
+            &rows,
+            args.template.as_deref().unwrap_or_default(),
+            &mut out,
+        )?,
+        "json" => print_json(&rows, &columns, args.raw_columns, args.raw, &mut out)?,
+        "csv" => print_delimited(&rows, &columns, args.raw_columns, ',', &mut out)?,
+        "tsv" => print_delimited(&rows, &columns, args.raw_columns, '\t', &mut out)?,
+        "markdown" => print_markdown(&rows, &columns, &mut out)?,
+        "html" => print_html(&rows, &columns, (sort_column, ascending), &mut out)?,
+        _ => {
+            let hyperlinks = match args.hyperlinks.as_str() {
+                "always" => true,
+                "never" => false,
+                _ => supports_hyperlinks(),
+            };
+            drop(out);
+            match &args.group_by {
+                Some(group_by) => print_grouped_tables(
+                    &rows,
+                    group_by,
+                    &columns,
+                    (sort_column, ascending),
+                    args.filename_length,
+                    hyperlinks,
+                    args.raw,
+                )?,
+                None => print_table(
+                    &rows,
+                    &columns,
+                    (sort_column, ascending),
+                    args.filename_length,
+                    hyperlinks,
+                )?,
+            }
+        }
+    }
+    if args.summary {
+        // Keep structured output on stdout parseable
+        if args.output == "table" {
+            print_totals(&rows, args.raw, &mut std::io::stdout().lock())?;
+        } else {
+            print_totals(&rows, args.raw, &mut std::io::stderr().lock())?;
+        }
+    }
+
+    for (path, container, video) in mismatches {
+        warn!(
+            "{}: container duration {} differs from video stream duration {}",
+            path.display(),
+            format_duration(&container.to_string()),
+            format_duration(&video.to_string())
+        );
+        log_event(
+            "warning",
+            serde_json::json!({
+                "path": path,
+                "warning": "duration_mismatch",
+                "container_duration": container,
+                "video_duration": video,
+            }),
+        );
+    }
+
+    for (path, (container, expected, extension)) in misnamed {
+        warn!(
+            "{}: {} data in a .{} file (expected .{})",
+            path.display(),
+            container,
+            extension,
+            expected
+        );
+        log_event(
+            "warning",
+            serde_json::json!({
+                "path": path,
+                "warning": "extension_mismatch",
+                "container": container,
+                "extension": extension,
+            }),
+        );
+    }
+
+    log_event(
+        "end",
+        serde_json::json!({
+            "rows": displayed,
+            "elapsed_secs": run_start.elapsed().as_secs_f64(),
+        }),
+    );
+
+    summary.displayed = displayed;
+    summary.total_size_bytes = rows
+        .iter()
+        .filter_map(|row| row.probe.format.size.parse::<u64>().ok())
+        .sum();
+    if let Some((files, bytes)) = probed {
+        print_throughput(&summary, files, bytes, run_start.elapsed());
+    }
+    if summary.unprobed > 0 {
+        warn!(
+            "Stopped at --max-runtime: {} files not probed yet; run again to continue (finished probes are cached)",
+            summary.unprobed
+        );
+    }
+    write_summary(&args, summary, run_start)?;
+
+    if !lost_roots.is_empty() {
+        let lost: Vec<String> = lost_roots
+            .iter()
+            .map(|(root, unprobed)| format!("{} ({} files not probed)", root.display(), unprobed))
+            .collect();
+        return Err(anyhow!(
+            "Scan incomplete, results are partial. Disappeared during the scan: {}",
+            lost.join(", ")
+        ));
+    }
+
+    // Quality gate: report every offending file, then fail the run
+    let mut violations = 0;
+    for row in &rows {
+        for filter in &args.fail_if {
+            if filter_matches(row, filter, args.ignore_diacritics, audio_only)? {
+                warn!("Failed check '{}': {}", filter, row.path.display());
+                violations += 1;
+                break;
+            }
+        }
+    }
+    if violations > 0 {
+        error!(
+            "{} of {} files failed --fail-if checks",
+            violations, displayed
+        );
+        std::process::exit(EXIT_FAIL_IF);
+    }
+    if args.fail_if_empty && displayed == 0 {
+        error!("No files left to display");
+        std::process::exit(EXIT_NO_FILES);
+    }
+    Ok(())
+}
+
+/// The `--group-by` section a row belongs to, and the position of that
+/// section: directories and codecs in name order, resolutions from the
+/// largest class down, bit depths from the deepest down.
+fn group_key(row: &ResultRow, group_by: &str) -> (String, (i64, String)) {
+    let video = primary_video(&row.probe);
+    let name = match group_by {
+        "dir" => row
+            .path
+            .parent()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default(),
+        "codec" => video
+            .or_else(|| primary_audio(&row.probe))
+            .and_then(|s| s.codec_name.clone())
+            .unwrap_or_else(|| "unknown".to_string()),
+        "resolution" => video.map_or("audio only", resolution_class).to_string(),
+        _ => video.map_or("audio only".to_string(), |v| {
+            get_bit_depth(v.pix_fmt.as_deref())
+        }),
+    };
+    let rank = match group_by {
+        "resolution" => ["2160p", "1440p", "1080p", "720p", "SD"]
+            .iter()
+            .position(|class| *class == name)
+            .unwrap_or(5) as i64,
+        "depth" => -(leading_number(&name).unwrap_or(0.0) as i64),
+        _ => 0,
+    };
+    (name.clone(), (rank, name))
+}
+
+/// Prints the rows as one table per `--group-by` section, each followed by
+/// its file count, size and duration. Rows must already be ordered by
+/// section.
+fn print_grouped_tables(
+    rows: &[ResultRow],
+    group_by: &str,
+    columns: &[&str],
+    sort: (&str, bool),
+    filename_length: usize,
+    hyperlinks: bool,
+    raw: bool,
+) -> Result<()> {
+    for group in rows.chunk_by(|a, b| group_key(a, group_by).0 == group_key(b, group_by).0) {
+        println!("{}", group_key(&group[0], group_by).0);
+        print_table(group, columns, sort, filename_length, hyperlinks)?;
+        let size: u64 = group
+            .iter()
+            .filter_map(|row| row.probe.format.size.parse::<u64>().ok())
+            .sum();
+        let duration: f64 = group
+            .iter()
+            .filter_map(|row| row.probe.format.duration.parse::<f64>().ok())
+            .sum();
+        if raw {
+            println!("{} files, {}, {:.0}\n", group.len(), size, duration);
+        } else {
+            println!(
+                "{} files, {}, {}\n",
+                group.len(),
+                format_size(&size.to_string()),
+                format_duration(&duration.to_string())
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_table(
+    rows: &[ResultRow],
+    columns: &[&str],
+    (sort_column, ascending): (&str, bool),
+    filename_length: usize,
+    hyperlinks: bool,
+) -> Result<()> {
+    if PLAIN.load(Ordering::Relaxed) {
+        return print_plain(rows, columns, (sort_column, ascending));
+    }
+    let highlight = HIGHLIGHT.lock().unwrap().clone();
+    let cell_value = |row: &ResultRow, column: &str| {
+        let value = match column {
+            "filename" => truncate_middle(row_value(row, column), filename_length),
+            _ => row_value(row, column).to_string(),
+        };
+        match &highlight {
+            Some(highlight) => highlight_value(row, column, value, highlight),
+            None => value,
+        }
+    };
+
     // Create and print table
     let mut table = Table::new();
-    let format = format::FormatBuilder::new()
-        .column_separator('│')
-        .borders('│')
-        .separator(
-            format::LinePosition::Top,
-            format::LineSeparator::new('─', '┬', '┌', '┐'),
-        )
-        .separator(
-            format::LinePosition::Bottom,
-            format::LineSeparator::new('─', '┴', '└', '┘'),
-        )
-        .separator(
-            format::LinePosition::Title,
-            format::LineSeparator::new('─', '┼', '├', '┤'),
-        )
-        .padding(1, 1)
-        .build();
-    table.set_format(format);
+    table.set_format(table_format());
 
-    // Add header row
-    table.add_row(Row::new(vec![
-        Cell::new("Filename").with_style(Attr::Bold),
-        Cell::new("Duration").with_style(Attr::Bold).style_spec("r"),
-        Cell::new("FPS").with_style(Attr::Bold).style_spec("r"),
-        Cell::new("Size").with_style(Attr::Bold).style_spec("r"),
-        Cell::new("Bitrate").with_style(Attr::Bold).style_spec("r"),
-        Cell::new("Resolution").with_style(Attr::Bold),
-        Cell::new("Format").with_style(Attr::Bold),
-        Cell::new("Profile").with_style(Attr::Bold),
-        Cell::new("Depth").with_style(Attr::Bold).style_spec("c"),
-        Cell::new("Color").with_style(Attr::Bold),
-        Cell::new("Audio").with_style(Attr::Bold),
-    ]));
+    // Add header row, marking the sort column and its direction
+    let defs: Vec<&ColumnDef> = columns.iter().filter_map(|c| column_def(c)).collect();
+    let headers: Vec<String> = defs
+        .iter()
+        .map(|def| match def.id == sort_column {
+            true if ascending => format!("{} ▲", def.header),
+            true => format!("{} ▼", def.header),
+            false => def.header.to_string(),
+        })
+        .collect();
+    let header_style = HEADER_STYLE.lock().unwrap().clone();
+    match header_style.as_str() {
+        "none" => {}
+        // Outside the table, so line-based tools can skip it like any comment
+        "comment" => println!("# {}", headers.join(", ")),
+        _ => {
+            table.add_row(Row::new(
+                defs.iter()
+                    .zip(&headers)
+                    .map(|(def, header)| Cell::new(header).style_spec(&format!("b{}", def.align)))
+                    .collect(),
+            ));
+        }
+    }
 
     // Add sorted rows to table
-    for (_, row) in rows {
-        table.add_row(row);
+    for row in rows {
+        table.add_row(Row::new(
+            defs.iter()
+                .map(|def| Cell::new(&cell_value(row, def.id)).style_spec(def.align))
+                .collect(),
+        ));
+    }
+
+    // Print the table
+    if !hyperlinks {
+        table.printstd();
+        return Ok(());
+    }
+
+    // Hyperlink escapes would throw off the column width calculation, so the
+    // table is rendered first and the filenames are wrapped afterwards.
+    let mut buffer = Vec::new();
+    match term::terminfo::TerminfoTerminal::new(&mut buffer) {
+        Some(mut terminal) => table.print_term(&mut terminal)?,
+        None => table.print(&mut buffer)?,
+    };
+    let rendered = String::from_utf8_lossy(&buffer);
+    let mut stdout = std::io::stdout().lock();
+    // Line 0 is the top border, followed by the header row if there is one
+    let first_row = if header_style == "normal" { 2 } else { 1 };
+    for (i, line) in rendered.lines().enumerate() {
+        match i.checked_sub(first_row).and_then(|r| rows.get(r)) {
+            Some(row) => {
+                let name = cell_value(row, "filename");
+                let link = hyperlink(&row.path, &name);
+                writeln!(
+                    stdout,
+                    "{}",
+                    line.replacen(&format!("│ {}", name), &format!("│ {}", link), 1)
+                )?;
+            }
+            None => writeln!(stdout, "{}", line)?,
+        }
+    }
+    Ok(())
+}
+
+/// Colors a table value by the `[highlight]` rules. The table ignores the
+/// escape codes when measuring columns, so alignment is unaffected.
+fn highlight_value(row: &ResultRow, column: &str, value: String, highlight: &Highlight) -> String {
+    if value.is_empty() {
+        return value;
+    }
+    match column {
+        "bitrate" => {
+            let mbps = row.numbers.get("bitrate").map(|bps| bps / 1_000_000.0);
+            let out_of_range = mbps.is_some_and(|mbps| {
+                highlight.bitrate_below.is_some_and(|below| mbps < below)
+                    || highlight.bitrate_above.is_some_and(|above| mbps > above)
+            });
+            match out_of_range {
+                true => value.red().to_string(),
+                false => value,
+            }
+        }
+        // HEVC's efficiency gains largely assume 10-bit encodes
+        "format" | "depth" if is_8bit_hevc(&row.probe) => value.yellow().to_string(),
+        "filename"
+            if !highlight.target.is_empty()
+                && should_include_row(row, &highlight.target, &[], false, false)
+                    .unwrap_or(false) =>
+        {
+            value.green().to_string()
+        }
+        _ => value,
+    }
+}
+
+fn is_8bit_hevc(probe: &FFProbeOutput) -> bool {
+    primary_video(probe).is_some_and(|video| {
+        video.codec_name.as_deref() == Some("hevc")
+            && video.pix_fmt.is_some()
+            && get_bit_depth(video.pix_fmt.as_deref()) == "8bit"
+    })
+}
+
+/// `--plain` result listing: one block per file with a "Header: value" line
+/// per non-empty column, separated by blank lines. Filenames aren't
+/// truncated, and the sort order is stated in words rather than arrows.
+fn print_plain(
+    rows: &[ResultRow],
+    columns: &[&str],
+    (sort_column, ascending): (&str, bool),
+) -> Result<()> {
+    let defs: Vec<&ColumnDef> = columns.iter().filter_map(|c| column_def(c)).collect();
+    let mut stdout = std::io::stdout().lock();
+    let header_style = HEADER_STYLE.lock().unwrap().clone();
+    if let Some(def) = column_def(sort_column).filter(|_| header_style != "none") {
+        let direction = if ascending { "ascending" } else { "descending" };
+        writeln!(
+            stdout,
+            "{}{} files, sorted by {}, {}\n",
+            if header_style == "comment" { "# " } else { "" },
+            rows.len(),
+            def.header,
+            direction
+        )?;
+    }
+    for (i, row) in rows.iter().enumerate() {
+        writeln!(stdout, "File {} of {}", i + 1, rows.len())?;
+        for def in &defs {
+            let value = row_value(row, def.id);
+            if !value.is_empty() {
+                writeln!(stdout, "{}: {}", def.header, value)?;
+            }
+        }
+        writeln!(stdout)?;
+    }
+    Ok(())
+}
+
+/// Summarizes the walk without probing: file count and total size grouped by
+/// extension and by parent directory, largest first.
+fn print_counts(media_files: &[(PathBuf, usize)], output: &str, raw: bool) -> Result<()> {
+    let size_text = |size: u64| {
+        if raw {
+            size.to_string()
+        } else {
+            format_size(&size.to_string())
+        }
+    };
+    let mut by_extension: HashMap<String, (usize, u64)> = HashMap::new();
+    let mut by_directory: HashMap<String, (usize, u64)> = HashMap::new();
+    let mut total_size = 0;
+    for (file, _) in media_files {
+        let size = fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+        total_size += size;
+        let extension = media_extension(file).unwrap_or_default();
+        let directory = file
+            .parent()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+        for (groups, key) in [
+            (&mut by_extension, extension),
+            (&mut by_directory, directory),
+        ] {
+            let entry = groups.entry(key).or_default();
+            entry.0 += 1;
+            entry.1 += size;
+        }
+    }
+
+    let sorted = |groups: HashMap<String, (usize, u64)>| {
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(&b.0)));
+        groups
+    };
+    let by_extension = sorted(by_extension);
+    let by_directory = sorted(by_directory);
+
+    if output == "json" {
+        let groups = |groups: &[(String, (usize, u64))]| {
+            groups
+                .iter()
+                .map(|(key, (files, size))| serde_json::json!({"name": key, "files": files, "size_bytes": size}))
+                .collect::<Vec<_>>()
+        };
+        let summary = serde_json::json!({
+            "files": media_files.len(),
+            "size_bytes": total_size,
+            "extensions": groups(&by_extension),
+            "directories": groups(&by_directory),
+        });
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+        return Ok(());
+    }
+
+    for (header, groups) in [("Extension", &by_extension), ("Directory", &by_directory)] {
+        let mut table = Table::new();
+        table.set_format(table_format());
+        table.set_titles(Row::new(vec![
+            Cell::new(header).style_spec("b"),
+            Cell::new("Files").style_spec("br"),
+            Cell::new("Size").style_spec("br"),
+        ]));
+        for (key, (files, size)) in groups {
+            table.add_row(Row::new(vec![
+                Cell::new(key),
+                Cell::new(&files.to_string()).style_spec("r"),
+                Cell::new(&size_text(*size)).style_spec("r"),
+            ]));
+        }
+        table.printstd();
+    }
+    println!(
+        "{} media files, {}",
+        media_files.len(),
+        size_text(total_size)
+    );
+    Ok(())
+}
+
+type ConsistencyValue = fn(&FFProbeOutput) -> String;
+
+/// Properties that should match across the episodes of a show or the files of
+/// a folder, as (name, value of a row)
+const CONSISTENCY_FIELDS: [(&str, ConsistencyValue); 3] = [
+    ("resolution", |probe| {
+        primary_video(probe)
+            .map_or("audio only", resolution_class)
+            .to_string()
+    }),
+    ("format", |probe| {
+        primary_video(probe)
+            .and_then(|video| video.codec_name.clone())
+            .unwrap_or_else(|| "none".to_string())
+    }),
+    ("audio", |probe| match primary_audio(probe) {
+        Some(audio) => format!(
+            "{} {}CH",
+            audio.codec_name.as_deref().unwrap_or("unknown"),
+            audio.channels.unwrap_or(0)
+        ),
+        None => "none".to_string(),
+    }),
+];
+
+/// Series name of a TV episode: the part of the filename before an episode
+/// marker like S01E02 or 1x02 ("The.Office.S02E01.mkv" is "The Office"), or
+/// else the folder above a "Season 1" or "S01" folder.
+fn show_name(path: &Path) -> Option<String> {
+    let chars: Vec<char> = path.file_stem()?.to_string_lossy().chars().collect();
+    let is_separator = |c: char| matches!(c, '.' | '_' | '-' | ' ' | '[' | '(');
+    let digits = |from: usize| {
+        chars[from..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count()
+    };
+    let marker = (1..chars.len()).find(|&i| {
+        if !is_separator(chars[i - 1]) {
+            return false;
+        }
+        let (season, separator) = match chars[i] {
+            's' | 'S' => (digits(i + 1), i + 1 + digits(i + 1)),
+            c if c.is_ascii_digit() => (digits(i), i + digits(i)),
+            _ => return false,
+        };
+        // S01E02 takes any episode number, 1x02 exactly two digits
+        let (expected, episode_digits) = match chars[i].is_ascii_digit() {
+            true => ('x', 2..=2),
+            false => ('e', 1..=4),
+        };
+        (1..=2).contains(&season)
+            && chars
+                .get(separator)
+                .is_some_and(|c| c.to_ascii_lowercase() == expected)
+            && episode_digits.contains(&digits(separator + 1))
+    });
+    if let Some(marker) = marker {
+        let name: String = chars[..marker]
+            .iter()
+            .map(|&c| if c == '.' || c == '_' { ' ' } else { c })
+            .collect();
+        let name = name.trim_matches(|c: char| is_separator(c) || c.is_whitespace());
+        if !name.is_empty() {
+            return Some(name.to_string());
+        }
+    }
+
+    let season_dir = path.parent()?;
+    let folder = season_dir.file_name()?.to_string_lossy().to_lowercase();
+    let is_season = folder.starts_with("season")
+        || folder
+            .strip_prefix('s')
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+    match is_season {
+        true => Some(
+            season_dir
+                .parent()?
+                .file_name()?
+                .to_string_lossy()
+                .to_string(),
+        ),
+        false => None,
+    }
+}
+
+/// Reports folders (or shows) whose files disagree on resolution class,
+/// video codec or audio setup, and the files that differ from the rest.
+fn print_consistency(
+    rows: &[ResultRow],
+    by: &str,
+    output: &str,
+    filename_length: usize,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let folder = |row: &ResultRow| {
+        row.path
+            .parent()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default()
+    };
+    let mut groups: HashMap<String, Vec<&ResultRow>> = HashMap::new();
+    for row in rows {
+        let name = match by {
+            // Files that don't look like episodes are compared per folder
+            "show" => show_name(&row.path).unwrap_or_else(|| folder(row)),
+            _ => folder(row),
+        };
+        groups.entry(name).or_default().push(row);
+    }
+    let mut groups: Vec<_> = groups
+        .into_iter()
+        .filter(|(_, rows)| rows.len() > 1)
+        .collect();
+    groups.sort_by(|a, b| a.0.cmp(&b.0));
+    let checked = groups.len();
+
+    /// A field where a file differs: (field, its value, the most common value)
+    type Difference = (&'static str, String, String);
+    // Per group: each inconsistent field with its value counts (most common
+    // first), and the rows that differ from the most common value
+    struct Inconsistency<'a> {
+        name: String,
+        files: usize,
+        fields: Vec<(&'static str, Vec<(String, usize)>)>,
+        outliers: Vec<(&'a ResultRow, Vec<Difference>)>,
+    }
+    let mut found = Vec::new();
+    for (name, rows) in groups {
+        let values: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                CONSISTENCY_FIELDS
+                    .iter()
+                    .map(|(_, value)| value(&row.probe))
+                    .collect()
+            })
+            .collect();
+        let mut fields = Vec::new();
+        for (index, (field, _)) in CONSISTENCY_FIELDS.iter().enumerate() {
+            let mut counts: Vec<(String, usize)> = Vec::new();
+            for row_values in &values {
+                match counts
+                    .iter_mut()
+                    .find(|(value, _)| *value == row_values[index])
+                {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((row_values[index].clone(), 1)),
+                }
+            }
+            // Stable, so ties keep the value seen first
+            counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+            if counts.len() > 1 {
+                fields.push((index, *field, counts));
+            }
+        }
+        if fields.is_empty() {
+            continue;
+        }
+        let outliers = rows
+            .iter()
+            .zip(&values)
+            .filter_map(|(row, row_values)| {
+                let differs: Vec<Difference> = fields
+                    .iter()
+                    .filter(|(index, _, counts)| row_values[*index] != counts[0].0)
+                    .map(|(index, field, counts)| {
+                        (*field, row_values[*index].clone(), counts[0].0.clone())
+                    })
+                    .collect();
+                (!differs.is_empty()).then_some((*row, differs))
+            })
+            .collect();
+        found.push(Inconsistency {
+            name,
+            files: rows.len(),
+            fields: fields
+                .into_iter()
+                .map(|(_, field, counts)| (field, counts))
+                .collect(),
+            outliers,
+        });
+    }
+
+    if output == "json" {
+        let report: Vec<serde_json::Value> = found
+            .iter()
+            .map(|group| {
+                let fields: serde_json::Map<String, serde_json::Value> = group
+                    .fields
+                    .iter()
+                    .map(|(field, counts)| {
+                        let counts: serde_json::Map<String, serde_json::Value> = counts
+                            .iter()
+                            .map(|(value, count)| (value.clone(), serde_json::json!(count)))
+                            .collect();
+                        (field.to_string(), serde_json::Value::Object(counts))
+                    })
+                    .collect();
+                let outliers: Vec<serde_json::Value> = group
+                    .outliers
+                    .iter()
+                    .map(|(row, differs)| {
+                        let differs: serde_json::Map<String, serde_json::Value> = differs
+                            .iter()
+                            .map(|(field, value, _)| (field.to_string(), serde_json::json!(value)))
+                            .collect();
+                        serde_json::json!({
+                            "id": file_id(&row.path),
+                            "path": row.path,
+                            "differs": differs,
+                        })
+                    })
+                    .collect();
+                serde_json::json!({
+                    "name": group.name,
+                    "files": group.files,
+                    "fields": fields,
+                    "outliers": outliers,
+                })
+            })
+            .collect();
+        writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    let kind = if by == "show" { "shows" } else { "folders" };
+    if found.is_empty() {
+        writeln!(
+            out,
+            "All {} {} with more than one file are consistent",
+            checked, kind
+        )?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    let mut titles = vec![
+        Cell::new(if by == "show" { "Show" } else { "Folder" }).style_spec("b"),
+        Cell::new("Files").style_spec("br"),
+    ];
+    titles.extend(CONSISTENCY_FIELDS.iter().map(|(field, _)| {
+        let header = column_def(field).map_or(*field, |def| def.header);
+        Cell::new(header).style_spec("b")
+    }));
+    table.set_titles(Row::new(titles));
+    for group in &found {
+        let mut cells = vec![
+            Cell::new(&group.name),
+            Cell::new(&group.files.to_string()).style_spec("r"),
+        ];
+        cells.extend(CONSISTENCY_FIELDS.iter().map(|(field, _)| {
+            let mix = group
+                .fields
+                .iter()
+                .find(|(name, _)| name == field)
+                .map(|(_, counts)| {
+                    counts
+                        .iter()
+                        .map(|(value, count)| format!("{} ×{}", value, count))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_default();
+            Cell::new(&mix)
+        }));
+        table.add_row(Row::new(cells));
+    }
+    write_table(&table, out)?;
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(Row::new(vec![
+        Cell::new("Filename").style_spec("b"),
+        Cell::new(if by == "show" { "Show" } else { "Folder" }).style_spec("b"),
+        Cell::new("Differs").style_spec("b"),
+    ]));
+    for group in &found {
+        for (row, differs) in &group.outliers {
+            let filename = row.path.file_name().unwrap_or_default().to_string_lossy();
+            let differs: Vec<String> = differs
+                .iter()
+                .map(|(field, value, usual)| format!("{} {} (others: {})", field, value, usual))
+                .collect();
+            table.add_row(Row::new(vec![
+                Cell::new(&truncate_middle(&filename, filename_length)),
+                Cell::new(&group.name),
+                Cell::new(&differs.join("; ")),
+            ]));
+        }
+    }
+    write_table(&table, out)?;
+    writeln!(
+        out,
+        "{} of {} {} are inconsistent",
+        found.len(),
+        checked,
+        kind
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Bits per pixel a re-encode to each `savings --target` codec is assumed to
+/// need for a good-looking result, from typical CRF 20-23 encodes of film
+/// content; grainy sources need more.
+const SAVINGS_TARGET_BPP: [(&str, f64); 3] = [("h264", 0.1), ("hevc", 0.06), ("av1", 0.045)];
+
+/// Prints `mediainfo savings`: for every video spending more bits per pixel
+/// than `bpp` (or the target codec's default), the size it would shrink to
+/// if the video were re-encoded at that rate, largest savings first. Audio
+/// and other streams are assumed to be copied unchanged.
+fn print_savings(
+    rows: &[ResultRow],
+    target: &str,
+    bpp: Option<f64>,
+    output: &str,
+    filename_length: usize,
+    raw: bool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let target_bpp = bpp.unwrap_or_else(|| {
+        SAVINGS_TARGET_BPP
+            .iter()
+            .find(|(codec, _)| *codec == target)
+            .map_or(0.06, |(_, bpp)| *bpp)
+    });
+    let size_text = |size: u64| {
+        if raw {
+            size.to_string()
+        } else {
+            format_size(&size.to_string())
+        }
+    };
+
+    // (row, bits per pixel, current size, estimated size)
+    let mut candidates: Vec<(&ResultRow, f64, u64, u64)> = rows
+        .iter()
+        .filter_map(|row| {
+            let bpp = bits_per_pixel(&row.probe)?;
+            let size = row.probe.format.size.parse::<u64>().ok()?;
+            let duration = row.probe.format.duration.parse::<f64>().ok()?;
+            if bpp <= target_bpp || duration <= 0.0 {
+                return None;
+            }
+            // bits_per_pixel is bitrate / (pixels x fps), so the video
+            // bitrate scales with it
+            let bitrate = bpp_bitrate(&row.probe)?;
+            let saved = bitrate * (1.0 - target_bpp / bpp) * duration / 8.0;
+            let estimated = size.saturating_sub(saved as u64);
+            Some((row, bpp, size, estimated))
+        })
+        .collect();
+    candidates.sort_by_key(|(_, _, size, estimated)| std::cmp::Reverse(size - estimated));
+
+    let total: u64 = rows
+        .iter()
+        .filter_map(|row| row.probe.format.size.parse::<u64>().ok())
+        .sum();
+    let saved: u64 = candidates
+        .iter()
+        .map(|(_, _, size, estimated)| size - estimated)
+        .sum();
+
+    if output == "json" {
+        let report: Vec<serde_json::Value> = candidates
+            .iter()
+            .map(|(row, bpp, size, estimated)| {
+                serde_json::json!({
+                    "id": file_id(&row.path),
+                    "path": row.path,
+                    "codec": primary_video(&row.probe).and_then(|v| v.codec_name.clone()),
+                    "bpp": bpp,
+                    "size": size,
+                    "estimated_size": estimated,
+                    "savings": size - estimated,
+                })
+            })
+            .collect();
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "target": target,
+                "target_bpp": target_bpp,
+                "total_size": total,
+                "total_savings": saved,
+                "files": report,
+            }))?
+        )?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    if candidates.is_empty() {
+        writeln!(
+            out,
+            "No video spends more than {} bits per pixel; nothing to gain from {}",
+            target_bpp, target
+        )?;
+        out.flush()?;
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(Row::new(vec![
+        Cell::new("Filename").style_spec("b"),
+        Cell::new("Format").style_spec("b"),
+        Cell::new("Resolution").style_spec("b"),
+        Cell::new("BPP").style_spec("br"),
+        Cell::new("Size").style_spec("br"),
+        Cell::new("Estimated").style_spec("br"),
+        Cell::new("Savings").style_spec("br"),
+    ]));
+    for (row, bpp, size, estimated) in &candidates {
+        let savings = size - estimated;
+        table.add_row(Row::new(vec![
+            Cell::new(&truncate_middle(
+                row_value(row, "filename"),
+                filename_length,
+            )),
+            Cell::new(row_value(row, "format")),
+            Cell::new(row_value(row, "resolution")),
+            Cell::new(&format!("{:.3}", bpp)).style_spec("r"),
+            Cell::new(&size_text(*size)).style_spec("r"),
+            Cell::new(&size_text(*estimated)).style_spec("r"),
+            Cell::new(&format!(
+                "{} ({:.0}%)",
+                size_text(savings),
+                100.0 * savings as f64 / *size as f64
+            ))
+            .style_spec("r"),
+        ]));
+    }
+    write_table(&table, out)?;
+    writeln!(
+        out,
+        "Re-encoding {} of {} files to {} at {} bits per pixel would save about {} of {} ({:.0}%)",
+        candidates.len(),
+        rows.len(),
+        target,
+        target_bpp,
+        size_text(saved),
+        size_text(total),
+        100.0 * saved as f64 / total.max(1) as f64
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Resolution class of a video stream. Width counts too, so letterboxed
+/// encodes (1920x800) land in the class they were mastered in.
+fn resolution_class(video: &Stream) -> &'static str {
+    let (width, height) = (video.width.unwrap_or(0), video.height.unwrap_or(0));
+    if width >= 3200 || height >= 1800 {
+        "2160p"
+    } else if width >= 2200 || height >= 1300 {
+        "1440p"
+    } else if width >= 1600 || height >= 900 {
+        "1080p"
+    } else if width >= 1100 || height >= 650 {
+        "720p"
+    } else {
+        "SD"
+    }
+}
+
+/// Upper bounds (exclusive, in Mbps) of the `mediainfo stats` bitrate buckets
+const BITRATE_BUCKETS: [(f64, &str); 6] = [
+    (2.0, "< 2 Mbps"),
+    (5.0, "2-5 Mbps"),
+    (10.0, "5-10 Mbps"),
+    (20.0, "10-20 Mbps"),
+    (40.0, "20-40 Mbps"),
+    (f64::INFINITY, "40+ Mbps"),
+];
+
+/// Prints `mediainfo stats`: how files and storage spread over video codecs,
+/// resolutions, bit depths and containers, plus an overall bitrate histogram.
+fn print_stats(rows: &[ResultRow], output: &str, raw: bool, out: &mut dyn Write) -> Result<()> {
+    let size_text = |size: u64| {
+        if raw {
+            size.to_string()
+        } else {
+            format_size(&size.to_string())
+        }
+    };
+
+    let mut by_codec: HashMap<String, (usize, u64)> = HashMap::new();
+    let mut by_resolution: HashMap<String, (usize, u64)> = HashMap::new();
+    let mut by_depth: HashMap<String, (usize, u64)> = HashMap::new();
+    let mut by_container: HashMap<String, (usize, u64)> = HashMap::new();
+    let mut by_bitrate = vec![(0usize, 0u64); BITRATE_BUCKETS.len()];
+    let mut total_size = 0;
+    for row in rows {
+        let size = row.probe.format.size.parse::<u64>().unwrap_or(0);
+        total_size += size;
+        let video = primary_video(&row.probe);
+        // Audio-only files are grouped together rather than spread over codecs
+        let (codec, resolution, depth) = match video {
+            Some(video) => (
+                video
+                    .codec_name
+                    .clone()
+                    .unwrap_or_else(|| "unknown".to_string()),
+                resolution_class(video).to_string(),
+                get_bit_depth(video.pix_fmt.as_deref()),
+            ),
+            None => (
+                "audio only".to_string(),
+                "audio only".to_string(),
+                "audio only".to_string(),
+            ),
+        };
+        let container = media_extension(&row.path).unwrap_or_default();
+        for (groups, key) in [
+            (&mut by_codec, codec),
+            (&mut by_resolution, resolution),
+            (&mut by_depth, depth),
+            (&mut by_container, container),
+        ] {
+            let entry = groups.entry(key).or_default();
+            entry.0 += 1;
+            entry.1 += size;
+        }
+
+        let duration = row.probe.format.duration.parse::<f64>().unwrap_or(0.0);
+        let bitrate = row
+            .probe
+            .format
+            .bit_rate
+            .as_deref()
+            .and_then(|b| b.parse::<f64>().ok())
+            .or_else(|| (duration > 0.0).then(|| size as f64 * 8.0 / duration));
+        if let Some(bitrate) = bitrate {
+            let mbps = bitrate / 1_000_000.0;
+            let bucket = BITRATE_BUCKETS
+                .iter()
+                .position(|(limit, _)| mbps < *limit)
+                .unwrap_or(BITRATE_BUCKETS.len() - 1);
+            by_bitrate[bucket].0 += 1;
+            by_bitrate[bucket].1 += size;
+        }
+    }
+
+    let sorted = |groups: HashMap<String, (usize, u64)>| {
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(&b.0)));
+        groups
+    };
+    let categories = [
+        ("Codec", "codecs", sorted(by_codec)),
+        ("Resolution", "resolutions", sorted(by_resolution)),
+        ("Depth", "bit_depths", sorted(by_depth)),
+        ("Container", "containers", sorted(by_container)),
+    ];
+
+    if output == "json" {
+        let mut summary = serde_json::json!({
+            "files": rows.len(),
+            "size_bytes": total_size,
+        });
+        for (_, key, groups) in &categories {
+            summary[*key] = groups
+                .iter()
+                .map(|(name, (files, size))| serde_json::json!({"name": name, "files": files, "size_bytes": size}))
+                .collect();
+        }
+        summary["bitrates"] = BITRATE_BUCKETS
+            .iter()
+            .zip(&by_bitrate)
+            .map(|((_, name), (files, size))| serde_json::json!({"name": name, "files": files, "size_bytes": size}))
+            .collect();
+        writeln!(out, "{}", serde_json::to_string_pretty(&summary)?)?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    let share = |size: u64| {
+        if total_size > 0 {
+            format!("{:.1}%", size as f64 * 100.0 / total_size as f64)
+        } else {
+            "-".to_string()
+        }
+    };
+    for (header, _, groups) in &categories {
+        let mut table = Table::new();
+        table.set_format(table_format());
+        table.set_titles(Row::new(vec![
+            Cell::new(header).style_spec("b"),
+            Cell::new("Files").style_spec("br"),
+            Cell::new("Size").style_spec("br"),
+            Cell::new("Share").style_spec("br"),
+        ]));
+        for (name, (files, size)) in groups {
+            table.add_row(Row::new(vec![
+                Cell::new(name),
+                Cell::new(&files.to_string()).style_spec("r"),
+                Cell::new(&size_text(*size)).style_spec("r"),
+                Cell::new(&share(*size)).style_spec("r"),
+            ]));
+        }
+        write_table(&table, out)?;
+    }
+
+    // Bars are scaled to the fullest bucket
+    let widest = by_bitrate
+        .iter()
+        .map(|(files, _)| *files)
+        .max()
+        .unwrap_or(0);
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(Row::new(vec![
+        Cell::new("Bitrate").style_spec("b"),
+        Cell::new("Files").style_spec("br"),
+        Cell::new("Size").style_spec("br"),
+        Cell::new(""),
+    ]));
+    for ((_, name), (files, size)) in BITRATE_BUCKETS.iter().zip(&by_bitrate) {
+        let bar = if widest > 0 {
+            "█".repeat((files * 30).div_ceil(widest))
+        } else {
+            String::new()
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(name),
+            Cell::new(&files.to_string()).style_spec("r"),
+            Cell::new(&size_text(*size)).style_spec("r"),
+            Cell::new(&bar),
+        ]));
+    }
+    write_table(&table, out)?;
+
+    writeln!(out, "{} files, {}", rows.len(), size_text(total_size))?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Languages of the sidecar subtitle files next to a media file
+/// (`Movie.eng.srt`, `Movie.pt-BR.forced.srt` for `Movie.mkv`), as guessed
+/// from their names.
+fn sidecar_subtitle_languages(file: &Path) -> Vec<String> {
+    let Some(stem) = file.file_stem().and_then(|stem| stem.to_str()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", stem);
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_subtitle_file(path))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix))
+        })
+        .filter_map(|path| subtitle_name_hints(&path).0)
+        .collect()
+}
+
+/// Whether a track language satisfies a requested one; a regional variant
+/// (`pt-br`) counts for its base language (`pt`).
+fn language_matches(language: &str, wanted: &str) -> bool {
+    language == wanted
+        || language
+            .split(['-', '_'])
+            .next()
+            .is_some_and(|base| base == wanted)
+}
+
+/// Prints `--audio-languages` and `--subtitle-languages`: for each language,
+/// how many of the files have at least one stream of `codec_type` in it, and
+/// how many don't. Files whose streams carry no language tag are counted
+/// separately, since they may well contain the language being audited.
+/// Subtitle coverage includes sidecar subtitle files. When languages are
+/// requested, they are always listed and the files lacking any of them follow.
+fn print_language_coverage(
+    rows: &[ResultRow],
+    codec_type: &str,
+    wanted: &[String],
+    output: &str,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let wanted: Vec<String> = wanted.iter().map(|l| l.to_lowercase()).collect();
+    let mut by_language: HashMap<String, usize> = wanted.iter().map(|l| (l.clone(), 0)).collect();
+    let (mut untagged, mut without) = (0, 0);
+    let mut lacking: Vec<(&Path, Vec<&str>)> = Vec::new();
+    for row in rows {
+        let streams: Vec<&Stream> = row
+            .probe
+            .streams
+            .iter()
+            .filter(|s| s.codec_type == codec_type)
+            .collect();
+        let mut languages: HashSet<String> = streams
+            .iter()
+            .filter_map(|s| stream_language(s))
+            .map(str::to_lowercase)
+            .collect();
+        let mut sidecars = 0;
+        if codec_type == "subtitle" && !is_subtitle_file(&row.path) {
+            let sidecar_languages = sidecar_subtitle_languages(&row.path);
+            sidecars = sidecar_languages.len();
+            languages.extend(sidecar_languages.iter().map(|l| l.to_lowercase()));
+        }
+
+        let missing: Vec<&str> = wanted
+            .iter()
+            .filter(|w| !languages.iter().any(|l| language_matches(l, w)))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            lacking.push((&row.path, missing));
+        }
+
+        if streams.is_empty() && sidecars == 0 {
+            without += 1;
+            continue;
+        }
+        if streams.iter().any(|s| stream_language(s).is_none()) {
+            untagged += 1;
+        }
+        for language in &languages {
+            *by_language.entry(language.clone()).or_default() += 1;
+        }
+        // Regional variants also count for a requested base language
+        for w in &wanted {
+            if !languages.contains(w) && languages.iter().any(|l| language_matches(l, w)) {
+                *by_language.entry(w.clone()).or_default() += 1;
+            }
+        }
+    }
+    let mut by_language: Vec<_> = by_language.into_iter().collect();
+    by_language.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let coverage = |files: usize| {
+        if rows.is_empty() {
+            0.0
+        } else {
+            files as f64 * 100.0 / rows.len() as f64
+        }
+    };
+
+    if output == "json" {
+        let languages: Vec<_> = by_language
+            .iter()
+            .map(|(language, files)| {
+                serde_json::json!({
+                    "language": language,
+                    "files": files,
+                    "missing": rows.len() - files,
+                    "coverage_percent": (coverage(*files) * 10.0).round() / 10.0,
+                })
+            })
+            .collect();
+        let mut summary = serde_json::json!({
+            "files": rows.len(),
+            "languages": languages,
+            "untagged": untagged,
+            format!("without_{}", codec_type): without,
+        });
+        if !wanted.is_empty() {
+            summary["lacking"] = lacking
+                .iter()
+                .map(|(path, missing)| serde_json::json!({"path": path, "missing": missing}))
+                .collect();
+        }
+        writeln!(out, "{}", serde_json::to_string_pretty(&summary)?)?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(Row::new(vec![
+        Cell::new("Language").style_spec("b"),
+        Cell::new("Files").style_spec("br"),
+        Cell::new("Missing").style_spec("br"),
+        Cell::new("Coverage").style_spec("br"),
+    ]));
+    for (language, files) in &by_language {
+        table.add_row(Row::new(vec![
+            Cell::new(language),
+            Cell::new(&files.to_string()).style_spec("r"),
+            Cell::new(&(rows.len() - files).to_string()).style_spec("r"),
+            Cell::new(&format!("{:.1}%", coverage(*files))).style_spec("r"),
+        ]));
+    }
+    write_table(&table, out)?;
+
+    if !lacking.is_empty() {
+        let mut table = Table::new();
+        table.set_format(table_format());
+        table.set_titles(Row::new(vec![
+            Cell::new("File").style_spec("b"),
+            Cell::new("Missing").style_spec("b"),
+        ]));
+        for (path, missing) in &lacking {
+            table.add_row(Row::new(vec![
+                Cell::new(&path.display().to_string()),
+                Cell::new(&missing.join(", ")),
+            ]));
+        }
+        write_table(&table, out)?;
+    }
+    writeln!(
+        out,
+        "{} files, {} with untagged {} tracks, {} without {}",
+        rows.len(),
+        untagged,
+        codec_type,
+        without,
+        match codec_type {
+            "subtitle" => "subtitles",
+            _ => codec_type,
+        }
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Prints library totals for `--summary`: a per-codec breakdown (video codec,
+/// or audio codec for files without video) followed by an overall line.
+fn print_totals(rows: &[ResultRow], raw: bool, out: &mut dyn Write) -> Result<()> {
+    let size_text = |size: u64| {
+        if raw {
+            size.to_string()
+        } else {
+            format_size(&size.to_string())
+        }
+    };
+    let duration_text = |secs: f64| {
+        if raw {
+            format!("{:.0}", secs)
+        } else {
+            format_duration(&secs.to_string())
+        }
+    };
+
+    let mut by_codec: HashMap<String, (usize, u64, f64)> = HashMap::new();
+    let (mut total_size, mut total_duration) = (0u64, 0f64);
+    for row in rows {
+        let size = row.probe.format.size.parse::<u64>().unwrap_or(0);
+        let duration = row.probe.format.duration.parse::<f64>().unwrap_or(0.0);
+        total_size += size;
+        total_duration += duration;
+        let codec = primary_video(&row.probe)
+            .or_else(|| primary_audio(&row.probe))
+            .and_then(|s| s.codec_name.clone())
+            .unwrap_or_else(|| "unknown".to_string());
+        let entry = by_codec.entry(codec).or_default();
+        entry.0 += 1;
+        entry.1 += size;
+        entry.2 += duration;
+    }
+    let mut by_codec: Vec<_> = by_codec.into_iter().collect();
+    by_codec.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(&b.0)));
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(Row::new(vec![
+        Cell::new("Codec").style_spec("b"),
+        Cell::new("Files").style_spec("br"),
+        Cell::new("Size").style_spec("br"),
+        Cell::new("Duration").style_spec("br"),
+    ]));
+    for (codec, (files, size, duration)) in &by_codec {
+        table.add_row(Row::new(vec![
+            Cell::new(codec),
+            Cell::new(&files.to_string()).style_spec("r"),
+            Cell::new(&size_text(*size)).style_spec("r"),
+            Cell::new(&duration_text(*duration)).style_spec("r"),
+        ]));
+    }
+    table.print(out)?;
+
+    // Weighted by duration, so long files count for more than short clips
+    let average_bitrate = if total_duration > 0.0 {
+        total_size as f64 * 8.0 / total_duration
+    } else {
+        0.0
+    };
+    let bitrate_text = if raw {
+        format!("{:.0}", average_bitrate)
+    } else {
+        format!("{:.2} Mbps", average_bitrate / 1_000_000.0)
+    };
+    writeln!(
+        out,
+        "{} files, {}, {} total duration, {} average bitrate",
+        rows.len(),
+        size_text(total_size),
+        duration_text(total_duration),
+        bitrate_text
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Best-effort detection of terminals known to support OSC 8 hyperlinks.
+fn supports_hyperlinks() -> bool {
+    if !std::io::stdout().is_terminal() {
+        return false;
+    }
+    if let Ok(force) = std::env::var("FORCE_HYPERLINK") {
+        return force != "0";
+    }
+    if ["DOMTERM", "WT_SESSION", "KITTY_WINDOW_ID"]
+        .iter()
+        .any(|v| std::env::var_os(v).is_some())
+    {
+        return true;
+    }
+    if let Ok(program) = std::env::var("TERM_PROGRAM") {
+        if ["iTerm.app", "WezTerm", "vscode", "ghostty", "Hyper"].contains(&program.as_str()) {
+            return true;
+        }
+    }
+    if let Ok(version) = std::env::var("VTE_VERSION") {
+        if version.parse::<u32>().unwrap_or(0) >= 5000 {
+            return true;
+        }
+    }
+    matches!(
+        std::env::var("TERM").as_deref(),
+        Ok("xterm-kitty") | Ok("alacritty") | Ok("foot") | Ok("wezterm")
+    )
+}
+
+fn hyperlink(path: &Path, text: &str) -> String {
+    format!("\x1B]8;;{}\x1B\\{}\x1B]8;;\x1B\\", file_url(path), text)
+}
+
+/// `file://` URL of a path, percent-encoded; network streams keep their URL.
+fn file_url(path: &Path) -> String {
+    if is_network_url(path) {
+        return path.display().to_string();
+    }
+    let absolute = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut url = String::from("file://");
+    for byte in absolute.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+/// Prints results as a JSON array. Each entry carries the displayed columns
+/// plus the full probe data, so it can be re-rendered later with --from-json.
+fn print_json(
+    rows: &[ResultRow],
+    columns: &[&str],
+    raw_columns: bool,
+    raw: bool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let entries: Vec<serde_json::Value> = rows
+        .iter()
+        .map(|row| {
+            let mut entry = serde_json::Map::new();
+            entry.insert("id".to_string(), serde_json::json!(file_id(&row.path)));
+            entry.insert("path".to_string(), serde_json::json!(row.path));
+            for column in columns {
+                let value = row_value(row, column);
+                let value = match value.parse::<u64>() {
+                    Ok(number) if raw && RAW_VALUE_COLUMNS.contains(column) => {
+                        serde_json::json!(number)
+                    }
+                    _ => serde_json::json!(value),
+                };
+                entry.insert(column.to_string(), value);
+            }
+            if columns.contains(&"audio_tracks") {
+                let codecs: Vec<&str> = row
+                    .probe
+                    .streams
+                    .iter()
+                    .filter(|s| s.codec_type == "audio")
+                    .map(|s| s.codec_name.as_deref().unwrap_or("unknown"))
+                    .collect();
+                entry.insert("audio_codecs".to_string(), serde_json::json!(codecs));
+            }
+            if columns.contains(&"audio_streams") {
+                let streams: Vec<serde_json::Value> = row
+                    .probe
+                    .streams
+                    .iter()
+                    .filter(|s| s.codec_type == "audio")
+                    .map(audio_stream_details)
+                    .collect();
+                entry.insert(
+                    "audio_stream_details".to_string(),
+                    serde_json::json!(streams),
+                );
+            }
+            if columns.contains(&"subtitles") {
+                let streams: Vec<serde_json::Value> = row
+                    .probe
+                    .streams
+                    .iter()
+                    .filter(|s| s.codec_type == "subtitle")
+                    .map(subtitle_stream_details)
+                    .collect();
+                entry.insert("subtitle_details".to_string(), serde_json::json!(streams));
+            }
+            if raw_columns {
+                for (name, value) in RAW_COLUMN_NAMES.iter().zip(raw_fields(&row.probe)) {
+                    entry.insert(name.to_string(), value);
+                }
+            }
+            entry.insert("probe".to_string(), serde_json::json!(row.probe));
+            serde_json::Value::Object(entry)
+        })
+        .collect();
+    writeln!(out, "{}", serde_json::to_string_pretty(&entries)?)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes the table columns as CSV (RFC 4180 quoting) or TSV (tabs and
+/// newlines in values replaced by spaces), with a header row.
+fn print_delimited(
+    rows: &[ResultRow],
+    columns: &[&str],
+    raw_columns: bool,
+    delimiter: char,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let escape = |value: &str| -> String {
+        if delimiter == '\t' {
+            value.replace(['\t', '\n', '\r'], " ")
+        } else if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+    let separator = delimiter.to_string();
+
+    let defs: Vec<&ColumnDef> = columns.iter().filter_map(|c| column_def(c)).collect();
+    let mut header: Vec<String> = defs.iter().map(|def| def.id.to_string()).collect();
+    if raw_columns {
+        header.extend(RAW_COLUMN_NAMES.iter().map(|name| name.to_string()));
+    }
+    match HEADER_STYLE.lock().unwrap().as_str() {
+        "none" => {}
+        "comment" => writeln!(out, "# {}", header.join(&separator))?,
+        _ => writeln!(out, "{}", header.join(&separator))?,
+    }
+
+    for row in rows {
+        let mut fields: Vec<String> = defs
+            .iter()
+            .map(|def| escape(row_value(row, def.id)))
+            .collect();
+        if raw_columns {
+            fields.extend(raw_fields(&row.probe).iter().map(|value| match value {
+                serde_json::Value::Null => String::new(),
+                value => value.to_string(),
+            }));
+        }
+        writeln!(out, "{}", fields.join(&separator))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Returns the placeholder names in a --template string, rejecting unknown
+/// ones and unbalanced braces. `{{` and `}}` stand for literal braces.
+fn template_placeholders(template: &str) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(anyhow!("Unclosed '{{{}' in template", name)),
+                    }
+                }
+                if !TEMPLATE_RAW_PLACEHOLDERS.contains(&name.as_str())
+                    && column_def(resolve_column(&name)).is_none()
+                {
+                    return Err(anyhow!(
+                        "Unknown template placeholder '{{{}}}' (expected a column id or one of: {})",
+                        name,
+                        TEMPLATE_RAW_PLACEHOLDERS.join(", ")
+                    ));
+                }
+                names.push(name);
+            }
+            '}' => {
+                return Err(anyhow!(
+                    "Unmatched '}}' in template (use '}}}}' for a literal brace)"
+                ))
+            }
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
+/// Unformatted value of a raw template placeholder: bytes, seconds and bits
+/// per second as the probe reports them, and the primary video's dimensions.
+/// Empty when the probe doesn't have it.
+fn template_raw_value(row: &ResultRow, name: &str) -> String {
+    let video = primary_video(&row.probe);
+    let value = match name {
+        "path" => Some(row.path.display().to_string()),
+        "size_bytes" => row
+            .probe
+            .format
+            .size
+            .parse::<u64>()
+            .ok()
+            .map(|n| n.to_string()),
+        "duration_s" => row
+            .probe
+            .format
+            .duration
+            .parse::<f64>()
+            .ok()
+            .map(|n| n.to_string()),
+        "bitrate_bps" => row
+            .probe
+            .format
+            .bit_rate
+            .as_deref()
+            .and_then(|b| b.parse::<u64>().ok())
+            .map(|n| n.to_string()),
+        "width" => video.and_then(|v| v.width).map(|n| n.to_string()),
+        "height" => video.and_then(|v| v.height).map(|n| n.to_string()),
+        _ => None,
+    };
+    value.unwrap_or_default()
+}
+
+/// Writes one line per row with the --template placeholders filled in.
+fn print_template(rows: &[ResultRow], template: &str, out: &mut dyn Write) -> Result<()> {
+    for row in rows {
+        let mut line = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    line.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    line.push('}');
+                }
+                '{' => {
+                    let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    if TEMPLATE_RAW_PLACEHOLDERS.contains(&name.as_str()) {
+                        line.push_str(&template_raw_value(row, &name));
+                    } else {
+                        line.push_str(row_value(row, resolve_column(&name)));
+                    }
+                }
+                c => line.push(c),
+            }
+        }
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes the table columns as a GitHub-flavored Markdown table, aligned like
+/// the terminal table. Pipes in values are escaped and newlines flattened.
+fn print_markdown(rows: &[ResultRow], columns: &[&str], out: &mut dyn Write) -> Result<()> {
+    let escape = |value: &str| value.replace('|', "\\|").replace(['\n', '\r'], " ");
+    let defs: Vec<&ColumnDef> = columns.iter().filter_map(|c| column_def(c)).collect();
+    // Without a header only the rows are written, to append to an existing table
+    if HEADER_STYLE.lock().unwrap().as_str() != "none" {
+        let header: Vec<String> = defs.iter().map(|def| escape(def.header)).collect();
+        writeln!(out, "| {} |", header.join(" | "))?;
+        let alignment: Vec<&str> = defs
+            .iter()
+            .map(|def| match def.align {
+                "r" => "---:",
+                "c" => ":---:",
+                _ => "---",
+            })
+            .collect();
+        writeln!(out, "| {} |", alignment.join(" | "))?;
+    }
+    for row in rows {
+        let fields: Vec<String> = defs
+            .iter()
+            .map(|def| escape(row_value(row, def.id)))
+            .collect();
+        writeln!(out, "| {} |", fields.join(" | "))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Click handler for the `--output html` headers. Cells carry their position
+/// in the column's sort order (as `--sort` would order them) in `data-sort`,
+/// so sizes, durations and dates sort by value rather than by text.
+const HTML_SORT_SCRIPT: &str = r#"document.querySelectorAll("th").forEach((th, column) => {
+  th.addEventListener("click", () => {
+    const ascending = th.dataset.order !== "asc";
+    document.querySelectorAll("th").forEach((other) => {
+      delete other.dataset.order;
+    });
+    th.dataset.order = ascending ? "asc" : "desc";
+    const body = document.querySelector("tbody");
+    const rows = Array.from(body.rows);
+    rows.sort((a, b) => {
+      const delta = a.cells[column].dataset.sort - b.cells[column].dataset.sort;
+      return ascending ? delta : -delta;
+    });
+    rows.forEach((row) => body.appendChild(row));
+  });
+});"#;
+
+/// Writes the table columns as a standalone HTML page whose column headers
+/// sort the rows when clicked. The initial order is that of the scan.
+fn print_html(
+    rows: &[ResultRow],
+    columns: &[&str],
+    (sort_column, ascending): (&str, bool),
+    out: &mut dyn Write,
+) -> Result<()> {
+    let defs: Vec<&ColumnDef> = columns.iter().filter_map(|c| column_def(c)).collect();
+
+    // Rank of every row within each column; equal values share a rank
+    let ranks: Vec<Vec<usize>> = defs
+        .iter()
+        .map(|def| {
+            let mut order: Vec<usize> = (0..rows.len()).collect();
+            order.sort_by(|&a, &b| compare_rows(&rows[a], &rows[b], def.id));
+            let mut ranks = vec![0; rows.len()];
+            for (position, &index) in order.iter().enumerate().skip(1) {
+                let previous = order[position - 1];
+                ranks[index] = match compare_rows(&rows[previous], &rows[index], def.id) {
+                    std::cmp::Ordering::Equal => ranks[previous],
+                    _ => position,
+                };
+            }
+            ranks
+        })
+        .collect();
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>mediainfo</title>")?;
+    writeln!(
+        out,
+        "<style>\n\
+         body {{ font-family: sans-serif; }}\n\
+         table {{ border-collapse: collapse; }}\n\
+         th, td {{ border: 1px solid #ccc; padding: 4px 8px; }}\n\
+         th {{ cursor: pointer; background: #f4f4f4; }}\n\
+         th[data-order=asc]::after {{ content: \" \\25B2\"; }}\n\
+         th[data-order=desc]::after {{ content: \" \\25BC\"; }}\n\
+         .r {{ text-align: right; }}\n\
+         .c {{ text-align: center; }}\n\
+         </style>"
+    )?;
+    writeln!(out, "</head>\n<body>\n<table>\n<thead>\n<tr>")?;
+    for def in &defs {
+        let order = match def.id == sort_column {
+            true if ascending => " data-order=\"asc\"",
+            true => " data-order=\"desc\"",
+            false => "",
+        };
+        writeln!(
+            out,
+            "<th class=\"{}\"{}>{}</th>",
+            def.align,
+            order,
+            html_escape(def.header)
+        )?;
+    }
+    writeln!(out, "</tr>\n</thead>\n<tbody>")?;
+    for (index, row) in rows.iter().enumerate() {
+        write!(out, "<tr>")?;
+        for (def, ranks) in defs.iter().zip(&ranks) {
+            write!(
+                out,
+                "<td class=\"{}\" data-sort=\"{}\">{}</td>",
+                def.align,
+                ranks[index],
+                html_escape(row_value(row, def.id))
+            )?;
+        }
+        writeln!(out, "</tr>")?;
+    }
+    writeln!(out, "</tbody>\n</table>")?;
+    writeln!(out, "<script>\n{}\n</script>", HTML_SORT_SCRIPT)?;
+    writeln!(out, "</body>\n</html>")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Prints the version and the environment it runs against, the first thing
+/// to ask for in a bug report.
+fn print_version(args: &Args, config: &Config) -> Result<()> {
+    println!(
+        "mediainfo {} ({} {})",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+
+    let ffprobe = match find_in_path("ffprobe") {
+        Some(path) => {
+            let version = Command::new(&path)
+                .arg("-version")
+                .output()
+                .ok()
+                .and_then(|output| {
+                    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+                    // "ffprobe version 6.1.1 Copyright ..."
+                    stdout
+                        .lines()
+                        .next()
+                        .and_then(|line| line.strip_prefix("ffprobe version "))
+                        .and_then(|rest| rest.split_whitespace().next())
+                        .map(str::to_string)
+                })
+                .unwrap_or_else(|| "unknown version".to_string());
+            format!("{} ({})", version, path.display())
+        }
+        None => "not found in PATH".to_string(),
+    };
+    println!("ffprobe:  {}", ffprobe);
+
+    let config_file = get_config_file()?;
+    println!(
+        "config:   {}{}",
+        config_file.display(),
+        if config_file.exists() {
+            ""
+        } else {
+            " (not present)"
+        }
+    );
+
+    let backend = match args.cache_backend.clone() {
+        Some(backend) => backend,
+        None => config
+            .cache_backend
+            .clone()
+            .unwrap_or_else(|| "json".to_string()),
+    };
+    let cache_files = if backend == "sqlite" {
+        vec![get_cache_db_file()?]
+    } else {
+        vec![get_cache_file()?, get_cache_journal_file()?]
+    };
+    let cache_size: u64 = cache_files
+        .iter()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    println!(
+        "cache:    {} ({} backend, {})",
+        cache_files[0].display(),
+        backend,
+        format_size(&cache_size.to_string())
+    );
+
+    let mut features = vec![format!("sqlite {}", rusqlite::version())];
+    if xattr::SUPPORTED_PLATFORM {
+        features.push("xattr".to_string());
+    }
+    features.push("watch".to_string());
+    features.push("native probing (mp4, matroska, audio)".to_string());
+    if supports_hyperlinks() {
+        features.push("hyperlinks".to_string());
+    }
+    println!("features: {}", features.join(", "));
+    Ok(())
+}
+
+/// Full path of an executable found on PATH, like `which`.
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let name = if cfg!(windows) {
+        format!("{}.exe", program)
+    } else {
+        program.to_string()
+    };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
+
+/// Prints the column ids accepted by --columns, --sort and --filter, with
+/// their display headers and aliases.
+fn print_column_list() {
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.add_row(Row::new(vec![
+        Cell::new("ID").with_style(Attr::Bold),
+        Cell::new("Header").with_style(Attr::Bold),
+        Cell::new("Aliases").with_style(Attr::Bold),
+    ]));
+    for def in COLUMN_DEFS {
+        let aliases: Vec<&str> = COLUMN_ALIASES
+            .iter()
+            .filter(|(_, id)| *id == def.id)
+            .map(|(alias, _)| *alias)
+            .collect();
+        table.add_row(Row::new(vec![
+            Cell::new(def.id),
+            Cell::new(def.header),
+            Cell::new(&aliases.join(", ")),
+        ]));
+    }
+    table.printstd();
+}
+
+fn column_def(id: &str) -> Option<&'static ColumnDef> {
+    COLUMN_DEFS.iter().find(|def| def.id == id)
+}
+
+/// Maps a column alias to its id; other names are returned unchanged.
+fn resolve_column(name: &str) -> &str {
+    COLUMN_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, id)| id)
+}
+
+/// Writes the completion script for `shell` to stdout. Column arguments are
+/// parsed by functions clap can't see into, so the script is generated from
+/// a copy of the command where they list the column ids and aliases instead,
+/// with the header text as description. Filters complete to `column:`.
+fn print_completions(shell: Shell) {
+    let columns: Vec<PossibleValue> = COLUMN_DEFS
+        .iter()
+        .map(|def| PossibleValue::new(def.id).help(def.header))
+        .chain(
+            COLUMN_ALIASES
+                .iter()
+                .map(|(alias, id)| PossibleValue::new(*alias).help(*id)),
+        )
+        .collect();
+    let filters: Vec<PossibleValue> = COLUMN_DEFS
+        .iter()
+        .map(|def| {
+            // Leaked once per run of the generator
+            let name: &'static str = Box::leak(format!("{}:", def.id).into_boxed_str());
+            PossibleValue::new(name).help(def.header)
+        })
+        .collect();
+    let mut command = Args::command();
+    for arg in ["sort", "columns", "add_columns"] {
+        command = command.mut_arg(arg, |arg| {
+            arg.value_parser(PossibleValuesParser::new(columns.clone()))
+        });
+    }
+    for arg in ["filter", "filter_any", "fail_if"] {
+        command = command.mut_arg(arg, |arg| {
+            arg.value_parser(PossibleValuesParser::new(filters.clone()))
+        });
+    }
+    clap_complete::generate(shell, &mut command, "mediainfo", &mut std::io::stdout());
+}
+
+/// Clap value parser for column arguments, accepting ids and aliases.
+fn parse_column_arg(name: &str) -> Result<String, String> {
+    let id = resolve_column(name);
+    match column_def(id) {
+        Some(def) => Ok(def.id.to_string()),
+        None => Err(format!(
+            "unknown column '{}' (expected one of: {})",
+            name,
+            COLUMN_DEFS
+                .iter()
+                .map(|c| c.id)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn row_value<'a>(row: &'a ResultRow, column: &str) -> &'a str {
+    row.values.get(column).map(String::as_str).unwrap_or("")
+}
+
+/// Size in bytes, duration in seconds, bitrates in bits per second, frame
+/// rate and resolution in pixels, straight from the probe data. Values the
+/// probe doesn't have are left out.
+fn numeric_values(probe: &FFProbeOutput) -> HashMap<&'static str, f64> {
+    let number = |text: Option<&str>| text.and_then(|t| t.parse::<f64>().ok());
+    let mut numbers = HashMap::new();
+    let format = &probe.format;
+    numbers.insert("size", number(Some(&format.size)));
+    numbers.insert("duration", number(Some(&format.duration)));
+    numbers.insert("bitrate", number(format.bit_rate.as_deref()));
+    if let Some(video) = primary_video(probe) {
+        let fps = video.r_frame_rate.as_deref().and_then(|rate| {
+            let (num, den) = rate.split_once('/')?;
+            let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+            (den != 0.0).then(|| num / den)
+        });
+        numbers.insert("fps", fps);
+        let pixels = video.width.unwrap_or(0) as f64 * video.height.unwrap_or(0) as f64;
+        numbers.insert("resolution", Some(pixels));
+        numbers.insert(
+            "aspect",
+            display_aspect(video).map(|(w, h)| w as f64 / h as f64),
+        );
+    }
+    numbers.insert("reencode", reencode_priority(probe));
+    numbers.insert("bpp", bits_per_pixel(probe));
+    numbers.insert("created", creation_secs(probe).map(|secs| secs as f64));
+    if let Some(audio) = primary_audio(probe) {
+        // Same fallback as the column: lossless files may only have the overall rate
+        let only_stream = probe.streams.len() == 1;
+        let bitrate = audio
+            .bit_rate
+            .as_deref()
+            .or(format.bit_rate.as_deref().filter(|_| only_stream));
+        numbers.insert("audio_bitrate", number(bitrate));
+    }
+    numbers
+        .into_iter()
+        .filter_map(|(column, value)| Some((column, value?)))
+        .collect()
+}
+
+fn compare_rows(a: &ResultRow, b: &ResultRow, column: &str) -> std::cmp::Ordering {
+    if NUMERIC_VALUE_COLUMNS.contains(&column) {
+        let number = |row: &ResultRow| row.numbers.get(column).copied().unwrap_or(0.0);
+        return number(a)
+            .partial_cmp(&number(b))
+            .unwrap_or(std::cmp::Ordering::Equal);
+    }
+    let (a_value, b_value) = (row_value(a, column), row_value(b, column));
+    match column {
+        "audio_tracks" | "segments" | "events" => a_value
+            .parse::<u32>()
+            .unwrap_or(0)
+            .cmp(&b_value.parse::<u32>().unwrap_or(0)),
+        // Relative dates can't be compared as text, use the timestamps instead
+        "modified" | "first_seen" | "last_seen" => {
+            let secs = |row: &ResultRow| row.numbers.get(column).copied();
+            secs(a)
+                .partial_cmp(&secs(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        }
+        "sample_rate" => {
+            let number = |value: &str| {
+                value
+                    .trim_end_matches(|c: char| !c.is_ascii_digit())
+                    .parse::<f64>()
+                    .unwrap_or(0.0)
+            };
+            number(a_value)
+                .partial_cmp(&number(b_value))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        }
+        "size_delta" | "bitrate_delta" => parse_delta_percent(a_value)
+            .partial_cmp(&parse_delta_percent(b_value))
+            .unwrap_or(std::cmp::Ordering::Equal),
+        _ => a_value.cmp(b_value),
+    }
+}
+
+/// Replaces the human-readable size, duration and bitrate of a row with
+/// plain integers for `--raw`. Values left empty (e.g. no video stream) stay empty.
+fn apply_raw_values(row: &mut ResultRow) {
+    let format = &row.probe.format;
+    let raw = [
+        format.size.parse::<u64>().ok(),
+        format
+            .duration
+            .parse::<f64>()
+            .ok()
+            .map(|secs| secs.round() as u64),
+        format
+            .bit_rate
+            .as_deref()
+            .and_then(|b| b.parse::<u64>().ok()),
+    ];
+    for (column, value) in RAW_VALUE_COLUMNS.iter().zip(raw) {
+        if let (Some(current), Some(value)) = (row.values.get_mut(column), value) {
+            if !current.is_empty() {
+                *current = value.to_string();
+            }
+        }
+    }
+}
+
+fn raw_fields(probe: &FFProbeOutput) -> [serde_json::Value; 3] {
+    [
+        serde_json::json!(probe.format.size.parse::<u64>().ok()),
+        serde_json::json!(probe.format.duration.parse::<f64>().ok()),
+        serde_json::json!(probe
+            .format
+            .bit_rate
+            .as_deref()
+            .and_then(|b| b.parse::<u64>().ok())),
+    ]
+}
+
+fn load_json_results(path: &Path) -> Result<Vec<(PathBuf, FFProbeOutput)>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let entries: Vec<ExportedEntry> = serde_json::from_str(&content)
+        .map_err(|e| anyhow!("Invalid result file {}: {}", path.display(), e))?;
+    Ok(entries
+        .into_iter()
+        .map(|e| (local_path(&e.path), e.probe))
+        .collect())
+}
+
+/// Unions several exported result sets, printing the merged set as JSON.
+/// Entries are kept in order of first appearance; later files replace duplicates.
+fn merge_results(files: &[PathBuf], dedupe: &str) -> Result<()> {
+    let mut merged: Vec<serde_json::Value> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut duplicates = 0;
+
+    for file in files {
+        let content = fs::read_to_string(file)
+            .map_err(|e| anyhow!("Failed to read {}: {}", file.display(), e))?;
+        let entries: Vec<serde_json::Value> = serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Invalid result file {}: {}", file.display(), e))?;
+
+        for entry in entries {
+            let parsed: ExportedEntry = serde_json::from_value(entry.clone())
+                .map_err(|e| anyhow!("Invalid entry in {}: {}", file.display(), e))?;
+            let key = match dedupe {
+                "content" => probe_fingerprint(&parsed.probe)?,
+                _ => parsed.path.to_string_lossy().into_owned(),
+            };
+            match index.get(&key) {
+                Some(&i) => {
+                    duplicates += 1;
+                    merged[i] = entry;
+                }
+                None => {
+                    index.insert(key, merged.len());
+                    merged.push(entry);
+                }
+            }
+        }
+    }
+
+    info!(
+        "Merged {} files: {} entries ({} duplicates removed)",
+        files.len(),
+        merged.len(),
+        duplicates
+    );
+    println!("{}", serde_json::to_string_pretty(&merged)?);
+    Ok(())
+}
+
+/// Identifies a file by its probed content rather than its location, so the
+/// same media stored under different paths on different machines matches.
+fn probe_fingerprint(probe: &FFProbeOutput) -> Result<String> {
+    let mut hasher = XxHash64::default();
+    hasher.write(probe.format.size.as_bytes());
+    hasher.write(probe.format.duration.as_bytes());
+    hasher.write(&serde_json::to_vec(&probe.streams)?);
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Seeds the cache from MediaInfo XML or JSON exports, so a library already
+/// catalogued with MediaInfo doesn't have to be probed again. Entries for
+/// files that aren't reachable locally can't be cached (there is nothing to
+/// sign), but are still part of the result set printed with `--print`.
+fn import_mediainfo(exports: &[PathBuf], print: bool, args: &Args) -> Result<()> {
+    let mut entries: Vec<(PathBuf, FFProbeOutput)> = Vec::new();
+    for export in exports {
+        let content = fs::read_to_string(export)
+            .map_err(|e| anyhow!("Failed to read {}: {}", export.display(), e))?;
+        let media = parse_mediainfo_export(&content)
+            .map_err(|e| anyhow!("Invalid MediaInfo export {}: {}", export.display(), e))?;
+        for media in &media {
+            entries.push(mediainfo_to_probe(media)?);
+        }
+    }
+
+    let (mut cached, mut missing) = (0, 0);
+    for (path, probe) in &mut entries {
+        if !path.is_file() {
+            missing += 1;
+            continue;
+        }
+        if probe.format.size.is_empty() {
+            probe.format.size = fs::metadata(&*path)?.len().to_string();
+        }
+        save_to_cache(path, probe, &args.signature)?;
+        cached += 1;
+    }
+    flush_cache()?;
+    info!(
+        "Imported {} entries from {} exports: {} cached, {} not found locally",
+        entries.len(),
+        exports.len(),
+        cached,
+        missing
+    );
+
+    if print {
+        let entries: Vec<serde_json::Value> = entries
+            .iter()
+            .map(|(path, probe)| serde_json::json!({"path": path, "probe": probe}))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    }
+    Ok(())
+}
+
+/// Reads the `media` objects of a MediaInfo export. XML exports are turned
+/// into the shape of the JSON ones: `{"@ref": path, "track": [{"@type": ..}]}`.
+fn parse_mediainfo_export(content: &str) -> Result<Vec<serde_json::Value>> {
+    if content.trim_start().starts_with('<') {
+        let document = roxmltree::Document::parse(content)?;
+        return Ok(document
+            .descendants()
+            .filter(|node| node.has_tag_name("media"))
+            .map(|media| {
+                let tracks: Vec<serde_json::Value> = media
+                    .children()
+                    .filter(|node| node.has_tag_name("track"))
+                    .map(|track| {
+                        let mut fields = serde_json::Map::new();
+                        fields.insert(
+                            "@type".to_string(),
+                            serde_json::json!(track.attribute("type").unwrap_or_default()),
+                        );
+                        for field in track.children().filter(|node| node.is_element()) {
+                            if let Some(text) = field.text() {
+                                fields.insert(
+                                    field.tag_name().name().to_string(),
+                                    serde_json::json!(text.trim()),
+                                );
+                            }
+                        }
+                        serde_json::Value::Object(fields)
+                    })
+                    .collect();
+                serde_json::json!({
+                    "@ref": media.attribute("ref").unwrap_or_default(),
+                    "track": tracks,
+                })
+            })
+            .collect());
+    }
+
+    // A single file exports as {"media": {..}}, several as a list of those
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    let documents = match value {
+        serde_json::Value::Array(documents) => documents,
+        document => vec![document],
+    };
+    let mut media = Vec::new();
+    for document in documents {
+        match document.get("media") {
+            Some(serde_json::Value::Array(list)) => media.extend(list.iter().cloned()),
+            Some(single) => media.push(single.clone()),
+            None => return Err(anyhow!("no \"media\" object found")),
+        }
+    }
+    Ok(media)
+}
+
+/// Converts one MediaInfo `media` object into ffprobe's shape, with the
+/// fields the columns use: codecs, resolution, frame rate, pixel format,
+/// color, audio layout, languages and dispositions.
+fn mediainfo_to_probe(media: &serde_json::Value) -> Result<(PathBuf, FFProbeOutput)> {
+    let reference = media
+        .get("@ref")
+        .and_then(|r| r.as_str())
+        .filter(|r| !r.is_empty())
+        .ok_or_else(|| anyhow!("MediaInfo entry without a file path (@ref)"))?;
+    let path = local_path(Path::new(reference));
+    let tracks = match media.get("track") {
+        Some(serde_json::Value::Array(tracks)) => tracks.clone(),
+        Some(track) => vec![track.clone()],
+        None => Vec::new(),
+    };
+    let field = |track: &serde_json::Value, name: &str| -> Option<String> {
+        track
+            .get(name)
+            .and_then(|value| value.as_str())
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+
+    let mut format = serde_json::json!({
+        "filename": path.to_string_lossy(),
+        "imported_from": "mediainfo",
+    });
+    let mut streams = Vec::new();
+    for track in &tracks {
+        let kind = field(track, "@type").unwrap_or_default();
+        if kind == "General" {
+            if let Some(name) = field(track, "Format") {
+                format["format_name"] = serde_json::json!(name.to_lowercase());
+            }
+            if let Some(size) = field(track, "FileSize") {
+                format["size"] = serde_json::json!(size);
+            }
+            if let Some(duration) = field(track, "Duration") {
+                format["duration"] = serde_json::json!(duration);
+            }
+            if let Some(bitrate) = field(track, "OverallBitRate") {
+                format["bit_rate"] = serde_json::json!(bitrate);
+            }
+            let mut tags = serde_json::Map::new();
+            for (name, tag) in [
+                ("Title", "title"),
+                ("Encoded_Application", "encoder"),
+                ("Encoded_Date", "creation_time"),
+            ] {
+                if let Some(value) = field(track, name) {
+                    tags.insert(tag.to_string(), serde_json::json!(value));
+                }
+            }
+            if !tags.is_empty() {
+                format["tags"] = serde_json::Value::Object(tags);
+            }
+            continue;
+        }
+
+        let codec_type = match kind.as_str() {
+            "Video" => "video",
+            "Audio" => "audio",
+            "Text" => "subtitle",
+            _ => continue,
+        };
+        let mut stream = serde_json::json!({
+            "index": streams.len(),
+            "codec_type": codec_type,
+            "codec_name": mediainfo_codec_name(
+                codec_type,
+                &field(track, "Format").unwrap_or_default(),
+                field(track, "Format_Profile").as_deref(),
+                field(track, "BitDepth").as_deref(),
+            ),
+            "disposition": {
+                "default": (field(track, "Default").as_deref() == Some("Yes")) as i32,
+                "forced": (field(track, "Forced").as_deref() == Some("Yes")) as i32,
+            },
+        });
+        for (name, key) in [("BitRate", "bit_rate"), ("Duration", "duration")] {
+            if let Some(value) = field(track, name) {
+                stream[key] = serde_json::json!(value);
+            }
+        }
+        let number = |name: &str| field(track, name).and_then(|v| v.parse::<i64>().ok());
+
+        match codec_type {
+            "video" => {
+                stream["width"] = serde_json::json!(number("Width"));
+                stream["height"] = serde_json::json!(number("Height"));
+                if let Some(profile) = field(track, "Format_Profile") {
+                    // "High@L4.1" -> "High"
+                    let profile = profile.split('@').next().unwrap_or_default();
+                    stream["profile"] = serde_json::json!(profile);
+                }
+                let frame_rate =
+                    match (field(track, "FrameRate_Num"), field(track, "FrameRate_Den")) {
+                        (Some(num), Some(den)) => Some(format!("{}/{}", num, den)),
+                        _ => field(track, "FrameRate")
+                            .and_then(|fps| fps.parse::<f64>().ok())
+                            .map(|fps| format!("{}/1000", (fps * 1000.0).round())),
+                    };
+                stream["r_frame_rate"] = serde_json::json!(frame_rate);
+                let subsampling = match field(track, "ChromaSubsampling").as_deref() {
+                    Some("4:2:2") => "422",
+                    Some("4:4:4") => "444",
+                    _ => "420",
+                };
+                let depth = match field(track, "BitDepth").as_deref() {
+                    Some("10") => "10le",
+                    Some("12") => "12le",
+                    _ => "",
+                };
+                stream["pix_fmt"] = serde_json::json!(format!("yuv{}p{}", subsampling, depth));
+                let color = |name: &str| {
+                    field(track, name).map(|value| match value.as_str() {
+                        "BT.709" => "bt709".to_string(),
+                        "BT.2020" => "bt2020".to_string(),
+                        "BT.2020 non-constant" => "bt2020nc".to_string(),
+                        "BT.601" | "BT.601 NTSC" => "smpte170m".to_string(),
+                        "PQ" => "smpte2084".to_string(),
+                        "HLG" => "arib-std-b67".to_string(),
+                        "Limited" => "tv".to_string(),
+                        "Full" => "pc".to_string(),
+                        other => other.to_lowercase(),
+                    })
+                };
+                stream["color_primaries"] = serde_json::json!(color("colour_primaries"));
+                stream["color_transfer"] = serde_json::json!(color("transfer_characteristics"));
+                stream["color_space"] = serde_json::json!(color("matrix_coefficients"));
+                stream["color_range"] = serde_json::json!(color("colour_range"));
+                // Dolby Vision and HDR10+ are what ffprobe reports as side data
+                let hdr = field(track, "HDR_Format").unwrap_or_default();
+                if hdr.contains("Dolby Vision") {
+                    stream["side_data_list"] =
+                        serde_json::json!([{"side_data_type": "DOVI configuration record"}]);
+                } else if hdr.contains("2094") {
+                    stream["side_data_list"] = serde_json::json!([
+                        {"side_data_type": "HDR Dynamic Metadata SMPTE2094-40 (HDR10+)"}
+                    ]);
+                }
+            }
+            "audio" => {
+                let channels = number("Channels");
+                stream["channels"] = serde_json::json!(channels);
+                stream["channel_layout"] = serde_json::json!(match channels {
+                    Some(1) => Some("mono"),
+                    Some(2) => Some("stereo"),
+                    Some(6) => Some("5.1"),
+                    Some(8) => Some("7.1"),
+                    _ => None,
+                });
+                stream["sample_rate"] = serde_json::json!(field(track, "SamplingRate"));
+                stream["bits_per_raw_sample"] = serde_json::json!(field(track, "BitDepth"));
+            }
+            _ => {}
+        }
+
+        let mut tags = serde_json::Map::new();
+        for (name, tag) in [("Language", "language"), ("Title", "title")] {
+            if let Some(value) = field(track, name) {
+                tags.insert(tag.to_string(), serde_json::json!(value));
+            }
+        }
+        if !tags.is_empty() {
+            stream["tags"] = serde_json::Value::Object(tags);
+        }
+        streams.push(stream);
+    }
+
+    let probe = serde_json::from_value(serde_json::json!({
+        "streams": streams,
+        "format": format,
+    }))?;
+    Ok((path, probe))
+}
+
+/// ffprobe codec names for MediaInfo formats (AVC -> h264, MPEG Audio
+/// Layer 3 -> mp3); unknown formats are lowercased.
+fn mediainfo_codec_name(
+    codec_type: &str,
+    format: &str,
+    profile: Option<&str>,
+    depth: Option<&str>,
+) -> String {
+    let name = match (codec_type, format) {
+        ("video", "AVC") => "h264",
+        ("video", "HEVC") => "hevc",
+        ("video", "AV1") => "av1",
+        ("video", "VP8") => "vp8",
+        ("video", "VP9") => "vp9",
+        ("video", "MPEG Video") => "mpeg2video",
+        ("video", "MPEG-4 Visual") => "mpeg4",
+        ("video", "VC-1") => "vc1",
+        ("video", "ProRes") => "prores",
+        ("audio", "AAC") => "aac",
+        ("audio", "AC-3") => "ac3",
+        ("audio", "E-AC-3") => "eac3",
+        ("audio", "DTS") => "dts",
+        ("audio", "MLP FBA") => "truehd",
+        ("audio", "FLAC") => "flac",
+        ("audio", "ALAC") => "alac",
+        ("audio", "Opus") => "opus",
+        ("audio", "Vorbis") => "vorbis",
+        ("audio", "MPEG Audio") if profile == Some("Layer 2") => "mp2",
+        ("audio", "MPEG Audio") => "mp3",
+        ("audio", "PCM") => return format!("pcm_s{}le", depth.unwrap_or("16")),
+        ("subtitle", "UTF-8") => "subrip",
+        ("subtitle", "ASS") => "ass",
+        ("subtitle", "SSA") => "ssa",
+        ("subtitle", "PGS") => "hdmv_pgs_subtitle",
+        ("subtitle", "VobSub") => "dvd_subtitle",
+        ("subtitle", "Timed Text") => "mov_text",
+        ("subtitle", "WebVTT") => "webvtt",
+        _ => return format.to_lowercase(),
+    };
+    name.to_string()
+}
+
+/// Wall time, files/s, media size covered per second and cache hit rate of
+/// the run, so the effect of tuning --jobs or --timeout is visible. `files`
+/// counts the files actually probed or read from the cache.
+fn print_throughput(summary: &RunSummary, files: usize, probed_bytes: u64, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64().max(0.001);
+    let hit_rate = match files {
+        0 => 0.0,
+        files => summary.cache_hits as f64 * 100.0 / files as f64,
+    };
+    info!(
+        "Done in {}: {} files ({:.1} files/s), {} of media ({}/s), {:.0}% cache hits ({}/{})",
+        format_elapsed(secs),
+        files,
+        files as f64 / secs,
+        format_size(&probed_bytes.to_string()),
+        format_size(&((probed_bytes as f64 / secs) as u64).to_string()),
+        hit_rate,
+        summary.cache_hits,
+        files
+    );
+}
+
+/// Writes the `--summary-json` line, if requested. The target is `stderr`,
+/// `stdout` or a file descriptor number opened by the caller (e.g. `3>summary.json`).
+fn write_summary(args: &Args, mut summary: RunSummary, run_start: Instant) -> Result<()> {
+    let Some(target) = &args.summary_json else {
+        return Ok(());
+    };
+    summary.elapsed_secs = run_start.elapsed().as_secs_f64();
+    let line = serde_json::to_string(&summary)?;
+    match target.as_str() {
+        "stderr" | "2" => eprintln!("{}", line),
+        "stdout" | "1" => println!("{}", line),
+        fd => {
+            let fd: u32 = fd.parse().map_err(|_| {
+                anyhow!(
+                    "Invalid --summary-json target: {} (expected stderr, stdout or a file descriptor)",
+                    fd
+                )
+            })?;
+            let path = format!("/dev/fd/{}", fd);
+            let mut file = fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .map_err(|e| anyhow!("Failed to open {}: {}", path, e))?;
+            writeln!(file, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+fn open_session_log(path: &Path) -> Result<()> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| anyhow!("Failed to open log file {}: {}", path.display(), e))?;
+    *SESSION_LOG.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Appends one JSON line to the session log, if one was requested.
+/// Logging failures are reported but never abort the scan.
+fn log_event(event: &str, data: serde_json::Value) {
+    let mut log_guard = SESSION_LOG.lock().unwrap();
+    if let Some(file) = &mut *log_guard {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        let mut record = serde_json::json!({ "timestamp": timestamp, "event": event });
+        if let (Some(record), serde_json::Value::Object(data)) = (record.as_object_mut(), data) {
+            record.extend(data);
+        }
+        if let Err(e) = writeln!(file, "{}", record) {
+            warn!("Failed to write log file: {}", e);
+        }
+    }
+}
+
+fn table_format() -> format::TableFormat {
+    if PLAIN.load(Ordering::Relaxed) {
+        return format::FormatBuilder::new()
+            .column_separator(' ')
+            .padding(0, 1)
+            .build();
+    }
+    format::FormatBuilder::new()
+        .column_separator('│')
+        .borders('│')
+        .separator(
+            format::LinePosition::Top,
+            format::LineSeparator::new('─', '┬', '┌', '┐'),
+        )
+        .separator(
+            format::LinePosition::Bottom,
+            format::LineSeparator::new('─', '┴', '└', '┘'),
+        )
+        .separator(
+            format::LinePosition::Title,
+            format::LineSeparator::new('─', '┼', '├', '┤'),
+        )
+        .padding(1, 1)
+        .build()
+}
+
+/// Writes a report table to `out`, with the bold headers `printstd` would
+/// draw when it ends up on a terminal.
+fn write_table(table: &Table, out: &mut dyn Write) -> Result<()> {
+    if STYLED_OUTPUT.load(Ordering::Relaxed) {
+        let mut buffer = Vec::new();
+        match term::terminfo::TerminfoTerminal::new(&mut buffer) {
+            Some(mut terminal) => table.print_term(&mut terminal)?,
+            None => table.print(&mut buffer)?,
+        };
+        out.write_all(&buffer)?;
+    } else {
+        table.print(out)?;
+    }
+    Ok(())
+}
+
+/// Cache validity key for a file. `mtime` (size + modification time) is the
+/// default; `size` suits filesystems with unreliable mtimes, and `hash`
+/// (size + hash of the first and last 64 KiB) survives copies between machines.
+fn get_file_signature(path: &Path, mode: &str) -> Result<String> {
+    let metadata = fs::metadata(path)?;
+    let size = metadata.len();
+    match mode {
+        "size" => Ok(size.to_string()),
+        "hash" => {
+            const CHUNK: u64 = 64 * 1024;
+            let mut file = fs::File::open(path)?;
+            let mut hasher = XxHash64::default();
+            let mut buffer = Vec::new();
+            (&mut file).take(CHUNK).read_to_end(&mut buffer)?;
+            if size > CHUNK {
+                file.seek(SeekFrom::Start(size.saturating_sub(CHUNK).max(CHUNK)))?;
+                file.read_to_end(&mut buffer)?;
+            }
+            hasher.write(&buffer);
+            Ok(format!("{}-xxh{:016x}", size, hasher.finish()))
+        }
+        _ => {
+            let modified = metadata
+                .modified()?
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs();
+            Ok(format!("{}-{}", size, modified))
+        }
+    }
+}
+
+/// `--cache-dir`, else `~/.cache/mediainfo` (or under `$XDG_CACHE_HOME`).
+/// The older `~/.mediainfo/cache` is still used while the new one doesn't exist.
+fn get_cache_dir() -> Result<PathBuf> {
+    let cache_dir = match &*CACHE_DIR.lock().unwrap() {
+        Some(dir) => dir.clone(),
+        None => {
+            let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not find home directory"))?;
+            let cache_home = std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .filter(|dir| dir.is_absolute())
+                .unwrap_or_else(|| home.join(".cache"));
+            let cache_dir = cache_home.join("mediainfo");
+            let legacy_dir = home.join(".mediainfo").join("cache");
+            match !cache_dir.exists() && legacy_dir.exists() {
+                true => legacy_dir,
+                false => cache_dir,
+            }
+        }
+    };
+    fs::create_dir_all(&cache_dir)?;
+    Ok(cache_dir)
+}
+
+fn load_cache() -> Result<Cache> {
+    let cache_path = get_cache_file()?;
+    debug!("Reading cache from: {}", cache_path.display());
+    let mut cache = if cache_path.exists() {
+        debug!(
+            "Cache file size: {} bytes",
+            fs::metadata(&cache_path)?.len()
+        );
+        read_cache_file(&cache_path).unwrap_or_else(|e| {
+            warn!("Failed to parse cache: {}", e);
+            Cache::default()
+        })
+    } else {
+        debug!("Cache file does not exist");
+        Cache::default()
+    };
+    replay_cache_journal(&mut cache)?;
+    Ok(cache)
+}
+
+/// Parses a cache file straight from disk instead of reading it into a
+/// String first, so a multi-GB cache isn't held in memory twice.
+fn read_cache_file<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let reader = std::io::BufReader::new(fs::File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Writes the whole cache, replacing the file atomically, and drops the
+/// journal whose entries it now contains.
+fn save_cache(cache: &Cache) -> Result<()> {
+    let cache_path = get_cache_file()?;
+    let temp_path = cache_path.with_extension("json.tmp");
+    let mut writer = std::io::BufWriter::new(fs::File::create(&temp_path)?);
+    serde_json::to_writer_pretty(&mut writer, cache)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&temp_path, &cache_path)?;
+
+    let journal_path = get_cache_journal_file()?;
+    if journal_path.exists() {
+        fs::remove_file(journal_path)?;
+    }
+    Ok(())
+}
+
+/// Name of the cache file kept inside each scanned root with `--cache-mode local`
+const LOCAL_CACHE_FILE: &str = ".mediainfo-cache.json";
+
+/// Loads the cache stored inside a scan root (`--cache-mode local`). A root
+/// that is a single file keeps its cache in the file's directory.
+fn open_local_cache(root: &Path) -> Result<()> {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let dir = match root.is_file() {
+        true => root.parent().map(Path::to_path_buf).unwrap_or(root),
+        false => root,
+    };
+    let cache_path = dir.join(LOCAL_CACHE_FILE);
+    let cache = match cache_path.exists() {
+        true => read_cache_file(&cache_path).unwrap_or_else(|e| {
+            warn!("Failed to parse cache {}: {}", cache_path.display(), e);
+            Cache::default()
+        }),
+        false => Cache::default(),
+    };
+    LOCAL_CACHES.lock().unwrap().insert(dir, cache);
+    Ok(())
+}
+
+/// The directory holding an existing `.mediainfo-cache.json` for `root`: the
+/// root itself (its directory for a single file) or the nearest parent.
+fn find_local_cache(root: &Path) -> Option<PathBuf> {
+    let root = root.canonicalize().ok()?;
+    let dir = match root.is_file() {
+        true => root.parent()?,
+        false => &root,
+    };
+    dir.ancestors()
+        .find(|dir| dir.join(LOCAL_CACHE_FILE).is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Runs `f` on the local cache of the root `file` lives under, with the
+/// file's path relative to that root as the key. None when `file` isn't
+/// under a root with a local cache.
+fn with_local_cache<T>(file: &Path, f: impl FnOnce(&mut Cache, &str) -> T) -> Option<T> {
+    let mut caches = LOCAL_CACHES.lock().unwrap();
+    if caches.is_empty() {
+        return None;
+    }
+    let canonical = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let (root, cache) = caches
+        .iter_mut()
+        .filter(|(root, _)| canonical.starts_with(root))
+        .max_by_key(|(root, _)| root.as_os_str().len())?;
+    let key = canonical.strip_prefix(root).ok()?.to_str()?.to_string();
+    Some(f(cache, &key))
+}
+
+/// Writes back local caches that changed. A read-only drive only gets a
+/// warning; its results are still shown.
+fn flush_local_caches() {
+    let mut caches = LOCAL_CACHES.lock().unwrap();
+    for (root, cache) in caches.iter_mut().filter(|(_, cache)| cache.dirty) {
+        let cache_path = root.join(LOCAL_CACHE_FILE);
+        let temp_path = cache_path.with_extension("json.tmp");
+        let written = serde_json::to_vec(cache)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok(fs::write(&temp_path, content)?))
+            .and_then(|_| Ok(fs::rename(&temp_path, &cache_path)?));
+        match written {
+            Ok(()) => cache.dirty = false,
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                warn!("Could not write {}: {}", cache_path.display(), e);
+            }
+        }
+    }
+}
+
+fn get_cache_file() -> Result<PathBuf> {
+    let cache_dir = get_cache_dir()?;
+    Ok(cache_dir.join("cache.json"))
+}
+
+/// Append-only log of entries probed since the cache was last written. New
+/// probes are appended here instead of rewriting the whole cache each time;
+/// `flush_cache` folds them into `cache.json` at the end of the run.
+fn get_cache_journal_file() -> Result<PathBuf> {
+    let cache_dir = get_cache_dir()?;
+    Ok(cache_dir.join("cache.journal"))
+}
+
+#[derive(Deserialize, Serialize)]
+struct JournalRecord {
+    path: String,
+    entry: CacheEntry,
+}
+
+/// Applies journaled entries left by an earlier run that didn't get to flush
+/// (interrupted or crashed). A truncated last line is ignored.
+fn replay_cache_journal(cache: &mut Cache) -> Result<()> {
+    for record in read_cache_journal()? {
+        cache.entries.insert(record.path, record.entry);
+        cache.dirty = true;
+    }
+    Ok(())
+}
+
+/// Entries of the cache journal, read line by line.
+fn read_cache_journal() -> Result<Vec<JournalRecord>> {
+    let journal_path = get_cache_journal_file()?;
+    if !journal_path.exists() {
+        return Ok(Vec::new());
+    }
+    let reader = std::io::BufReader::new(fs::File::open(&journal_path)?);
+    Ok(reader
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| serde_json::from_str::<JournalRecord>(&line).ok())
+        .collect())
+}
+
+fn append_to_cache_journal(record: &JournalRecord) -> Result<()> {
+    let mut journal = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(get_cache_journal_file()?)?;
+    writeln!(journal, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+/// Cache key for a file: its canonical path, with local mount points mapped
+/// back to the prefixes used in the cache (see `--map`).
+fn cache_key(file: &Path) -> Result<String> {
+    let canonical = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let maps = PATH_MAPS.lock().unwrap();
+    let key = maps
+        .iter()
+        .find_map(|(cached, local)| Some(cached.join(canonical.strip_prefix(local).ok()?)))
+        .unwrap_or(canonical);
+    key.to_str()
+        .map(String::from)
+        .ok_or_else(|| anyhow!("Invalid file path"))
+}
+
+/// Short stable identifier of a file for scripts: a hash of its canonical
+/// path, so it is the same in every run as long as the file stays put.
+fn file_id(file: &Path) -> String {
+    let canonical = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let mut hasher = XxHash64::default();
+    hasher.write(canonical.to_string_lossy().as_bytes());
+    format!("{:016x}", hasher.finish())[..12].to_string()
+}
+
+/// Prints the cached files with the given IDs (or ID prefixes) as a JSON
+/// result set, or just their paths.
+fn show_files(ids: &[String], paths_only: bool) -> Result<()> {
+    let entries: Vec<(String, PathBuf, FFProbeOutput)> = get_cached_files()?
+        .into_iter()
+        .map(|(path, probe)| (file_id(&path), path, probe))
+        .collect();
+    let mut found = Vec::new();
+    for id in ids {
+        let id = id.to_lowercase();
+        let matches: Vec<&(String, PathBuf, FFProbeOutput)> = entries
+            .iter()
+            .filter(|(file_id, _, _)| file_id.starts_with(&id))
+            .collect();
+        match matches.as_slice() {
+            [entry] => found.push(*entry),
+            [] => return Err(anyhow!("No cached file with ID {}", id)),
+            _ => {
+                return Err(anyhow!(
+                    "ID {} is ambiguous ({} files match), use more characters",
+                    id,
+                    matches.len()
+                ))
+            }
+        }
+    }
+
+    if paths_only {
+        for (_, path, _) in found {
+            println!("{}", path.display());
+        }
+        return Ok(());
+    }
+    let entries: Vec<serde_json::Value> = found
+        .iter()
+        .map(|(id, path, probe)| serde_json::json!({"id": id, "path": path, "probe": probe}))
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&entries)?);
+    Ok(())
+}
+
+/// User tags per file, keyed like the cache
+type Tags = HashMap<String, Vec<String>>;
+
+fn get_tags_file() -> Result<PathBuf> {
+    Ok(get_cache_dir()?.join("tags.json"))
+}
+
+fn load_tags() -> Result<Tags> {
+    let tags_path = get_tags_file()?;
+    match tags_path.exists() {
+        true => read_cache_file(&tags_path),
+        false => Ok(HashMap::new()),
+    }
+}
+
+fn save_tags(tags: &Tags) -> Result<()> {
+    let tags_path = get_tags_file()?;
+    let temp_path = tags_path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_vec_pretty(tags)?)?;
+    fs::rename(&temp_path, &tags_path)?;
+    Ok(())
+}
+
+/// The user's tags for `file`. Tags are kept apart from the cache entries,
+/// so re-probing or clearing the cache doesn't lose them.
+fn file_tags(file: &Path) -> Vec<String> {
+    let mut tags = TAGS.lock().unwrap();
+    let tags = tags.get_or_insert_with(|| {
+        load_tags().unwrap_or_else(|e| {
+            warn!("Failed to read tags: {}", e);
+            HashMap::new()
+        })
+    });
+    cache_key(file)
+        .ok()
+        .and_then(|key| tags.get(&key).cloned())
+        .unwrap_or_default()
+}
+
+/// `mediainfo tag`: adds `tag` to each file, or removes it with `--remove`.
+fn tag_files(files: &[PathBuf], tag: &str, remove: bool) -> Result<()> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() || tag.contains(|c: char| c == ',' || c.is_whitespace()) {
+        return Err(anyhow!(
+            "Invalid tag '{}': use a single word such as keep, delete or re-encode",
+            tag
+        ));
+    }
+    let mut tags = load_tags()?;
+    for file in files {
+        if !file.is_file() {
+            return Err(anyhow!("{} is not a file", file.display()));
+        }
+        let key = cache_key(file)?;
+        let file_tags = tags.entry(key.clone()).or_default();
+        match remove {
+            true => file_tags.retain(|t| *t != tag),
+            false if !file_tags.contains(&tag) => file_tags.push(tag.clone()),
+            false => {}
+        }
+        if file_tags.is_empty() {
+            tags.remove(&key);
+        }
+    }
+
+    save_tags(&tags)?;
+    match remove {
+        true => info!("Removed tag '{}' from {} files", tag, files.len()),
+        false => info!("Tagged {} files as '{}'", files.len(), tag),
+    }
+    Ok(())
+}
+
+/// `mediainfo apply`: runs `command` on each file tagged `tag`, one at a
+/// time, in path order. Files that are gone afterwards (deleted, trashed,
+/// moved) lose their tags; with `untag`, so do the others it succeeded on.
+fn apply_tag(
+    paths: &[PathBuf],
+    tag: &str,
+    command: &str,
+    dry_run: bool,
+    untag: bool,
+) -> Result<()> {
+    let tag = tag.trim().to_lowercase();
+    let mut tags = load_tags()?;
+    let roots: Vec<PathBuf> = paths
+        .iter()
+        .map(|path| path.canonicalize().unwrap_or_else(|_| path.clone()))
+        .collect();
+    let mut keys: Vec<String> = tags
+        .iter()
+        .filter(|(_, file_tags)| file_tags.contains(&tag))
+        .map(|(key, _)| key.clone())
+        .filter(|key| roots.is_empty() || roots.iter().any(|root| Path::new(key).starts_with(root)))
+        .collect();
+    keys.sort();
+    if keys.is_empty() {
+        warn!("No files are tagged '{}'", tag);
+        return Ok(());
+    }
+    if dry_run {
+        for key in &keys {
+            println!("{}", local_path(Path::new(key)).display());
+        }
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for key in &keys {
+        let file = local_path(Path::new(key));
+        if !file.exists() {
+            warn!("{} no longer exists, dropping its tags", file.display());
+            tags.remove(key);
+            continue;
+        }
+        match run_tag_command(command, &file, &tags[key]) {
+            Ok(()) if !file.exists() => {
+                tags.remove(key);
+            }
+            Ok(()) if untag => {
+                let file_tags = tags.entry(key.clone()).or_default();
+                file_tags.retain(|t| *t != tag);
+                if file_tags.is_empty() {
+                    tags.remove(key);
+                }
+            }
+            Ok(()) => {}
+            Err(e) => {
+                error!("{}: {}", file.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    save_tags(&tags)?;
+    info!(
+        "Ran {} on {} files tagged '{}' ({} failed)",
+        command,
+        keys.len(),
+        tag,
+        failed
+    );
+    if failed > 0 {
+        return Err(anyhow!("{} of {} commands failed", failed, keys.len()));
+    }
+    Ok(())
+}
+
+/// Runs an `apply --exec` command for one file. The path is passed as a
+/// shell argument rather than pasted into the command, so names with spaces
+/// or quotes are safe; it goes where `{}` is, or at the end.
+fn run_tag_command(command: &str, file: &Path, file_tags: &[String]) -> Result<()> {
+    let mut shell = if cfg!(windows) {
+        // cmd has no positional arguments; the path is read from MEDIAINFO_PATH
+        // with delayed expansion, which happens after the command line is
+        // parsed, so characters like & or % in a file name stay literal
+        let command = match command.contains("{}") {
+            true => command.replace("{}", "\"!MEDIAINFO_PATH!\""),
+            false => format!("{} \"!MEDIAINFO_PATH!\"", command),
+        };
+        let mut shell = Command::new("cmd");
+        shell.arg("/V:ON").arg("/C").arg(command);
+        shell
+    } else {
+        let command = match command.contains("{}") {
+            true => command.replace("{}", "\"$1\""),
+            false => format!("{} \"$1\"", command),
+        };
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command).arg("mediainfo").arg(file);
+        shell
+    };
+    let status = shell
+        .env("MEDIAINFO_PATH", file)
+        .env("MEDIAINFO_ID", file_id(file))
+        .env("MEDIAINFO_TAGS", file_tags.join(","))
+        .stdin(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", command, status));
+    }
+    Ok(())
+}
+
+/// Inverse of `cache_key`: where a cached (or exported) path lives on this machine.
+fn local_path(path: &Path) -> PathBuf {
+    let maps = PATH_MAPS.lock().unwrap();
+    maps.iter()
+        .find_map(|(cached, local)| Some(local.join(path.strip_prefix(cached).ok()?)))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+fn parse_max_runtime(value: &str) -> Result<Duration, String> {
+    parse_human_duration(value)
+        .filter(|secs| *secs > 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("expected a duration like 90, 10m or 1h30m, got '{}'", value))
+}
+
+fn parse_path_map(value: &str) -> Result<(PathBuf, PathBuf), String> {
+    match value.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((PathBuf::from(from), PathBuf::from(to)))
+        }
+        _ => Err(format!("expected FROM=TO, got '{}'", value)),
+    }
+}
+
+fn get_cached_probe(file: &Path, signature_mode: &str) -> Result<Option<FFProbeOutput>> {
+    if let Some(result) = with_local_cache(file, |cache, key| {
+        cache_lookup(cache, key, file, signature_mode, false)
+    }) {
+        return result;
+    }
+    let path_str = &cache_key(file)?;
+
+    if let Some(conn) = &*SQLITE_CACHE.lock().unwrap() {
+        let Some(entry) = sqlite_get_entry(conn, path_str)? else {
+            return Ok(None);
+        };
+        if entry.signature != get_file_signature(file, signature_mode)? {
+            return Ok(None);
+        }
+        let now = now_secs();
+        conn.execute(
+            "UPDATE entries SET last_seen = ?1 WHERE path = ?2 AND (last_seen IS NULL OR last_seen < ?3)",
+            rusqlite::params![now, path_str, now - LAST_SEEN_RESOLUTION_SECS],
+        )?;
+        return Ok(Some(entry.probe_data));
+    }
+
+    // Only load cache if it hasn't been loaded yet
+    let mut cache_guard = CACHE.lock().unwrap();
+    if cache_guard.is_none() {
+        // Load cache silently without progress indicators
+        let cache_path = get_cache_file()?;
+        let mut cache = if cache_path.exists() {
+            read_cache_file(&cache_path).unwrap_or_default()
+        } else {
+            Cache::default()
+        };
+        replay_cache_journal(&mut cache)?;
+        *cache_guard = Some(cache);
+    }
+
+    match &mut *cache_guard {
+        Some(cache) => cache_lookup(cache, path_str, file, signature_mode, true),
+        None => Ok(None),
+    }
+}
+
+/// The cached probe under `key` if the file's signature still matches. An
+/// entry last seen over a day ago is marked as seen by this scan, through
+/// the journal with `journal` (the central cache), otherwise by marking the
+/// cache for rewriting.
+fn cache_lookup(
+    cache: &mut Cache,
+    key: &str,
+    file: &Path,
+    signature_mode: &str,
+    journal: bool,
+) -> Result<Option<FFProbeOutput>> {
+    let Some(entry) = cache.entries.get_mut(key) else {
+        return Ok(None);
+    };
+    if get_file_signature(file, signature_mode)? != entry.signature {
+        return Ok(None);
+    }
+    let now = now_secs();
+    if entry
+        .last_seen
+        .is_some_and(|seen| now - seen < LAST_SEEN_RESOLUTION_SECS)
+    {
+        return Ok(Some(entry.probe_data.clone()));
+    }
+    entry.last_seen = Some(now);
+    if journal {
+        append_to_cache_journal(&JournalRecord {
+            path: key.to_string(),
+            entry: entry.clone(),
+        })?;
+    } else {
+        cache.dirty = true;
+    }
+    Ok(Some(entry.probe_data.clone()))
+}
+
+/// A fresh entry for `key`, keeping the first_seen time of the entry it replaces.
+fn new_cache_entry(
+    cache: &Cache,
+    key: &str,
+    file: &Path,
+    probe_data: &FFProbeOutput,
+    signature_mode: &str,
+) -> Result<CacheEntry> {
+    let now = now_secs();
+    Ok(CacheEntry {
+        signature: get_file_signature(file, signature_mode)?,
+        probe_data: probe_data.clone(),
+        first_seen: cache
+            .entries
+            .get(key)
+            .and_then(|e| e.first_seen)
+            .or(Some(now)),
+        last_seen: Some(now),
+        probed_at: Some(now),
+    })
+}
+
+fn save_to_cache(file: &Path, probe_data: &FFProbeOutput, signature_mode: &str) -> Result<()> {
+    if let Some(result) = with_local_cache(file, |cache, key| {
+        let entry = new_cache_entry(cache, key, file, probe_data, signature_mode)?;
+        cache.entries.insert(key.to_string(), entry);
+        cache.dirty = true;
+        Ok(())
+    }) {
+        return result;
+    }
+    let path_str = &cache_key(file)?;
+
+    if let Some(conn) = &*SQLITE_CACHE.lock().unwrap() {
+        let now = now_secs();
+        let entry = CacheEntry {
+            signature: get_file_signature(file, signature_mode)?,
+            probe_data: probe_data.clone(),
+            first_seen: Some(now),
+            last_seen: Some(now),
+            probed_at: Some(now),
+        };
+        return sqlite_put_entry(conn, path_str, &entry);
+    }
+
+    let mut cache_guard = CACHE.lock().unwrap();
+    if cache_guard.is_none() {
+        *cache_guard = Some(load_cache()?);
+    }
+
+    if let Some(cache) = &mut *cache_guard {
+        let record = JournalRecord {
+            path: path_str.to_string(),
+            entry: new_cache_entry(cache, path_str, file, probe_data, signature_mode)?,
+        };
+        append_to_cache_journal(&record)?;
+        cache.entries.insert(record.path, record.entry);
+        cache.dirty = true;
+    }
+
+    Ok(())
+}
+
+/// Writes back cache changes that were deferred during the scan.
+fn flush_cache() -> Result<()> {
+    flush_local_caches();
+    let mut cache_guard = CACHE.lock().unwrap();
+    if let Some(cache) = &mut *cache_guard {
+        if cache.dirty {
+            save_cache(cache)?;
+            cache.dirty = false;
+        }
+    }
+    Ok(())
+}
+
+fn get_cache_db_file() -> Result<PathBuf> {
+    let cache_dir = get_cache_dir()?;
+    Ok(cache_dir.join("cache.db"))
+}
+
+/// Opens the SQLite cache, creating it on first use and importing any
+/// existing JSON cache. WAL mode lets several scans write at the same time.
+fn open_sqlite_cache() -> Result<()> {
+    let db_path = get_cache_db_file()?;
+    let conn = rusqlite::Connection::open(&db_path)
+        .map_err(|e| anyhow!("Failed to open cache database {}: {}", db_path.display(), e))?;
+    conn.busy_timeout(Duration::from_secs(30))?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS entries (
+            path TEXT PRIMARY KEY,
+            signature TEXT NOT NULL,
+            probe_hash TEXT NOT NULL,
+            probe_data TEXT NOT NULL,
+            first_seen INTEGER,
+            last_seen INTEGER,
+            probed_at INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS entries_signature ON entries (signature);
+        CREATE INDEX IF NOT EXISTS entries_probe_hash ON entries (probe_hash);",
+    )?;
+    // Databases created before probed_at was tracked
+    let has_probed_at = conn
+        .prepare("SELECT probed_at FROM entries LIMIT 0")
+        .is_ok();
+    if !has_probed_at {
+        conn.execute("ALTER TABLE entries ADD COLUMN probed_at INTEGER", [])?;
+    }
+
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM entries", [], |row| row.get(0))?;
+    if count == 0 && get_cache_file()?.exists() {
+        let cache = load_cache()?;
+        let transaction = conn.unchecked_transaction()?;
+        for (path, entry) in &cache.entries {
+            sqlite_put_entry(&transaction, path, entry)?;
+        }
+        transaction.commit()?;
+        info!(
+            "Imported {} entries from the JSON cache into {}",
+            cache.entries.len(),
+            db_path.display()
+        );
+    }
+
+    *SQLITE_CACHE.lock().unwrap() = Some(conn);
+    Ok(())
+}
+
+fn sqlite_get_entry(conn: &rusqlite::Connection, path: &str) -> Result<Option<CacheEntry>> {
+    let row = conn
+        .query_row(
+            "SELECT signature, probe_data, first_seen, last_seen, probed_at FROM entries WHERE path = ?1",
+            [path],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((signature, probe_data, first_seen, last_seen, probed_at)) = row else {
+        return Ok(None);
+    };
+    Ok(Some(CacheEntry {
+        signature,
+        probe_data: serde_json::from_str(&probe_data)?,
+        first_seen,
+        last_seen,
+        probed_at,
+    }))
+}
+
+/// Inserts or updates an entry, keeping the original first_seen.
+fn sqlite_put_entry(conn: &rusqlite::Connection, path: &str, entry: &CacheEntry) -> Result<()> {
+    conn.execute(
+        "INSERT INTO entries (path, signature, probe_hash, probe_data, first_seen, last_seen, probed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (path) DO UPDATE SET
+            signature = excluded.signature,
+            probe_hash = excluded.probe_hash,
+            probe_data = excluded.probe_data,
+            last_seen = excluded.last_seen,
+            probed_at = excluded.probed_at",
+        rusqlite::params![
+            path,
+            entry.signature,
+            probe_fingerprint(&entry.probe_data)?,
+            serde_json::to_string(&entry.probe_data)?,
+            entry.first_seen,
+            entry.last_seen,
+            entry.probed_at,
+        ],
+    )?;
+    Ok(())
+}
+
+/// First/last seen timestamps of a cached file. Cache keys are canonical
+/// paths, but --cached rows may point at files that no longer exist.
+fn cache_seen_times(path: &Path) -> (Option<i64>, Option<i64>) {
+    with_cache_entry(path, |entry| (entry.first_seen, entry.last_seen)).unwrap_or((None, None))
+}
+
+/// Looks up the cache entry of a file without loading the cache.
+fn with_cache_entry<T>(path: &Path, f: impl FnOnce(&CacheEntry) -> T) -> Option<T> {
+    if let Some(entry) = with_local_cache(path, |cache, key| cache.entries.get(key).cloned()) {
+        return entry.as_ref().map(f);
+    }
+    let key = cache_key(path).ok()?;
+    if let Some(conn) = &*SQLITE_CACHE.lock().unwrap() {
+        return sqlite_get_entry(conn, &key).ok().flatten().as_ref().map(f);
+    }
+    let cache_guard = CACHE.lock().unwrap();
+    cache_guard.as_ref()?.entries.get(&key).map(f)
+}
+
+/// The last cached probe of a file, whether or not its signature still matches.
+fn cached_probe_data(file: &Path) -> Result<Option<FFProbeOutput>> {
+    if let Some(probe) = with_local_cache(file, |cache, key| {
+        cache.entries.get(key).map(|entry| entry.probe_data.clone())
+    }) {
+        return Ok(probe);
+    }
+    if SQLITE_CACHE.lock().unwrap().is_none() {
+        let mut cache_guard = CACHE.lock().unwrap();
+        if cache_guard.is_none() {
+            *cache_guard = Some(load_cache()?);
+        }
+    }
+    Ok(with_cache_entry(file, |entry| entry.probe_data.clone()))
+}
+
+/// Columns compared when a refresh re-probes a cached file.
+const CHANGE_COLUMNS: [&str; 15] = [
+    "duration",
+    "size",
+    "bitrate",
+    "resolution",
+    "fps",
+    "format",
+    "profile",
+    "depth",
+    "color",
+    "audio",
+    "audio_codec",
+    "sample_rate",
+    "channels",
+    "audio_streams",
+    "subtitles",
+];
+
+/// Reports the displayed values that differ between the cached probe and a
+/// fresh one, so `--refresh` doubles as change detection (e.g. a repaired
+/// file whose duration changed).
+fn report_probe_changes(
+    file: &Path,
+    previous: &FFProbeOutput,
+    current: &FFProbeOutput,
+) -> Result<()> {
+    let options = DisplayOptions {
+        columns: CHANGE_COLUMNS.to_vec(),
+        dates: "iso".to_string(),
+        reference: None,
+    };
+    let before = format_probe_output(file, previous, &options)?;
+    let after = format_probe_output(file, current, &options)?;
+    let changes: Vec<(&str, &str, &str)> = CHANGE_COLUMNS
+        .iter()
+        .filter_map(|column| {
+            let old = before.get(column).map_or("", String::as_str);
+            let new = after.get(column).map_or("", String::as_str);
+            (old != new).then_some((*column, old, new))
+        })
+        .collect();
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    info!(
+        "Changed since last probe: {}: {}",
+        file.display(),
+        changes
+            .iter()
+            .map(|(column, old, new)| {
+                let shown = |value: &str| match value {
+                    "" => "none".to_string(),
+                    value => value.to_string(),
+                };
+                format!("{} {} -> {}", column, shown(old), shown(new))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    log_event(
+        "changed",
+        serde_json::json!({
+            "path": file,
+            "changes": changes
+                .iter()
+                .map(|(column, old, new)| (column.to_string(), serde_json::json!({"old": old, "new": new})))
+                .collect::<serde_json::Map<_, _>>(),
+        }),
+    );
+    Ok(())
+}
+
+/// Cache column value: whether the row was served from the cache and how
+/// old that data is, or "miss" when ffprobe ran during this scan.
+fn cache_status(path: &Path, hit: bool) -> String {
+    if !hit {
+        return "miss".to_string();
+    }
+    match with_cache_entry(path, |entry| entry.probed_at).flatten() {
+        Some(probed_at) => format!("hit ({})", format_date(Some(probed_at), "relative")),
+        None => "hit".to_string(),
+    }
+}
+
+fn format_duration(duration: &str) -> String {
+    if let Ok(secs) = duration.parse::<f64>() {
+        let hours = (secs / 3600.0).floor();
+        let minutes = ((secs % 3600.0) / 60.0).floor();
+        let seconds = secs % 60.0;
+
+        if hours > 0.0 {
+            format!(
+                "{:02}:{:02}:{:02}",
+                hours as u32, minutes as u32, seconds as u32
+            )
+        } else {
+            format!("{:02}:{:02}", minutes as u32, seconds as u32)
+        }
+    } else {
+        String::new()
+    }
+}
+
+fn format_size(size: &str) -> String {
+    if let Ok(bytes) = size.parse::<u64>() {
+        const KB: u64 = 1024;
+        const MB: u64 = KB * 1024;
+        const GB: u64 = MB * 1024;
+
+        if bytes >= GB {
+            format!("{:.2} GB", bytes as f64 / GB as f64)
+        } else if bytes >= MB {
+            format!("{:.2} MB", bytes as f64 / MB as f64)
+        } else if bytes >= KB {
+            format!("{:.2} KB", bytes as f64 / KB as f64)
+        } else {
+            format!("{} B", bytes)
+        }
+    } else {
+        String::new()
+    }
+}
+
+/// How worthwhile re-encoding a file is, from 0 to 100: mostly how dated
+/// the video codec is, then how many bits it spends per pixel, then how
+/// much space there is to win. MPEG-2 and Xvid remuxes of a few GB float to
+/// the top; lean AV1 encodes score near zero. None for files without video.
+fn reencode_priority(probe: &FFProbeOutput) -> Option<f64> {
+    let video = primary_video(probe)?;
+    let codec = match video.codec_name.as_deref().unwrap_or_default() {
+        "mpeg1video" | "mpeg2video" | "mpeg4" | "msmpeg4v2" | "msmpeg4v3" | "h263" | "wmv1"
+        | "wmv2" | "wmv3" | "vc1" | "rv30" | "rv40" | "theora" | "mjpeg" => 1.0,
+        "h264" | "vp8" => 0.6,
+        "hevc" | "vp9" => 0.15,
+        "av1" => 0.0,
+        _ => 0.5,
+    };
+    // Around 0.1 bits per pixel is a good modern encode; 0.3 and up is waste
+    let bpp = bits_per_pixel(probe).map_or(0.0, |bpp| (bpp / 0.3).min(1.0));
+    let size_gb = probe.format.size.parse::<f64>().unwrap_or(0.0) / (1u64 << 30) as f64;
+    let size = (size_gb.ln_1p() / 20f64.ln_1p()).min(1.0);
+    Some(100.0 * (0.45 * codec + 0.3 * bpp + 0.25 * size))
+}
+
+/// Video bits spent per pixel per frame: the stream bitrate (or the
+/// container's, when the stream has none) over width x height x fps.
+fn bits_per_pixel(probe: &FFProbeOutput) -> Option<f64> {
+    let video = primary_video(probe)?;
+    let bitrate = bpp_bitrate(probe)?;
+    let (num, den) = video.r_frame_rate.as_deref()?.split_once('/')?;
+    let fps = num.parse::<f64>().ok()? / den.parse::<f64>().ok()?;
+    let pixels = video.width? as f64 * video.height? as f64;
+    (fps.is_finite() && fps > 0.0 && pixels > 0.0).then(|| bitrate / (pixels * fps))
+}
+
+/// Display aspect ratio of a video in lowest terms, e.g. (16, 9): ffprobe's
+/// `display_aspect_ratio`, or else the frame size scaled by the sample aspect
+/// ratio. Swapped for videos a player rotates by 90 degrees, such as
+/// portrait phone recordings stored as landscape frames.
+fn display_aspect(video: &Stream) -> Option<(u64, u64)> {
+    let ratio = |text: &str| -> Option<(u64, u64)> {
+        let (w, h) = text.split_once(':')?;
+        let (w, h) = (w.parse::<u64>().ok()?, h.parse::<u64>().ok()?);
+        (w > 0 && h > 0).then_some((w, h))
+    };
+    let (width, height) = (video.width? as u64, video.height? as u64);
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let (w, h) = match video.display_aspect_ratio.as_deref().and_then(ratio) {
+        Some(dar) => dar,
+        None => {
+            let (sar_w, sar_h) = video
+                .extra
+                .get("sample_aspect_ratio")
+                .and_then(|v| v.as_str())
+                .and_then(ratio)
+                .unwrap_or((1, 1));
+            (width * sar_w, height * sar_h)
+        }
+    };
+    let divisor = gcd(w, h);
+    let (w, h) = (w / divisor, h / divisor);
+    Some(match video_rotation(video) {
+        90 | 270 => (h, w),
+        _ => (w, h),
+    })
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Rotation in degrees (0-359) from the display matrix side data, or the
+/// `rotate` tag older ffprobe versions report.
+fn video_rotation(video: &Stream) -> i64 {
+    let side_data = video
+        .side_data_list
+        .iter()
+        .flatten()
+        .find_map(|data| data.get("rotation")?.as_f64());
+    let tag = || video.tags.as_ref()?.get("rotate")?.parse::<f64>().ok();
+    side_data
+        .or_else(tag)
+        .map_or(0, |degrees| (degrees.round() as i64).rem_euclid(360))
+}
+
+/// Ratios shown by their common name; any other is shown as a decimal
+const NAMED_ASPECT_RATIOS: [(u64, u64); 7] =
+    [(1, 1), (5, 4), (4, 3), (3, 2), (16, 10), (16, 9), (21, 9)];
+
+/// "16:9", "9:16" for portrait, or "2.40:1" for ratios without a common name.
+fn format_aspect((w, h): (u64, u64)) -> String {
+    for (a, b) in NAMED_ASPECT_RATIOS {
+        if w * b == h * a {
+            return format!("{}:{}", a, b);
+        }
+        if w * a == h * b {
+            return format!("{}:{}", b, a);
+        }
+    }
+    if w >= h {
+        format!("{:.2}:1", w as f64 / h as f64)
+    } else {
+        format!("1:{:.2}", h as f64 / w as f64)
+    }
+}
+
+fn orientation((w, h): (u64, u64)) -> &'static str {
+    match w.cmp(&h) {
+        std::cmp::Ordering::Greater => "landscape",
+        std::cmp::Ordering::Less => "portrait",
+        std::cmp::Ordering::Equal => "square",
+    }
+}
+
+/// Aspect ratio filter values: "16:9", "2.39:1" or a plain ratio like 1.78.
+fn parse_aspect(text: &str) -> Option<f64> {
+    let ratio = match text.trim().split_once(':') {
+        Some((w, h)) => w.trim().parse::<f64>().ok()? / h.trim().parse::<f64>().ok()?,
+        None => text.trim().parse::<f64>().ok()?,
+    };
+    (ratio.is_finite() && ratio > 0.0).then_some(ratio)
+}
+
+/// The bitrate `bits_per_pixel` is based on.
+fn bpp_bitrate(probe: &FFProbeOutput) -> Option<f64> {
+    primary_video(probe)?
+        .bit_rate
+        .as_deref()
+        .or(probe.format.bit_rate.as_deref())
+        .and_then(|b| b.parse::<f64>().ok())
+}
+
+/// Dynamic range of a video stream: Dolby Vision (configuration record or
+/// dvh1/dvhe tag), HDR10+ (dynamic metadata), HDR10 (PQ transfer), HLG, or SDR.
+fn hdr_format(video: &Stream) -> &'static str {
+    let side_data_types: Vec<&str> = video
+        .side_data_list
+        .iter()
+        .flatten()
+        .filter_map(|d| d.get("side_data_type")?.as_str())
+        .collect();
+    let dolby_vision_tag = video
+        .codec_tag_string
+        .as_deref()
+        .is_some_and(|tag| matches!(tag, "dvh1" | "dvhe" | "dva1" | "dvav"));
+    if dolby_vision_tag || side_data_types.iter().any(|t| t.contains("DOVI")) {
+        return "Dolby Vision";
+    }
+    match video.color_transfer.as_deref() {
+        Some("smpte2084") if side_data_types.iter().any(|t| t.contains("HDR10+")) => "HDR10+",
+        Some("smpte2084") => "HDR10",
+        Some("arib-std-b67") => "HLG",
+        _ => "SDR",
+    }
+}
+
+/// Whether a file has real video, as opposed to embedded cover art.
+fn has_video(probe: &FFProbeOutput) -> bool {
+    primary_video(probe).is_some()
+}
+
+/// Embedded cover art or thumbnails, which ffprobe reports as a
+/// single-frame video stream with the `attached_pic` disposition.
+fn is_attached_pic(stream: &Stream) -> bool {
+    stream.codec_type == "video" && has_disposition(stream, "attached_pic")
+}
+
+/// The video stream the table describes, never a cover art image: the one
+/// flagged as default, then the largest, then the first.
+fn primary_video(probe: &FFProbeOutput) -> Option<&Stream> {
+    probe
+        .streams
+        .iter()
+        .filter(|s| s.codec_type == "video" && !is_attached_pic(s))
+        // Reversed so ties go to the earliest stream
+        .rev()
+        .max_by_key(|s| {
+            let pixels = i64::from(s.width.unwrap_or(0)) * i64::from(s.height.unwrap_or(0));
+            (has_disposition(s, "default"), pixels)
+        })
+}
+
+/// The audio stream a player would pick: the one flagged as default, then
+/// the one with the most channels, then the first.
+fn primary_audio(probe: &FFProbeOutput) -> Option<&Stream> {
+    probe
+        .streams
+        .iter()
+        .filter(|s| s.codec_type == "audio")
+        // Reversed so ties go to the earliest stream
+        .rev()
+        .max_by_key(|s| (has_disposition(s, "default"), s.channels.unwrap_or(0)))
+}
+
+fn has_disposition(stream: &Stream, flag: &str) -> bool {
+    stream
+        .disposition
+        .as_ref()
+        .and_then(|d| d.get(flag))
+        .is_some_and(|&value| value == 1)
+}
+
+/// Sample bit depth of lossless/PCM audio. Lossy codecs have none.
+fn audio_bit_depth(audio: &Stream) -> Option<String> {
+    let bits = audio
+        .bits_per_raw_sample
+        .as_deref()
+        .and_then(|b| b.parse::<i32>().ok())
+        .filter(|&b| b > 0)
+        .or(audio.bits_per_sample.filter(|&b| b > 0))?;
+    Some(format!("{}bit", bits))
+}
+
+fn get_bit_depth(pix_fmt: Option<&str>) -> String {
+    match pix_fmt {
+        Some(fmt) if fmt.contains("p10") => "10bit",
+        Some(fmt) if fmt.contains("p12") => "12bit",
+        _ => "8bit",
+    }
+    .to_string()
+}
+
+fn format_elapsed(secs: f64) -> String {
+    if secs >= 60.0 {
+        let minutes = (secs / 60.0).floor();
+        let seconds = secs % 60.0;
+        format!("{}:{:02}", minutes as u32, seconds as u32)
+    } else {
+        format!("{}s", secs.round() as u32)
+    }
+}
+
+/// Reads the paths of a `--files-from` list: one per line, or NUL-separated
+/// with `--null`. Blank lines are skipped, and so are Windows line endings.
+fn read_path_list(list: &Path, null: bool) -> Result<Vec<PathBuf>> {
+    let mut content = Vec::new();
+    if list == Path::new("-") {
+        std::io::stdin().read_to_end(&mut content)?;
+    } else {
+        content = fs::read(list)
+            .map_err(|e| anyhow!("Failed to read file list {}: {}", list.display(), e))?;
+    }
+    let separator = if null { b'\0' } else { b'\n' };
+    // Names that aren't valid UTF-8 are kept as they are where the OS allows it
+    let to_path = |entry: &[u8]| -> PathBuf {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            PathBuf::from(std::ffi::OsStr::from_bytes(entry))
+        }
+        #[cfg(not(unix))]
+        {
+            PathBuf::from(String::from_utf8_lossy(entry).into_owned())
+        }
+    };
+    Ok(content
+        .split(|byte| *byte == separator)
+        .map(|entry| entry.strip_suffix(b"\r").unwrap_or(entry))
+        .filter(|entry| !entry.is_empty())
+        .map(to_path)
+        .collect())
+}
+
+/// Walks the given roots, returning media files along with the index of the
+/// root they were found under. Paths matching a root's exclude patterns are
+/// skipped, and a file reachable through several paths (hardlinks, symlinks,
+/// overlapping roots) is only returned for the first one.
+fn collect_media_files(roots: &[(PathBuf, ScanSettings)]) -> Vec<(PathBuf, usize)> {
+    let start_time = Instant::now();
+    let mut media_files = Vec::new();
+    let mut scanned = 0;
+    let mut found = 0;
+    let mut partial = 0;
+    let mut seen = HashSet::new();
+    let mut duplicates = 0;
+
+    let spinner = start_progress(ProgressBar::new_spinner().with_style(
+        ProgressStyle::with_template("{spinner} Scanning: {msg} ({elapsed})").unwrap(),
+    ));
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let status = |scanned: usize, found: usize| {
+        spinner.set_message(format!("{} scanned, {} media files found", scanned, found));
+    };
+    status(scanned, found);
+
+    for (root_index, (root, settings)) in roots.iter().enumerate() {
+        if is_stream_input(root) {
+            scanned += 1;
+            found += 1;
+            media_files.push((root.clone(), root_index));
+        } else if root.is_file() {
+            scanned += 1;
+            // Subtitle files are only scanned when asked for explicitly
+            if is_skipped_partial(root, settings) {
+                partial += 1;
+            } else if file_identity(root).is_some_and(|id| !seen.insert(id)) {
+                duplicates += 1;
+            } else if is_media_file(root) || is_subtitle_file(root) {
+                found += 1;
+                status(scanned, found);
+                media_files.push((root.clone(), root_index));
+            }
+        } else if root.is_dir() {
+            let mut walker =
+                WalkDir::new(root).follow_links(settings.follow_symlinks == Some(true));
+            if let Some(depth) = settings.max_depth {
+                walker = walker.max_depth(depth);
+            }
+            let walker = walker
+                .into_iter()
+                .filter_entry(|e| !is_excluded(e.path(), root, &settings.exclude));
+            for entry in walker.filter_map(|e| e.ok()) {
+                if settings.follow_symlinks == Some(false) && entry.path_is_symlink() {
+                    continue;
+                }
+                let path = entry.path().to_path_buf();
+                if path.is_file() {
+                    scanned += 1;
+                    if !is_scan_candidate(&path, root, settings) {
+                        if is_skipped_partial(&path, settings) && is_media_file(&path) {
+                            partial += 1;
+                        }
+                    } else if file_identity(&path).is_some_and(|id| !seen.insert(id)) {
+                        duplicates += 1;
+                    } else {
+                        found += 1;
+                        status(scanned, found);
+                        media_files.push((path, root_index));
+                    }
+                }
+            }
+        }
+    }
+    finish_progress();
+    let elapsed = start_time.elapsed().as_secs_f64();
+    info!(
+        "Scanning completed in {}: {} media files found",
+        format_elapsed(elapsed),
+        found
+    );
+    if partial > 0 {
+        info!(
+            "Skipped {} unfinished download{} (use --include-partial to probe them)",
+            partial,
+            if partial == 1 { "" } else { "s" }
+        );
+    }
+    if duplicates > 0 {
+        info!(
+            "Skipped {} path{} to files already found (hardlinks or symlinks)",
+            duplicates,
+            if duplicates == 1 { "" } else { "s" }
+        );
+    }
+    media_files
+}
+
+/// Device and inode of a file, after symlinks, which every hardlink and
+/// symlink to it shares. Not available on Windows, where nothing is deduplicated.
+#[cfg(unix)]
+fn file_identity(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Makes `bar` the active progress display. It draws on stderr only when
+/// that is a terminal, so redirected logs stay free of escape sequences.
+fn start_progress(bar: ProgressBar) -> ProgressBar {
+    // A redrawing bar is read out over and over
+    if PLAIN.load(Ordering::Relaxed) {
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    *ACTIVE_PROGRESS.lock().unwrap() = Some(bar.clone());
+    bar
+}
+
+fn finish_progress() {
+    if let Some(bar) = ACTIVE_PROGRESS.lock().unwrap().take() {
+        bar.finish_and_clear();
+    }
+}
+
+/// Prints a message to stderr above the active progress display.
+fn progress_eprintln(message: &str) {
+    match &*ACTIVE_PROGRESS.lock().unwrap() {
+        Some(bar) => bar.suspend(|| eprintln!("{}", message)),
+        None => eprintln!("{}", message),
+    }
+}
+
+/// Writes this crate's log records to stderr, above the progress display.
+/// Status messages are printed as they are; warnings and errors get a prefix.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+            && metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            log::Level::Error => progress_eprintln(&format!("Error: {}", record.args())),
+            log::Level::Warn => progress_eprintln(&format!("Warning: {}", record.args())),
+            _ => progress_eprintln(&record.args().to_string()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Sets the stderr verbosity: `-q` keeps warnings and errors, `-v`/`-vv` add
+/// debug/trace details. Without either, `RUST_LOG` may name a level (`debug`,
+/// or `mediainfo=debug` among other entries); the default is info.
+fn init_logging(quiet: bool, verbose: u8) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => std::env::var("RUST_LOG")
+            .ok()
+            .and_then(|spec| rust_log_level(&spec))
+            .unwrap_or(LevelFilter::Info),
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// The level a `RUST_LOG` value sets for this crate, from a bare level or a
+/// `mediainfo=level` entry (the latter wins). Other crates' entries are ignored.
+fn rust_log_level(spec: &str) -> Option<LevelFilter> {
+    let mut level = None;
+    for entry in spec.split(',').map(str::trim) {
+        match entry.split_once('=') {
+            Some((env!("CARGO_CRATE_NAME"), value)) => {
+                return value.parse().ok();
+            }
+            Some(_) => {}
+            None => level = entry.parse().ok().or(level),
+        }
+    }
+    level
+}
+
+/// Whether a file found under `root` should be probed: a media file, or one
+/// of the `--ext` extensions when given, within `--max-depth` of the root.
+/// Unfinished downloads only count with `--include-partial`.
+fn is_scan_candidate(path: &Path, root: &Path, settings: &ScanSettings) -> bool {
+    if is_skipped_partial(path, settings) {
+        return false;
+    }
+    let wanted = if settings.extensions.is_empty() {
+        is_media_file(path)
+    } else {
+        media_extension(path).is_some_and(|ext| settings.extensions.contains(&ext))
+    };
+    let depth = path
+        .strip_prefix(root)
+        .map_or(0, |p| p.components().count());
+    wanted && settings.max_depth.is_none_or(|max| depth <= max)
+}
+
+/// Exclude patterns match either a single path component (`@eaDir`,
+/// `*.sample.*`) or the path relative to the scanned root (`extras/**`).
+fn is_excluded(path: &Path, root: &Path, exclude: &GlobSet) -> bool {
+    if exclude.is_empty() || path == root {
+        return false;
+    }
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    exclude.is_match(relative)
+        || path
+            .file_name()
+            .is_some_and(|name| exclude.is_match(Path::new(name)))
+}
+
+fn resolve_scan_settings(
+    root: &Path,
+    args: &Args,
+    profiles: &HashMap<String, ScanProfile>,
+) -> Result<ScanSettings> {
+    // The longest matching prefix wins
+    let canonical = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let profile = profiles
+        .iter()
+        .filter(|(prefix, _)| canonical.starts_with(prefix) || root.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, profile)| profile.clone())
+        .unwrap_or_default();
+
+    let signature = profile.signature.unwrap_or_else(|| args.signature.clone());
+    if !["mtime", "size", "hash"].contains(&signature.as_str()) {
+        return Err(anyhow!(
+            "Invalid signature mode in config profile: {} (expected mtime, size or hash)",
+            signature
+        ));
+    }
+
+    let mut exclude = GlobSetBuilder::new();
+    for pattern in profile.exclude.iter().chain(&args.exclude) {
+        exclude.add(
+            Glob::new(pattern)
+                .map_err(|e| anyhow!("Invalid exclude pattern '{}': {}", pattern, e))?,
+        );
+    }
+
+    let default_jobs = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    Ok(ScanSettings {
+        jobs: profile.jobs.or(args.jobs).unwrap_or(default_jobs).max(1),
+        timeout: profile.timeout.or(args.timeout).map(Duration::from_secs),
+        signature,
+        xattr: profile.xattr.unwrap_or(args.xattr),
+        exclude: exclude.build()?,
+        extensions: args
+            .ext
+            .iter()
+            .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect(),
+        max_depth: args.max_depth,
+        include_partial: args.include_partial,
+        follow_symlinks: match (args.follow_symlinks, args.no_follow_symlinks) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        },
+        retry: RetryPolicy {
+            retries: args.retries,
+            base_delay: Duration::from_millis(args.retry_delay),
+        },
+        backend: args
+            .backend
+            .clone()
+            .unwrap_or_else(|| "ffprobe".to_string()),
+        stream_window: Duration::from_secs(args.stream_window.max(1)),
+    })
+}
+
+/// Probes files using `settings.jobs` worker threads. Results keep the
+/// order of `files`; failures are reported and left out.
+///
+/// If `root` disappears mid-run (drive unplugged, mount dropped), probing
+/// stops and the files that were not probed are returned as the second value.
+fn probe_files(
+    files: Vec<PathBuf>,
+    root: &Path,
+    settings: &ScanSettings,
+    no_cache: bool,
+    progress: &Mutex<ProbeProgress>,
+) -> (Vec<(PathBuf, FFProbeOutput)>, Vec<PathBuf>) {
+    let queue = Mutex::new(files.into_iter().enumerate());
+    let results = Mutex::new(Vec::new());
+    let remaining = Mutex::new(Vec::new());
+    let root_lost = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        for _ in 0..settings.jobs {
+            scope.spawn(|| loop {
+                if progress
+                    .lock()
+                    .unwrap()
+                    .deadline
+                    .is_some_and(|d| Instant::now() >= d)
+                {
+                    break;
+                }
+                let next = queue.lock().unwrap().next();
+                let Some((index, file)) = next else {
+                    break;
+                };
+                if root_lost.load(Ordering::Relaxed) {
+                    remaining.lock().unwrap().push((index, file));
+                    continue;
+                }
+                match probe_with_progress(&file, root, settings, no_cache, progress) {
+                    Some(probe) => results.lock().unwrap().push((index, file, probe)),
+                    // Don't report every remaining file as broken when the
+                    // whole drive is gone
+                    None if !root_available(root, &file) => {
+                        root_lost.store(true, Ordering::Relaxed);
+                        remaining.lock().unwrap().push((index, file));
+                    }
+                    None => {}
+                }
+            });
+        }
+    });
+
+    // Whatever is still queued was cut off by --max-runtime
+    progress.lock().unwrap().unprobed += queue.into_inner().unwrap().count();
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _, _)| *index);
+    let mut remaining = remaining.into_inner().unwrap();
+    remaining.sort_by_key(|(index, _)| *index);
+    (
+        results
+            .into_iter()
+            .map(|(_, file, probe)| (file, probe))
+            .collect(),
+        remaining.into_iter().map(|(_, file)| file).collect(),
+    )
+}
+
+/// Probes media files under `dirs` as they are created or modified, until
+/// interrupted. Files are only probed once they have been quiet for `settle`,
+/// so partially written files aren't probed (and cached) mid-download.
+/// `feed` is the Atom feed to add them to, and how many entries it keeps.
+fn watch_dirs(
+    dirs: &[PathBuf],
+    print: bool,
+    settle: Duration,
+    exec: Option<&str>,
+    feed: Option<(&Path, usize)>,
+    args: &Args,
+    config: &Config,
+) -> Result<()> {
+    let roots = dirs
+        .iter()
+        .map(|dir| {
+            Ok((
+                dir.clone(),
+                resolve_scan_settings(dir, args, &config.profiles)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for dir in dirs {
+        if !dir.is_dir() {
+            return Err(anyhow!("Not a directory: {}", dir.display()));
+        }
+        watcher
+            .watch(dir, RecursiveMode::Recursive)
+            .map_err(|e| anyhow!("Failed to watch {}: {}", dir.display(), e))?;
+    }
+    info!(
+        "Watching {} for new media files (Ctrl-C to stop)",
+        dirs.iter()
+            .map(|d| d.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    log_event("watch", serde_json::json!({ "dirs": dirs }));
+
+    let options = DisplayOptions {
+        columns: DEFAULT_COLUMNS.to_vec(),
+        dates: "iso".to_string(),
+        reference: None,
+    };
+    if print {
+        println!("path\t{}", DEFAULT_COLUMNS.join("\t"));
+    }
+    // Entries from before a restart are kept
+    let mut feed_entries = match feed {
+        Some((path, _)) => read_feed(path)?,
+        None => Vec::new(),
+    };
+
+    // Files with pending changes, and when they last changed
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        match receiver.recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    for path in event.paths {
+                        if path.extension().is_some() {
+                            pending.insert(path, Instant::now());
+                        }
+                    }
+                }
+            }
+            Ok(Err(e)) => error!("Could not watch for changes: {}", e),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        let settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for file in settled {
+            pending.remove(&file);
+            // Longest root first, so nested watched directories use their own profile
+            let Some((root, settings)) = roots
+                .iter()
+                .filter(|(root, _)| file.starts_with(root))
+                .max_by_key(|(root, _)| root.as_os_str().len())
+            else {
+                continue;
+            };
+            // Renamed away or deleted before it settled
+            if !file.is_file()
+                || !is_scan_candidate(&file, root, settings)
+                || file
+                    .ancestors()
+                    .take_while(|path| *path != root.as_path())
+                    .any(|path| is_excluded(path, root, &settings.exclude))
+            {
+                continue;
+            }
+            match process_file(&file, false, settings) {
+                Ok(probe) => {
+                    log_event("file", serde_json::json!({ "path": file }));
+                    let values = format_probe_output(&file, &probe, &options)?;
+                    if print {
+                        let fields: Vec<String> = DEFAULT_COLUMNS
+                            .iter()
+                            .map(|column| {
+                                values
+                                    .get(column)
+                                    .map(|v| v.replace(['\t', '\n'], " "))
+                                    .unwrap_or_default()
+                            })
+                            .collect();
+                        println!("{}\t{}", file.display(), fields.join("\t"));
+                    } else {
+                        info!("Probed {}", file.display());
+                    }
+                    if let Some((path, size)) = feed {
+                        feed_entries.retain(|entry| entry.path != file);
+                        feed_entries.insert(0, FeedEntry::new(&file, &probe, &values));
+                        feed_entries.truncate(size);
+                        if let Err(e) = write_feed(path, dirs, &feed_entries) {
+                            warn!("Failed to write feed {}: {}", path.display(), e);
+                        }
+                    }
+                    if let Some(command) = exec {
+                        if let Err(e) = run_hook(command, &file, &probe, &values) {
+                            error!("Hook failed for {}: {}", file.display(), e);
+                            log_event(
+                                "hook_error",
+                                serde_json::json!({ "path": file, "error": e.to_string() }),
+                            );
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("Could not process {}: {}", file.display(), e);
+                    log_event(
+                        "error",
+                        serde_json::json!({ "path": file, "error": e.to_string() }),
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs a `watch --exec` command through the shell for a probed file. The
+/// file is described in MEDIAINFO_PATH, MEDIAINFO_ID, one MEDIAINFO_<COLUMN>
+/// variable per default column, and the raw MEDIAINFO_SIZE_BYTES,
+/// MEDIAINFO_DURATION_SECONDS and MEDIAINFO_BITRATE_BPS; the same entry as
+/// `--output json` (id, path, probe) is written to its stdin.
+fn run_hook(
+    command: &str,
+    file: &Path,
+    probe: &FFProbeOutput,
+    values: &HashMap<&str, String>,
+) -> Result<()> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell
+        .arg(command)
+        .env("MEDIAINFO_PATH", file)
+        .env("MEDIAINFO_ID", file_id(file))
+        .stdin(Stdio::piped());
+    for column in DEFAULT_COLUMNS {
+        let value = values.get(column).map(String::as_str).unwrap_or_default();
+        shell.env(format!("MEDIAINFO_{}", column.to_uppercase()), value);
+    }
+    for (name, value) in RAW_COLUMN_NAMES.iter().zip(raw_fields(probe)) {
+        let value = match value {
+            serde_json::Value::Null => String::new(),
+            value => value.to_string(),
+        };
+        shell.env(format!("MEDIAINFO_{}", name.to_uppercase()), value);
+    }
+
+    let mut child = shell.spawn()?;
+    let entry = serde_json::json!({"id": file_id(file), "path": file, "probe": probe});
+    if let Some(mut stdin) = child.stdin.take() {
+        // Hooks that only use the environment may exit without reading it
+        let _ = stdin.write_all(serde_json::to_string(&entry)?.as_bytes());
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", command, status));
+    }
+    Ok(())
+}
+
+/// A file in the `watch --feed` feed. Besides the Atom fields, every entry
+/// carries its details in `mi:` elements, which are also how the feed is
+/// read back when the watch restarts.
+struct FeedEntry {
+    id: String,
+    path: PathBuf,
+    title: String,
+    summary: String,
+    size: u64,
+    duration: Option<f64>,
+    resolution: String,
+    /// Unix time the file was probed
+    added: i64,
+}
+
+/// Namespace of the feed's `mi:` elements
+const FEED_NAMESPACE: &str = "urn:mediainfo";
+
+impl FeedEntry {
+    fn new(file: &Path, probe: &FFProbeOutput, values: &HashMap<&str, String>) -> Self {
+        let value = |column: &str| values.get(column).cloned().unwrap_or_default();
+        let resolution = primary_video(probe)
+            .and_then(|video| Some(format!("{}x{}", video.width?, video.height?)))
+            .unwrap_or_default();
+        let summary = [resolution.clone(), value("duration"), value("size")]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(", ");
+        FeedEntry {
+            id: file_id(file),
+            path: file.to_path_buf(),
+            title: Some(value("title"))
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| value("filename")),
+            summary,
+            size: probe.format.size.parse().unwrap_or(0),
+            duration: probe.format.duration.parse().ok(),
+            resolution,
+            added: now_secs(),
+        }
+    }
+}
+
+/// Reads the entries of a feed written by `write_feed`; a missing file is an
+/// empty feed.
+fn read_feed(path: &Path) -> Result<Vec<FeedEntry>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(anyhow!("Failed to read feed {}: {}", path.display(), e)),
+    };
+    let document = roxmltree::Document::parse(&content)
+        .map_err(|e| anyhow!("Failed to parse feed {}: {}", path.display(), e))?;
+    Ok(document
+        .descendants()
+        .filter(|node| node.has_tag_name("entry"))
+        .filter_map(|entry| {
+            let field = |namespace: Option<&str>, name: &str| {
+                entry
+                    .children()
+                    .find(|node| match namespace {
+                        Some(namespace) => node.has_tag_name((namespace, name)),
+                        None => node.has_tag_name(name),
+                    })
+                    .map(|node| node.text().unwrap_or_default().to_string())
+            };
+            let mi = |name: &str| field(Some(FEED_NAMESPACE), name);
+            let path = PathBuf::from(mi("path")?);
+            Some(FeedEntry {
+                id: file_id(&path),
+                title: field(None, "title").unwrap_or_default(),
+                summary: field(None, "summary").unwrap_or_default(),
+                size: mi("size").and_then(|s| s.parse().ok()).unwrap_or(0),
+                duration: mi("duration").and_then(|d| d.parse().ok()),
+                resolution: mi("resolution").unwrap_or_default(),
+                added: field(None, "updated")
+                    .and_then(|updated| parse_iso_timestamp(&updated))
+                    .unwrap_or(0),
+                path,
+            })
+        })
+        .collect())
+}
+
+/// Writes the feed as Atom (the format OPDS catalogs are built on), newest
+/// entry first. The file is replaced atomically, so pollers never see it
+/// half written.
+fn write_feed(path: &Path, dirs: &[PathBuf], entries: &[FeedEntry]) -> Result<()> {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str(&format!(
+        "<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:mi=\"{}\">\n",
+        FEED_NAMESPACE
+    ));
+    let dirs = dirs
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    xml.push_str(&format!(
+        "  <id>urn:mediainfo:feed:{}</id>\n",
+        file_id(path)
+    ));
+    xml.push_str(&format!(
+        "  <title>New media in {}</title>\n",
+        html_escape(&dirs)
+    ));
+    let updated = entries.first().map_or_else(now_secs, |entry| entry.added);
+    xml.push_str(&format!(
+        "  <updated>{}</updated>\n",
+        format_iso_timestamp(updated)
+    ));
+    xml.push_str("  <author><name>mediainfo</name></author>\n");
+    for entry in entries {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>urn:mediainfo:{}</id>\n", entry.id));
+        xml.push_str(&format!(
+            "    <title>{}</title>\n",
+            html_escape(&entry.title)
+        ));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            format_iso_timestamp(entry.added)
+        ));
+        xml.push_str(&format!(
+            "    <link rel=\"enclosure\" href=\"{}\" length=\"{}\"/>\n",
+            html_escape(&file_url(&entry.path)),
+            entry.size
+        ));
+        xml.push_str(&format!(
+            "    <summary>{}</summary>\n",
+            html_escape(&entry.summary)
+        ));
+        xml.push_str(&format!(
+            "    <mi:path>{}</mi:path>\n",
+            html_escape(&entry.path.to_string_lossy())
+        ));
+        xml.push_str(&format!("    <mi:size>{}</mi:size>\n", entry.size));
+        if let Some(duration) = entry.duration {
+            xml.push_str(&format!("    <mi:duration>{}</mi:duration>\n", duration));
+        }
+        if !entry.resolution.is_empty() {
+            xml.push_str(&format!(
+                "    <mi:resolution>{}</mi:resolution>\n",
+                entry.resolution
+            ));
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, xml)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Outcome of decoding a file with `verify`, cached by file signature so
+/// unchanged files aren't decoded again.
+#[derive(Clone, Deserialize, Serialize)]
+struct Verification {
+    signature: String,
+    ok: bool,
+    /// The first VERIFY_MAX_ERRORS lines ffmpeg reported
+    errors: Vec<String>,
+    /// Total number of error lines
+    error_count: usize,
+    /// Unix time the file was decoded
+    verified_at: i64,
+}
+
+/// Error lines kept per file; a badly damaged file can produce thousands
+const VERIFY_MAX_ERRORS: usize = 20;
+
+fn get_verify_cache_file() -> Result<PathBuf> {
+    Ok(get_cache_dir()?.join("verify.json"))
+}
+
+/// Decodes every media file under the scan paths and reports the ones that
+/// don't decode cleanly. Each root is decoded with its profile's jobs and
+/// signature mode; timeouts don't apply, since decoding takes far longer
+/// than probing.
+fn verify_files(
+    args: &Args,
+    config: &Config,
+    output: &str,
+    failed_only: bool,
+    no_cache: bool,
+) -> Result<()> {
+    if find_in_path("ffmpeg").is_none() {
+        return Err(anyhow!("verify needs ffmpeg in PATH to decode files"));
+    }
+    let roots = args
+        .paths
+        .iter()
+        .map(|path| {
+            Ok((
+                path.clone(),
+                resolve_scan_settings(path, args, &config.profiles)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let media_files = collect_media_files(&roots);
+    if media_files.is_empty() {
+        warn!("No media files found!");
+        return Ok(());
+    }
+
+    let cache_path = get_verify_cache_file()?;
+    let cache: HashMap<String, Verification> = match cache_path.exists() {
+        true => read_cache_file(&cache_path).unwrap_or_default(),
+        false => HashMap::new(),
+    };
+    let cache = Mutex::new(cache);
+    let failures = std::sync::atomic::AtomicUsize::new(0);
+    let bar = start_progress(
+        ProgressBar::new(media_files.len() as u64)
+            .with_style(
+                ProgressStyle::with_template(
+                    "Verifying {bar:30} {pos}/{len} files ({msg}) ETA {eta}",
+                )
+                .unwrap(),
+            )
+            .with_message("0 failed"),
+    );
+    let results = Mutex::new(Vec::new());
+    for_each_media_file(&roots, &media_files, |file, settings| {
+        match verify_file(file, &settings.signature, &cache, no_cache) {
+            Ok((verification, cached)) => {
+                if !verification.ok {
+                    let failed = failures.fetch_add(1, Ordering::Relaxed) + 1;
+                    bar.set_message(format!("{} failed", failed));
+                }
+                results
+                    .lock()
+                    .unwrap()
+                    .push((file.clone(), verification, cached));
+            }
+            Err(e) => error!("Could not verify {}: {}", file.display(), e),
+        }
+        bar.inc(1);
+    });
+    finish_progress();
+
+    let cache = cache.into_inner().unwrap();
+    let temp_path = cache_path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_vec(&cache)?)?;
+    fs::rename(&temp_path, &cache_path)?;
+
+    let mut results = results.into_inner().unwrap();
+    let failed = failures.into_inner();
+    let from_cache = results.iter().filter(|(_, _, cached)| *cached).count();
+    info!(
+        "Verified {} files: {} failed ({} unchanged since an earlier verify)",
+        results.len(),
+        failed,
+        from_cache
+    );
+    // Failures first, then by path
+    results.sort_by(|a, b| a.1.ok.cmp(&b.1.ok).then_with(|| a.0.cmp(&b.0)));
+    if failed_only {
+        results.retain(|(_, verification, _)| !verification.ok);
+    }
+
+    if output == "json" {
+        let entries: Vec<serde_json::Value> = results
+            .iter()
+            .map(|(file, verification, cached)| {
+                serde_json::json!({
+                    "id": file_id(file),
+                    "path": file,
+                    "ok": verification.ok,
+                    "errors": verification.errors,
+                    "error_count": verification.error_count,
+                    "verified_at": verification.verified_at,
+                    "cached": cached,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else if !results.is_empty() {
+        let mut table = Table::new();
+        table.set_format(table_format());
+        table.set_titles(Row::new(vec![
+            Cell::new("Filename").style_spec("b"),
+            Cell::new("Result").style_spec("b"),
+            Cell::new("Verified").style_spec("br"),
+            Cell::new("Errors").style_spec("b"),
+        ]));
+        for (file, verification, _) in &results {
+            let filename = file.file_name().unwrap_or_default().to_string_lossy();
+            let errors = match verification.errors.first() {
+                Some(first) if verification.error_count > 1 => {
+                    format!("{} (+{} more)", first, verification.error_count - 1)
+                }
+                Some(first) => first.clone(),
+                None => String::new(),
+            };
+            table.add_row(Row::new(vec![
+                Cell::new(&truncate_middle(&filename, args.filename_length)),
+                Cell::new(if verification.ok { "OK" } else { "FAILED" }),
+                Cell::new(&format_date(Some(verification.verified_at), "relative")).style_spec("r"),
+                Cell::new(&truncate_middle(&errors, 80)),
+            ]));
+        }
+        table.printstd();
+    }
+
+    if failed > 0 {
+        std::process::exit(EXIT_VERIFY_FAILED);
+    }
+    Ok(())
+}
+
+/// Contact sheet layout for `thumbs --grid`, e.g. "4x3".
+fn parse_grid(value: &str) -> Result<(u32, u32), String> {
+    let parsed = value
+        .split_once(['x', 'X'])
+        .and_then(|(cols, rows)| Some((cols.parse().ok()?, rows.parse().ok()?)));
+    match parsed {
+        Some((cols, rows)) if cols > 0 && rows > 0 => Ok((cols, rows)),
+        _ => Err(format!("expected COLSxROWS like 4x3, got '{}'", value)),
+    }
+}
+
+fn get_thumbs_dir() -> Result<PathBuf> {
+    match &*THUMBS_DIR.lock().unwrap() {
+        Some(dir) => Ok(dir.clone()),
+        None => Ok(get_cache_dir()?.join("thumbs")),
+    }
+}
+
+/// Where `thumbs` puts the image for a file: `<file id>.jpg`.
+fn thumbnail_path(file: &Path) -> Option<PathBuf> {
+    Some(
+        get_thumbs_dir()
+            .ok()?
+            .join(format!("{}.jpg", file_id(file))),
+    )
+}
+
+/// Extracts one frame per video (or a contact sheet with `grid`) into the
+/// thumbnails directory. Probe results come from the cache where possible,
+/// and existing images are kept unless `force` is set.
+fn make_thumbnails(
+    args: &Args,
+    config: &Config,
+    grid: Option<(u32, u32)>,
+    width: u32,
+    force: bool,
+) -> Result<()> {
+    if find_in_path("ffmpeg").is_none() {
+        return Err(anyhow!("thumbs needs ffmpeg in PATH to extract frames"));
+    }
+    let dir = get_thumbs_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+    let roots = args
+        .paths
+        .iter()
+        .map(|path| {
+            Ok((
+                path.clone(),
+                resolve_scan_settings(path, args, &config.profiles)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let media_files = collect_media_files(&roots);
+    if media_files.is_empty() {
+        warn!("No media files found!");
+        return Ok(());
+    }
+
+    // Written, already there, no video stream, failed
+    let counts = Mutex::new([0usize; 4]);
+    let bar = start_progress(ProgressBar::new(media_files.len() as u64).with_style(
+        ProgressStyle::with_template("Thumbnails {bar:30} {pos}/{len} files ETA {eta}").unwrap(),
+    ));
+    for_each_media_file(&roots, &media_files, |file, settings| {
+        let outcome = match thumbnail_path(file) {
+            Some(dest) if dest.exists() && !force => Ok(1),
+            Some(dest) => {
+                process_file(file, false, settings).and_then(|probe| match primary_video(&probe) {
+                    Some(_) => extract_thumbnail(file, &probe, &dest, grid, width).map(|_| 0),
+                    None => Ok(2),
+                })
+            }
+            None => Err(anyhow!("Could not determine the thumbnail path")),
+        };
+        let index = outcome.unwrap_or_else(|e| {
+            error!("Could not create thumbnail for {}: {}", file.display(), e);
+            3
+        });
+        counts.lock().unwrap()[index] += 1;
+        bar.inc(1);
+    });
+    finish_progress();
+    flush_cache()?;
+
+    let [written, existing, no_video, failed] = counts.into_inner().unwrap();
+    info!(
+        "Wrote {} thumbnails to {} ({} already there, {} without video, {} failed)",
+        written,
+        dir.display(),
+        existing,
+        no_video,
+        failed
+    );
+    Ok(())
+}
+
+/// Runs ffmpeg for one thumbnail. A single frame is taken at 10% of the
+/// duration to skip intros and fades; a contact sheet samples frames evenly
+/// over the whole video, which means decoding all of it.
+fn extract_thumbnail(
+    file: &Path,
+    probe: &FFProbeOutput,
+    dest: &Path,
+    grid: Option<(u32, u32)>,
+    width: u32,
+) -> Result<()> {
+    let duration = probe.format.duration.parse::<f64>().unwrap_or(0.0);
+    let scale = format!("scale={}:-2", width);
+    let mut command = Command::new("ffmpeg");
+    command.args(["-nostdin", "-v", "error"]);
+    match grid {
+        Some((cols, rows)) if duration > 0.0 => {
+            let fps = (cols * rows) as f64 / duration;
+            command.arg("-i").arg(file).args([
+                "-vf",
+                &format!("fps={},{},tile={}x{}", fps, scale, cols, rows),
+            ]);
+        }
+        _ => {
+            command
+                .args(["-ss", &format!("{:.3}", duration * 0.1), "-i"])
+                .arg(file)
+                .args(["-vf", &scale]);
+        }
+    }
+    // Written next to the final name first, so an interrupted run leaves no
+    // half-written image that would be skipped next time
+    let temp = dest.with_extension("tmp.jpg");
+    command
+        .args(["-frames:v", "1", "-q:v", "3", "-y"])
+        .arg(&temp);
+    let output = command.output()?;
+    if !output.status.success() || !temp.exists() {
+        let _ = fs::remove_file(&temp);
+        return Err(anyhow!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    fs::rename(&temp, dest)?;
+    Ok(())
+}
+
+/// Runs `f` on every collected media file, each root's files on as many
+/// threads as its profile's `jobs`.
+fn for_each_media_file(
+    roots: &[(PathBuf, ScanSettings)],
+    media_files: &[(PathBuf, usize)],
+    f: impl Fn(&PathBuf, &ScanSettings) + Sync,
+) {
+    for (root_index, (_, settings)) in roots.iter().enumerate() {
+        let queue = Mutex::new(
+            media_files
+                .iter()
+                .filter(|(_, index)| *index == root_index)
+                .map(|(file, _)| file),
+        );
+        std::thread::scope(|scope| {
+            for _ in 0..settings.jobs {
+                scope.spawn(|| loop {
+                    let next = queue.lock().unwrap().next();
+                    let Some(file) = next else {
+                        break;
+                    };
+                    f(file, settings);
+                });
+            }
+        });
+    }
+}
+
+/// Decodes a file with `ffmpeg -f null` unless a cached result for the same
+/// signature exists. Returns the verification and whether it was cached.
+fn verify_file(
+    file: &Path,
+    signature_mode: &str,
+    cache: &Mutex<HashMap<String, Verification>>,
+    no_cache: bool,
+) -> Result<(Verification, bool)> {
+    let key = cache_key(file)?;
+    let signature = get_file_signature(file, signature_mode)?;
+    if !no_cache {
+        if let Some(verification) = cache.lock().unwrap().get(&key) {
+            if verification.signature == signature {
+                return Ok((verification.clone(), true));
+            }
+        }
+    }
+
+    let output = Command::new("ffmpeg")
+        .args(["-nostdin", "-hide_banner", "-v", "error", "-i"])
+        .arg(file)
+        .args(["-f", "null", "-"])
+        .output()?;
+    let mut errors: Vec<String> = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+    if !output.status.success() && errors.is_empty() {
+        errors.push(format!("ffmpeg exited with {}", output.status));
+    }
+    let error_count = errors.len();
+    errors.truncate(VERIFY_MAX_ERRORS);
+    let verification = Verification {
+        signature,
+        ok: output.status.success() && error_count == 0,
+        errors,
+        error_count,
+        verified_at: now_secs(),
+    };
+    cache.lock().unwrap().insert(key, verification.clone());
+    Ok((verification, false))
+}
+
+/// Whether a scan root is still reachable. An unplugged drive or dropped
+/// mount either removes the mount point or leaves it behind empty, so the
+/// directory the file lives in is checked as well.
+fn root_available(root: &Path, file: &Path) -> bool {
+    if is_stream_input(root) {
+        return true;
+    }
+    root.exists() && file.parent().is_none_or(|dir| dir.exists())
+}
+
+/// Whether a file that failed to probe is gone from disk (ENOENT), i.e.
+/// it was deleted or moved after the walk rather than being unreadable.
+fn has_vanished(file: &Path) -> bool {
+    !is_stream_input(file)
+        && fs::symlink_metadata(file).is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Lists files that disappeared between the walk and their probe. They are
+/// not errors by default, so they get their own note instead.
+fn report_vanished(vanished: &[PathBuf]) {
+    if vanished.is_empty() {
+        return;
+    }
+    let files: Vec<String> = vanished
+        .iter()
+        .map(|file| format!("  {}", file.display()))
+        .collect();
+    warn!(
+        "{} {} vanished before {} could be probed (deleted or moved during the scan):\n{}",
+        vanished.len(),
+        if vanished.len() == 1 { "file" } else { "files" },
+        if vanished.len() == 1 { "it" } else { "they" },
+        files.join("\n")
+    );
+}
+
+/// Asks whether to wait for a vanished root to come back. Without a terminal
+/// to prompt on, the scan stops with the results collected so far.
+fn wait_for_root(root: &Path, remaining: usize) -> bool {
+    warn!(
+        "{} is no longer available ({} files not probed yet)",
+        root.display(),
+        remaining
+    );
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return false;
+    }
+    loop {
+        eprint!("Reconnect it and press Enter to resume, or type q to stop with partial results: ");
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).unwrap_or(0) == 0
+            || answer.trim().eq_ignore_ascii_case("q")
+        {
+            return false;
+        }
+        if fs::read_dir(root).is_ok_and(|mut entries| entries.next().is_some()) || root.is_file() {
+            return true;
+        }
+        eprintln!("{} is still not available", root.display());
+    }
+}
+
+fn probe_with_progress(
+    file: &Path,
+    root: &Path,
+    settings: &ScanSettings,
+    no_cache: bool,
+    progress: &Mutex<ProbeProgress>,
+) -> Option<FFProbeOutput> {
+    let file_start = Instant::now();
+    let is_cached = !no_cache
+        && !is_stream_input(file)
+        && get_cached_probe(file, &settings.signature)
+            .ok()
+            .flatten()
+            .is_some_and(|probe| cache_usable(&probe, settings));
+    let result = process_file(file, no_cache, settings);
+    if result.is_err() && !root_available(root, file) {
+        // Not this file's fault; the caller stops the scan for this root
+        return None;
+    }
+
+    let mut progress = progress.lock().unwrap();
+    progress.processed += 1;
+    if is_cached {
+        progress.cached += 1;
+        progress.cache_hits.insert(file.to_path_buf());
+    }
+    progress.bar.inc(1);
+    progress
+        .bar
+        .set_message(format!("{} from cache", progress.cached));
+    match result {
+        Ok(probe) => {
+            log_event(
+                "file",
+                serde_json::json!({
+                    "path": file,
+                    "cache_hit": is_cached,
+                    "elapsed_secs": file_start.elapsed().as_secs_f64(),
+                }),
+            );
+            Some(probe)
+        }
+        Err(e) if has_vanished(file) => {
+            progress.vanished.push(file.to_path_buf());
+            log_event(
+                "vanished",
+                serde_json::json!({
+                    "path": file,
+                    "error": e.to_string(),
+                }),
+            );
+            None
+        }
+        Err(e) => {
+            progress.failed += 1;
+            error!("Could not process {}: {}", file.display(), e);
+            log_event(
+                "error",
+                serde_json::json!({
+                    "path": file,
+                    "error": e.to_string(),
+                    "elapsed_secs": file_start.elapsed().as_secs_f64(),
+                }),
+            );
+            None
+        }
+    }
+}
+
+fn parse_duration_to_secs(duration: &str) -> f64 {
+    let parts: Vec<&str> = duration.split(':').collect();
+    match parts.len() {
+        2 => {
+            let mins: f64 = parts[0].parse().unwrap_or(0.0);
+            let secs: f64 = parts[1].parse().unwrap_or(0.0);
+            mins * 60.0 + secs
+        }
+        3 => {
+            let hours: f64 = parts[0].parse().unwrap_or(0.0);
+            let mins: f64 = parts[1].parse().unwrap_or(0.0);
+            let secs: f64 = parts[2].parse().unwrap_or(0.0);
+            hours * 3600.0 + mins * 60.0 + secs
+        }
+        _ => 0.0,
+    }
+}
+
+fn parse_human_duration(duration_str: &str) -> Option<f64> {
+    let mut total_seconds = 0.0;
+    let mut current_number = String::new();
+    let mut chars = duration_str.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            current_number.push(c);
+        } else {
+            let number = current_number.parse::<f64>().ok()?;
+            current_number.clear();
+
+            match c {
+                'h' => total_seconds += number * 3600.0,
+                'm' => {
+                    if chars.peek() == Some(&'i') {
+                        chars.next(); // consume 'i'
+                        if chars.peek() == Some(&'n') {
+                            chars.next(); // consume 'n'
+                            total_seconds += number * 60.0;
+                        }
+                    } else {
+                        total_seconds += number * 60.0;
+                    }
+                }
+                's' => total_seconds += number,
+                _ => return None,
+            }
+        }
+    }
+
+    // Handle case where there might be a trailing number without unit (assume seconds)
+    if !current_number.is_empty() {
+        if let Ok(number) = current_number.parse::<f64>() {
+            total_seconds += number;
+        }
+    }
+
+    Some(total_seconds)
+}
+
+fn process_file(file: &Path, skip_cache: bool, settings: &ScanSettings) -> Result<FFProbeOutput> {
+    let retry = &settings.retry;
+
+    // Pipes can be read only once and have no stable identity to cache by
+    if is_stream_input(file) {
+        return run_probe(file, settings);
+    }
+
+    // Try to get from cache first, unless skip_cache is true
+    if !skip_cache {
+        if let Ok(Some(probe)) = get_cached_probe(file, &settings.signature) {
+            if cache_usable(&probe, settings) {
+                return Ok(probe);
+            }
+        }
+        if settings.xattr {
+            if let Ok(Some(probe)) = get_xattr_probe(file, &settings.signature) {
+                if cache_usable(&probe, settings) {
+                    return Ok(probe);
+                }
+            }
+        }
+    }
+
+    // If not in cache, cache is invalid, or skip_cache is true, run ffprobe
+    let mut attempt = 0;
+    let probe = loop {
+        match run_probe(file, settings) {
+            Ok(probe) => break probe,
+            Err(e) if attempt < retry.retries && is_transient_error(&e) => {
+                let delay = retry.base_delay * 2u32.pow(attempt);
+                attempt += 1;
+                warn!(
+                    "Transient error probing {} (attempt {}/{}), retrying in {}ms: {}",
+                    file.display(),
+                    attempt,
+                    retry.retries + 1,
+                    delay.as_millis(),
+                    e
+                );
+                log_event(
+                    "retry",
+                    serde_json::json!({
+                        "path": file,
+                        "attempt": attempt,
+                        "error": e.to_string(),
+                    }),
+                );
+                std::thread::sleep(delay);
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    if skip_cache {
+        if let Some(previous) = cached_probe_data(file)? {
+            report_probe_changes(file, &previous, &probe)?;
+        }
+    }
+
+    // Always save to cache, even if we skipped reading from it
+    save_to_cache(file, &probe, &settings.signature)?;
+    if settings.xattr {
+        if let Err(e) = write_xattr_signature(file, &probe, &settings.signature) {
+            // Typically an unsupported filesystem or a read-only file; say so once
+            if !XATTR_WARNED.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "Could not write extended attributes to {}: {}",
+                    file.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    Ok(probe)
+}
+
+/// Records the file's signature and a fingerprint of its probe result in
+/// extended attributes, which travel with the file when it is moved or copied
+/// with attributes preserved (`cp -a`, `rsync -X`).
+fn write_xattr_signature(file: &Path, probe: &FFProbeOutput, signature_mode: &str) -> Result<()> {
+    let signature = get_file_signature(file, signature_mode)?;
+    xattr::set(file, XATTR_SIGNATURE, signature.as_bytes())?;
+    xattr::set(file, XATTR_PROBE_HASH, probe_fingerprint(probe)?.as_bytes())?;
+    Ok(())
+}
+
+/// Cache lookup for files whose path is not (or no longer) in the cache. If
+/// the signature stored in the file's extended attributes still matches, the
+/// cache entry with the same probe fingerprint is reused under the new path.
+fn get_xattr_probe(file: &Path, signature_mode: &str) -> Result<Option<FFProbeOutput>> {
+    let (Some(signature), Some(probe_hash)) = (
+        xattr::get(file, XATTR_SIGNATURE)?,
+        xattr::get(file, XATTR_PROBE_HASH)?,
+    ) else {
+        return Ok(None);
+    };
+    if signature != get_file_signature(file, signature_mode)?.as_bytes() {
+        return Ok(None);
+    }
+    let probe_hash = String::from_utf8_lossy(&probe_hash);
+
+    let path_str = &cache_key(file)?;
+    if let Some(conn) = &*SQLITE_CACHE.lock().unwrap() {
+        let row: Option<(String, Option<i64>)> = conn
+            .query_row(
+                "SELECT probe_data, probed_at FROM entries WHERE probe_hash = ?1 LIMIT 1",
+                [probe_hash.as_ref()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((probe_data, probed_at)) = row else {
+            return Ok(None);
+        };
+        let now = now_secs();
+        let entry = CacheEntry {
+            signature: String::from_utf8_lossy(&signature).into_owned(),
+            probe_data: serde_json::from_str(&probe_data)?,
+            first_seen: Some(now),
+            last_seen: Some(now),
+            probed_at,
+        };
+        sqlite_put_entry(conn, path_str, &entry)?;
+        return Ok(Some(entry.probe_data));
+    }
+    let mut cache_guard = CACHE.lock().unwrap();
+    let Some(cache) = &mut *cache_guard else {
+        return Ok(None);
+    };
+    let Some(entry) = cache
+        .entries
+        .values()
+        .find(|entry| probe_fingerprint(&entry.probe_data).is_ok_and(|hash| hash == probe_hash))
+    else {
+        return Ok(None);
+    };
+
+    let mut entry = entry.clone();
+    entry.signature = String::from_utf8_lossy(&signature).into_owned();
+    entry.last_seen = Some(now_secs());
+    let record = JournalRecord {
+        path: path_str.to_string(),
+        entry,
+    };
+    append_to_cache_journal(&record)?;
+    let probe = record.entry.probe_data.clone();
+    cache.entries.insert(record.path, record.entry);
+    Ok(Some(probe))
+}
+
+/// Probes a file with the backend chosen by `--backend`.
+fn run_probe(file: &Path, settings: &ScanSettings) -> Result<FFProbeOutput> {
+    if is_subtitle_file(file) {
+        return probe_subtitle_file(file);
+    }
+    match settings.backend.as_str() {
+        "native" => native_probe(file),
+        _ if is_network_url(file) => probe_network_stream(file, settings),
+        _ => run_ffprobe(file, settings.timeout),
+    }
+}
+
+/// Results of the built-in parser are much sparser than ffprobe's, so they
+/// are only served from the cache while the native backend is in use.
+fn cache_usable(probe: &FFProbeOutput, settings: &ScanSettings) -> bool {
+    settings.backend == "native" || !probe.format.extra.contains_key("probe_backend")
+}
+
+/// Built-in fallback for when ffprobe isn't installed. Reads the container
+/// headers of MP4/MOV, Matroska/WebM and common audio files and returns
+/// them in ffprobe's shape, with only the fields the default columns need.
+fn native_probe(file: &Path) -> Result<FFProbeOutput> {
+    if is_stream_input(file) {
+        return Err(anyhow!(
+            "The native backend can't read streams, install ffprobe"
+        ));
+    }
+    let extension = media_extension(file).unwrap_or_default();
+    let (format_name, duration, streams) = match extension.as_str() {
+        "mp4" | "m4v" | "m4a" | "mov" | "qt" | "3gp" | "3g2" => native_probe_mp4(file)?,
+        "mkv" | "webm" => native_probe_matroska(file)?,
+        "mp3" | "wav" | "flac" | "ogg" | "opus" | "aac" => native_probe_audio(file, &extension)?,
+        _ => {
+            return Err(anyhow!(
+                "Unsupported container for the native backend: .{}",
+                extension
+            ))
+        }
+    };
+
+    let size = fs::metadata(file)?.len();
+    let mut format = serde_json::json!({
+        "filename": file.to_string_lossy(),
+        "format_name": format_name,
+        "size": size.to_string(),
+        "probe_backend": "native",
+    });
+    if let Some(duration) = duration.filter(|d| *d > 0.0) {
+        format["duration"] = serde_json::json!(format!("{:.6}", duration));
+        format["bit_rate"] =
+            serde_json::json!(((size as f64 * 8.0) / duration).round().to_string());
+    }
+    let streams: Vec<serde_json::Value> = streams
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut stream)| {
+            stream["index"] = serde_json::json!(index);
+            stream
+        })
+        .collect();
+    Ok(serde_json::from_value(serde_json::json!({
+        "streams": streams,
+        "format": format,
+    }))?)
+}
+
+/// Container format name, duration in seconds and ffprobe-shaped streams
+type NativeProbe = (String, Option<f64>, Vec<serde_json::Value>);
+
+fn native_probe_mp4(file: &Path) -> Result<NativeProbe> {
+    let reader = mp4::read_mp4(fs::File::open(file)?)?;
+    let mut tracks: Vec<&mp4::Mp4Track> = reader.tracks().values().collect();
+    tracks.sort_by_key(|track| track.track_id());
+
+    let mut streams = Vec::new();
+    for track in tracks {
+        let codec_name = track
+            .box_type()
+            .ok()
+            .map(|fourcc| match fourcc.to_string().as_str() {
+                "avc1" => "h264".to_string(),
+                "hev1" => "hevc".to_string(),
+                "vp09" => "vp9".to_string(),
+                "mp4a" => "aac".to_string(),
+                "tx3g" => "mov_text".to_string(),
+                other => other.to_string(),
+            });
+        let mut stream = match track.track_type() {
+            Ok(mp4::TrackType::Video) => {
+                let secs = track.duration().as_secs_f64();
+                let mut stream = serde_json::json!({
+                    "codec_type": "video",
+                    "width": track.width(),
+                    "height": track.height(),
+                });
+                if secs > 0.0 {
+                    let fps = track.sample_count() as f64 / secs;
+                    stream["r_frame_rate"] =
+                        serde_json::json!(format!("{}/1000", (fps * 1000.0).round()));
+                }
+                stream
+            }
+            Ok(mp4::TrackType::Audio) => {
+                let mut stream = serde_json::json!({ "codec_type": "audio" });
+                if let Ok(freq) = track.sample_freq_index() {
+                    stream["sample_rate"] = serde_json::json!(freq.freq().to_string());
+                }
+                if let Ok(channels) = track.channel_config() {
+                    stream["channels"] = serde_json::json!(channels as u8);
+                }
+                stream
+            }
+            Ok(mp4::TrackType::Subtitle) => serde_json::json!({ "codec_type": "subtitle" }),
+            Err(_) => continue,
+        };
+        stream["codec_name"] = serde_json::json!(codec_name);
+        if track.bitrate() > 0 {
+            stream["bit_rate"] = serde_json::json!(track.bitrate().to_string());
+        }
+        if !matches!(track.language(), "" | "und") {
+            stream["tags"] = serde_json::json!({ "language": track.language() });
+        }
+        streams.push(stream);
+    }
+    Ok((
+        "mov,mp4,m4a,3gp,3g2,mj2".to_string(),
+        Some(reader.duration().as_secs_f64()),
+        streams,
+    ))
+}
+
+fn native_probe_matroska(file: &Path) -> Result<NativeProbe> {
+    let mkv = matroska::open(file)?;
+    let mut streams = Vec::new();
+    for track in &mkv.tracks {
+        let codec_type = match track.tracktype {
+            matroska::Tracktype::Video => "video",
+            matroska::Tracktype::Audio => "audio",
+            matroska::Tracktype::Subtitle => "subtitle",
+            _ => continue,
+        };
+        let mut stream = serde_json::json!({
+            "codec_type": codec_type,
+            "codec_name": matroska_codec_name(&track.codec_id),
+            "disposition": {
+                "default": track.default as i32,
+                "forced": track.forced as i32,
+            },
+        });
+        match &track.settings {
+            matroska::Settings::Video(video) => {
+                stream["width"] = serde_json::json!(video.pixel_width);
+                stream["height"] = serde_json::json!(video.pixel_height);
+                if let Some(frame) = track.default_duration.filter(|d| !d.is_zero()) {
+                    stream["r_frame_rate"] =
+                        serde_json::json!(format!("1000000000/{}", frame.as_nanos()));
+                }
+            }
+            matroska::Settings::Audio(audio) => {
+                stream["sample_rate"] = serde_json::json!((audio.sample_rate as u64).to_string());
+                stream["channels"] = serde_json::json!(audio.channels);
+                if let Some(depth) = audio.bit_depth {
+                    stream["bits_per_raw_sample"] = serde_json::json!(depth.to_string());
+                }
+            }
+            matroska::Settings::None => {}
+        }
+        let mut tags = serde_json::Map::new();
+        if let Some(language) = &track.language {
+            tags.insert(
+                "language".to_string(),
+                serde_json::json!(language.to_string()),
+            );
+        }
+        if let Some(name) = &track.name {
+            tags.insert("title".to_string(), serde_json::json!(name));
+        }
+        if !tags.is_empty() {
+            stream["tags"] = serde_json::Value::Object(tags);
+        }
+        streams.push(stream);
+    }
+    Ok((
+        "matroska,webm".to_string(),
+        mkv.info.duration.map(|d| d.as_secs_f64()),
+        streams,
+    ))
+}
+
+/// ffprobe codec names for Matroska codec IDs (V_MPEG4/ISO/AVC -> h264)
+fn matroska_codec_name(codec_id: &str) -> String {
+    let name = match codec_id {
+        "V_MPEG4/ISO/AVC" => "h264",
+        "V_MPEGH/ISO/HEVC" => "hevc",
+        "V_AV1" => "av1",
+        "V_VP8" => "vp8",
+        "V_VP9" => "vp9",
+        "V_MPEG2" => "mpeg2video",
+        "V_MPEG4/ISO/ASP" => "mpeg4",
+        "A_AC3" => "ac3",
+        "A_EAC3" => "eac3",
+        "A_DTS" => "dts",
+        "A_TRUEHD" => "truehd",
+        "A_OPUS" => "opus",
+        "A_VORBIS" => "vorbis",
+        "A_FLAC" => "flac",
+        "A_MPEG/L3" => "mp3",
+        "A_PCM/INT/LIT" => "pcm_s16le",
+        "S_TEXT/UTF8" => "subrip",
+        "S_TEXT/ASS" | "S_TEXT/SSA" => "ass",
+        "S_TEXT/WEBVTT" => "webvtt",
+        "S_HDMV/PGS" => "hdmv_pgs_subtitle",
+        "S_VOBSUB" => "dvd_subtitle",
+        id if id.starts_with("A_AAC") => "aac",
+        id => {
+            return id
+                .split_once('_')
+                .map_or(id, |(_, rest)| rest)
+                .to_lowercase()
+        }
+    };
+    name.to_string()
+}
+
+fn native_probe_audio(file: &Path, extension: &str) -> Result<NativeProbe> {
+    use symphonia::core::codecs::CODEC_TYPE_OPUS;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    let source = MediaSourceStream::new(Box::new(fs::File::open(file)?), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension(extension);
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        source,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    let mut duration = None;
+    let mut streams = Vec::new();
+    for track in probed.format.tracks() {
+        let params = &track.codec_params;
+        let codec_name = match symphonia::default::get_codecs().get_codec(params.codec) {
+            Some(descriptor) => descriptor.short_name,
+            None if params.codec == CODEC_TYPE_OPUS => "opus",
+            None => "unknown",
+        };
+        let mut stream = serde_json::json!({
+            "codec_type": "audio",
+            "codec_name": codec_name,
+        });
+        if let Some(rate) = params.sample_rate {
+            stream["sample_rate"] = serde_json::json!(rate.to_string());
+        }
+        if let Some(channels) = params.channels {
+            stream["channels"] = serde_json::json!(channels.count());
+        }
+        if let Some(bits) = params.bits_per_sample {
+            stream["bits_per_raw_sample"] = serde_json::json!(bits.to_string());
+        }
+        if let (Some(time_base), Some(frames)) = (params.time_base, params.n_frames) {
+            let time = time_base.calc_time(frames);
+            duration.get_or_insert(time.seconds as f64 + time.frac);
+        }
+        streams.push(stream);
+    }
+    let format_name = match extension {
+        "opus" => "ogg",
+        other => other,
+    };
+    Ok((format_name.to_string(), duration, streams))
+}
+
+const SUBTITLE_EXTENSIONS: [&str; 5] = ["srt", "ass", "ssa", "vtt", "sup"];
+
+/// Standalone subtitle files (.srt, .ass, .vtt, .sup), which are not part of
+/// directory scans unless requested with --ext.
+fn is_subtitle_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SUBTITLE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Probe data of a standalone subtitle file: no video or audio, only
+/// subtitle streams.
+fn is_subtitle_probe(probe: &FFProbeOutput) -> bool {
+    !probe.streams.is_empty() && probe.streams.iter().all(|s| s.codec_type == "subtitle")
+}
+
+/// Reads a subtitle file directly, since ffprobe reports neither the number
+/// of events nor the language, which is usually only in the file name
+/// (`Movie.en.forced.srt`). Duration is the end of the last event.
+fn probe_subtitle_file(file: &Path) -> Result<FFProbeOutput> {
+    let extension = file
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let data = fs::read(file)?;
+    let (codec_name, events, duration) = if extension == "sup" {
+        let (events, duration) = pgs_events(&data);
+        ("hdmv_pgs_subtitle", events, duration)
+    } else {
+        let text = String::from_utf8_lossy(&data);
+        let (events, duration) = text_subtitle_events(&text, &extension);
+        let codec_name = match extension.as_str() {
+            "srt" => "subrip",
+            "vtt" => "webvtt",
+            _ => "ass",
+        };
+        (codec_name, events, duration)
+    };
+
+    let (language, flags) = subtitle_name_hints(file);
+    let mut stream = serde_json::json!({
+        "index": 0,
+        "codec_type": "subtitle",
+        "codec_name": codec_name,
+        "nb_events": events,
+        "disposition": {
+            "forced": flags.contains(&"forced") as i32,
+            "hearing_impaired": flags.contains(&"sdh") as i32,
+        },
+    });
+    if let Some(language) = language {
+        stream["tags"] = serde_json::json!({ "language": language });
+    }
+    let mut format = serde_json::json!({
+        "filename": file.to_string_lossy(),
+        "format_name": extension,
+        "size": data.len().to_string(),
+    });
+    if duration > 0.0 {
+        format["duration"] = serde_json::json!(format!("{:.6}", duration));
+    }
+    Ok(serde_json::from_value(serde_json::json!({
+        "streams": [stream],
+        "format": format,
+    }))?)
+}
+
+/// Event count and end of the last event of a text subtitle file.
+fn text_subtitle_events(text: &str, extension: &str) -> (u64, f64) {
+    let mut events = 0;
+    let mut end: f64 = 0.0;
+    for line in text.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        let event_end = match extension {
+            // Dialogue: 0,0:00:01.00,0:00:04.00,Default,,0,0,0,,Text
+            "ass" | "ssa" => match line.strip_prefix("Dialogue:") {
+                Some(rest) => rest.split(',').nth(2).and_then(parse_subtitle_timestamp),
+                None => continue,
+            },
+            // 00:00:01,000 --> 00:00:04,000 (WebVTT may add cue settings after it)
+            _ => match line.split_once("-->") {
+                Some((_, rest)) => rest
+                    .split_whitespace()
+                    .next()
+                    .and_then(parse_subtitle_timestamp),
+                None => continue,
+            },
+        };
+        events += 1;
+        end = end.max(event_end.unwrap_or(0.0));
+    }
+    (events, end)
+}
+
+/// Seconds of a subtitle timestamp: `01:02:03,456`, `1:02:03.45` or `02:03.456`.
+fn parse_subtitle_timestamp(timestamp: &str) -> Option<f64> {
+    let timestamp = timestamp.trim().replace(',', ".");
+    let parts: Vec<&str> = timestamp.split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [h, m, s] => (
+            h.parse::<f64>().ok()?,
+            m.parse::<f64>().ok()?,
+            s.parse::<f64>().ok()?,
+        ),
+        [m, s] => (0.0, m.parse::<f64>().ok()?, s.parse::<f64>().ok()?),
+        _ => return None,
+    };
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Number of subtitles shown and the last presentation time of a PGS (.sup)
+/// stream. Each segment starts with "PG", 32-bit PTS and DTS at 90 kHz, a
+/// type byte and a 16-bit size; a presentation composition segment (0x16)
+/// with at least one object starts a new subtitle.
+fn pgs_events(data: &[u8]) -> (u64, f64) {
+    let mut events = 0;
+    let mut last_pts = 0u32;
+    let mut offset = 0;
+    while offset + 13 <= data.len() && &data[offset..offset + 2] == b"PG" {
+        let pts = u32::from_be_bytes(data[offset + 2..offset + 6].try_into().unwrap());
+        let segment_type = data[offset + 10];
+        let size = u16::from_be_bytes([data[offset + 11], data[offset + 12]]) as usize;
+        let payload = &data[(offset + 13).min(data.len())..(offset + 13 + size).min(data.len())];
+        // Composition objects count at byte 10 of the composition segment
+        if segment_type == 0x16 && payload.get(10).is_some_and(|&objects| objects > 0) {
+            events += 1;
+        }
+        last_pts = last_pts.max(pts);
+        offset += 13 + size;
+    }
+    (events, last_pts as f64 / 90_000.0)
+}
+
+/// Language code and flags guessed from the dotted suffixes of a subtitle
+/// file name, e.g. `Movie (2020).pt-BR.forced.srt` -> ("pt-BR", ["forced"]).
+fn subtitle_name_hints(file: &Path) -> (Option<String>, Vec<&'static str>) {
+    let stem = file
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let mut language = None;
+    let mut flags = Vec::new();
+    // Only trailing parts; the title itself may contain dots
+    for part in stem.rsplit('.').take(3) {
+        match part.to_lowercase().as_str() {
+            "forced" => flags.push("forced"),
+            "sdh" | "hi" | "cc" => flags.push("sdh"),
+            "default" => {}
+            lower => {
+                let code = lower.split(['-', '_']).next().unwrap_or_default();
+                if language.is_none() && SUBTITLE_LANGUAGE_CODES.contains(&code) {
+                    language = Some(part.to_string());
+                }
+                break;
+            }
+        }
+    }
+    (language, flags)
+}
+
+/// ISO 639-1 and 639-2 codes accepted as a language in subtitle file names,
+/// limited to common ones so words like "dts" or "eng1" aren't mistaken for one.
+const SUBTITLE_LANGUAGE_CODES: [&str; 59] = [
+    "ar", "ara", "bg", "bul", "cs", "cze", "ces", "da", "dan", "de", "ger", "deu", "el", "gre",
+    "ell", "en", "eng", "es", "spa", "fi", "fin", "fr", "fre", "fra", "he", "heb", "hin", "hu",
+    "hun", "it", "ita", "ja", "jpn", "ko", "kor", "nl", "dut", "nld", "no", "nor", "pl", "pol",
+    "pt", "por", "ro", "rum", "ron", "ru", "rus", "sv", "swe", "th", "tha", "tr", "tur", "uk",
+    "ukr", "zh", "chi",
+];
+
+fn run_ffprobe(file: &Path, timeout: Option<Duration>) -> Result<FFProbeOutput> {
+    let mut command = Command::new("ffprobe");
+    let input = if file == Path::new("-") {
+        command.stdin(Stdio::inherit());
+        "pipe:0"
+    } else {
+        file.to_str().ok_or_else(|| anyhow!("Invalid file path"))?
+    };
+    command.args([
+        "-v",
+        "error",
+        "-print_format",
+        "json",
+        "-show_format",
+        "-show_streams",
+        input,
+    ]);
+    let output = run_with_timeout(command, timeout)?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Inputs that are read as a stream rather than a file: `-` for stdin, and
+/// `/dev/fd/N` or named pipes, e.g. from process substitution (`<(cmd)`).
+fn is_stream_input(path: &Path) -> bool {
+    if path == Path::new("-")
+        || path.starts_with("/dev/fd")
+        || path.starts_with("/proc/self/fd")
+        || is_network_url(path)
+    {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if fs::metadata(path).is_ok_and(|m| m.file_type().is_fifo()) {
+            return true;
+        }
+    }
+    false
+}
+
+/// URL schemes of live network streams (IP cameras, IPTV multicast)
+const NETWORK_SCHEMES: [&str; 5] = ["rtsp", "rtsps", "rtp", "udp", "srt"];
+
+/// Inputs like `rtsp://camera/stream1` or `udp://@239.0.0.1:1234`.
+fn is_network_url(path: &Path) -> bool {
+    path.to_str()
+        .and_then(|url| url.split_once("://"))
+        .is_some_and(|(scheme, _)| NETWORK_SCHEMES.contains(&scheme.to_lowercase().as_str()))
+}
+
+/// Probes a live network stream. Only `stream_window` of it is read, and
+/// since live streams carry no overall bitrate, it is measured from the
+/// packets seen in that window. Unless `--timeout` is set, ffprobe is
+/// stopped 15 seconds after the window, so a dead stream doesn't hang the scan.
+fn probe_network_stream(url: &Path, settings: &ScanSettings) -> Result<FFProbeOutput> {
+    let window = settings.stream_window;
+    let mut command = Command::new("ffprobe");
+    command.args(["-v", "error"]);
+    if url.to_string_lossy().to_lowercase().starts_with("rtsp") {
+        // Many cameras drop or reorder UDP packets; interleaved TCP is reliable
+        command.args(["-rtsp_transport", "tcp"]);
+    }
+    command
+        .args([
+            "-analyzeduration",
+            &window.as_micros().to_string(),
+            "-read_intervals",
+            &format!("%+{}", window.as_secs()),
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+            "-show_entries",
+            "packet=size,pts_time",
+        ])
+        .arg(url);
+    let timeout = settings.timeout.unwrap_or(window + Duration::from_secs(15));
+    let output = run_with_timeout(command, Some(timeout))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let mut value: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let packets = value
+        .as_object_mut()
+        .and_then(|object| object.remove("packets"))
+        .unwrap_or_default();
+    let mut probe: FFProbeOutput = serde_json::from_value(value)?;
+    let packets = packets.as_array().map(Vec::as_slice).unwrap_or_default();
+    let number = |packet: &serde_json::Value, key: &str| {
+        packet[key].as_str().and_then(|v| v.parse::<f64>().ok())
+    };
+    let bytes: f64 = packets.iter().filter_map(|p| number(p, "size")).sum();
+    let times: Vec<f64> = packets
+        .iter()
+        .filter_map(|p| number(p, "pts_time"))
+        .collect();
+    let span = times.iter().copied().fold(f64::MIN, f64::max)
+        - times.iter().copied().fold(f64::MAX, f64::min);
+    let seconds = if span > 0.0 {
+        span
+    } else {
+        window.as_secs_f64()
+    };
+    if probe.format.bit_rate.is_none() && bytes > 0.0 {
+        probe.format.bit_rate = Some(((bytes * 8.0 / seconds).round()).to_string());
+    }
+    probe
+        .format
+        .extra
+        .insert("live".to_string(), serde_json::json!(true));
+    Ok(probe)
+}
+
+/// Runs a command to completion, killing it if it exceeds `timeout`.
+/// Timeouts surface as `ErrorKind::TimedOut` so they are retried as transient.
+fn run_with_timeout(mut command: Command, timeout: Option<Duration>) -> Result<Output> {
+    let Some(timeout) = timeout else {
+        return Ok(command.output()?);
+    };
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Drain the pipes while waiting so a chatty child can't block on a full pipe
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stdout_reader = std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = stdout.read_to_end(&mut buffer);
+        buffer
+    });
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = stderr.read_to_end(&mut buffer);
+        buffer
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("timed out after {}s", timeout.as_secs()),
+            )
+            .into());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout_reader.join().unwrap_or_default(),
+        stderr: stderr_reader.join().unwrap_or_default(),
+    })
+}
+
+/// Whether a probe failure is likely to succeed on retry (flaky mounts, timeouts),
+/// as opposed to deterministic failures such as corrupt or unsupported files.
+fn is_transient_error(err: &anyhow::Error) -> bool {
+    if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+        return matches!(
+            io_err.kind(),
+            std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::Interrupted
+                | std::io::ErrorKind::WouldBlock
+                | std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+        ) || matches!(io_err.raw_os_error(), Some(5) | Some(116)); // EIO, ESTALE
+    }
+
+    const TRANSIENT_MESSAGES: [&str; 6] = [
+        "Input/output error",
+        "Resource temporarily unavailable",
+        "Stale file handle",
+        "Connection timed out",
+        "Connection reset",
+        "Interrupted system call",
+    ];
+    let message = err.to_string();
+    TRANSIENT_MESSAGES.iter().any(|m| message.contains(m))
+}
+
+fn truncate_middle(s: &str, max_len: usize) -> String {
+    if s.chars().count() <= max_len {
+        return s.to_string();
+    }
+
+    let ellipsis = "...";
+    let side_len = (max_len - ellipsis.len()) / 2;
+
+    let left: String = s.chars().take(side_len).collect();
+    let right: String = s
+        .chars()
+        .rev()
+        .take(side_len)
+        .collect::<String>()
+        .chars()
+        .rev()
+        .collect();
+
+    format!("{}{}{}", left, ellipsis, right)
+}
+
+fn format_probe_output(
+    file: &Path,
+    probe: &FFProbeOutput,
+    options: &DisplayOptions,
+) -> Result<HashMap<&'static str, String>> {
+    let mut fields = HashMap::new();
+
+    // Full filename, only the table shortens it (see --filename-length);
+    // stream URLs have no meaningful last component, so they are shown whole
+    let filename = match is_network_url(file) {
+        true => file.to_str(),
+        false => file.file_name().and_then(|n| n.to_str()),
+    };
+    fields.insert("filename", filename.unwrap_or("Unknown").to_string());
+
+    // Get duration
+    fields.insert("duration", format_duration(&probe.format.duration));
+
+    // Get file size
+    fields.insert("size", format_size(&probe.format.size));
+
+    // Get bitrate from format (more reliable than video stream bitrate)
+    let bitrate = probe
+        .format
+        .bit_rate
+        .as_deref()
+        .and_then(|b| b.parse::<f64>().ok())
+        .map(|b| format!("{:.2} Mbps", b / 1_000_000.0))
+        .unwrap_or_default();
+    fields.insert("bitrate", bitrate);
+
+    // Find video stream
+    if let Some(video) = primary_video(probe) {
+        // Get FPS
+        let fps = video
+            .r_frame_rate
+            .as_deref()
+            .and_then(|r| {
+                let parts: Vec<&str> = r.split('/').collect();
+                if parts.len() == 2 {
+                    parts[0].parse::<f64>().ok().and_then(|num| {
+                        parts[1].parse::<f64>().ok().map(|den| {
+                            if den != 0.0 {
+                                format!("{:.2}", num / den)
+                            } else {
+                                String::new()
+                            }
+                        })
+                    })
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default();
+        fields.insert("fps", fps);
+
+        // Get resolution
+        let width = video.width.unwrap_or(0);
+        let height = video.height.unwrap_or(0);
+        let aspect = video.display_aspect_ratio.as_deref().unwrap_or("");
+        let resolution = if aspect.is_empty() {
+            format!("{}x{}", width, height)
+        } else {
+            format!("{}x{} ({})", width, height, aspect)
+        };
+        fields.insert("resolution", resolution);
+        if let Some(ratio) = display_aspect(video) {
+            fields.insert("aspect", format_aspect(ratio));
+            fields.insert("orientation", orientation(ratio).to_string());
+        }
+
+        // Get codec name
+        fields.insert("format", video.codec_name.clone().unwrap_or_default());
+
+        // Get profile
+        fields.insert("profile", video.profile.clone().unwrap_or_default());
+
+        // Get bit depth
+        fields.insert("depth", get_bit_depth(video.pix_fmt.as_deref()));
+
+        // Get color info
+        let color_info = match (video.color_space.as_deref(), video.color_range.as_deref()) {
+            (Some(space), Some(range)) => format!("{} {}", space, range),
+            (Some(space), None) => space.to_string(),
+            (None, Some(range)) => range.to_string(),
+            (None, None) => String::new(),
+        };
+        fields.insert("color", color_info);
+
+        fields.insert("hdr", hdr_format(video).to_string());
+        fields.insert("stereo", stereo_mode(video).unwrap_or_default());
+    }
+
+    // Cover art dimensions, e.g. to find music with low-resolution artwork
+    if let Some(cover) = probe.streams.iter().find(|s| is_attached_pic(s)) {
+        let dimensions = match (cover.width, cover.height) {
+            (Some(width), Some(height)) => format!("{}x{}", width, height),
+            _ => String::new(),
+        };
+        let codec = cover.codec_name.as_deref().unwrap_or_default();
+        fields.insert(
+            "cover",
+            format!("{} {}", dimensions, codec).trim().to_string(),
+        );
+    }
+
+    if let Some(rating) = content_rating(probe) {
+        fields.insert("rating", rating);
+    }
+
+    if let Some(title) = format_tag(probe, &["title"]) {
+        fields.insert("title", title.to_string());
+    }
+
+    if options.columns.contains(&"thumbnail") {
+        if let Some(thumbnail) = thumbnail_path(file).filter(|path| path.exists()) {
+            fields.insert("thumbnail", thumbnail.display().to_string());
+        }
+    }
+
+    if let Some(score) = reencode_priority(probe) {
+        fields.insert("reencode", format!("{:.0}", score));
+    }
+    if let Some(bpp) = bits_per_pixel(probe) {
+        fields.insert("bpp", format!("{:.3}", bpp));
+    }
+
+    // Find audio stream
+    if let Some(audio) = primary_audio(probe) {
+        let channels = format!("{}CH", audio.channels.unwrap_or(0));
+        let bitrate = audio
+            .bit_rate
+            .as_deref()
+            .and_then(|b| b.parse::<f64>().ok())
+            .map(|b| format!(" {:.0}k", b / 1000.0))
+            .unwrap_or_default();
+        fields.insert("audio", format!("{}{}", channels, bitrate));
+
+        fields.insert("audio_codec", audio.codec_name.clone().unwrap_or_default());
+        let sample_rate = audio
+            .sample_rate
+            .as_deref()
+            .and_then(|r| r.parse::<f64>().ok())
+            .map(|r| format!("{} kHz", r / 1000.0))
+            .unwrap_or_default();
+        fields.insert("sample_rate", sample_rate);
+        let channels = match (&audio.channel_layout, audio.channels) {
+            (Some(layout), _) => layout.clone(),
+            (None, Some(channels)) => format!("{}CH", channels),
+            (None, None) => String::new(),
+        };
+        fields.insert("channels", channels);
+        fields.insert("audio_depth", audio_bit_depth(audio).unwrap_or_default());
+        // Lossless codecs often only report the overall bitrate
+        let only_stream = probe.streams.len() == 1;
+        let audio_bitrate = audio
+            .bit_rate
+            .as_deref()
+            .or(probe.format.bit_rate.as_deref().filter(|_| only_stream))
+            .and_then(|b| b.parse::<f64>().ok())
+            .map(|b| format!("{:.0}k", b / 1000.0))
+            .unwrap_or_default();
+        fields.insert("audio_bitrate", audio_bitrate);
+    }
+    let audio_tracks = probe
+        .streams
+        .iter()
+        .filter(|s| s.codec_type == "audio")
+        .count();
+    fields.insert("audio_tracks", audio_tracks.to_string());
+
+    let streams_of = |codec_type: &str, summary: fn(&Stream) -> String| {
+        probe
+            .streams
+            .iter()
+            .filter(|s| s.codec_type == codec_type)
+            .map(summary)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    fields.insert("audio_streams", streams_of("audio", audio_stream_summary));
+    let mut audio_languages: Vec<String> = Vec::new();
+    for stream in probe.streams.iter().filter(|s| s.codec_type == "audio") {
+        if let Some(language) = stream_language(stream).map(str::to_lowercase) {
+            if !audio_languages.contains(&language) {
+                audio_languages.push(language);
+            }
+        }
+    }
+    fields.insert("audio_lang", audio_languages.join(","));
+    fields.insert("subtitles", streams_of("subtitle", subtitle_stream_summary));
+    let events = probe
+        .streams
+        .iter()
+        .filter_map(|s| s.extra.get("nb_events").and_then(|n| n.as_u64()))
+        .sum::<u64>();
+    fields.insert(
+        "events",
+        match events {
+            0 => String::new(),
+            events => events.to_string(),
+        },
+    );
+
+    let forced_subs = probe
+        .streams
+        .iter()
+        .any(|s| s.codec_type == "subtitle" && is_forced_subtitle(s));
+    fields.insert(
+        "forced_subs",
+        if forced_subs { "yes" } else { "no" }.to_string(),
+    );
+    fields.insert("telemetry", telemetry_streams(probe).join(","));
+    fields.insert("timecode", start_timecode(probe).unwrap_or_default());
+    fields.insert("encoder", encoder_name(probe).unwrap_or_default());
+
+    if options.columns.contains(&"tags") {
+        fields.insert("tags", file_tags(file).join(","));
+    }
+
+    if options.columns.contains(&"modified") {
+        fields.insert(
+            "modified",
+            format_date(file_modified_secs(file), &options.dates),
+        );
+    }
+    fields.insert("created", format_date(creation_secs(probe), &options.dates));
+
+    if options.columns.contains(&"id") {
+        fields.insert("id", file_id(file));
+    }
+
+    if options.columns.contains(&"first_seen") || options.columns.contains(&"last_seen") {
+        let (first_seen, last_seen) = cache_seen_times(file);
+        fields.insert("first_seen", format_date(first_seen, &options.dates));
+        fields.insert("last_seen", format_date(last_seen, &options.dates));
+    }
+
+    if let Some(reference) = &options.reference {
+        let key = relative_to(file, &reference.current_root);
+        match reference.entries.get(&key) {
+            Some(other) => {
+                let size = |p: &FFProbeOutput| p.format.size.parse::<f64>().ok();
+                let bitrate = |p: &FFProbeOutput| p.format.bit_rate.as_deref()?.parse::<f64>().ok();
+                fields.insert(
+                    "size_delta",
+                    format_delta(size(probe), size(other), |d| {
+                        let sign = if d < 0.0 { "-" } else { "+" };
+                        format!("{}{}", sign, format_size(&(d.abs() as u64).to_string()))
+                    }),
+                );
+                fields.insert(
+                    "bitrate_delta",
+                    format_delta(bitrate(probe), bitrate(other), |d| {
+                        format!("{:+.2} Mbps", d / 1_000_000.0)
+                    }),
+                );
+            }
+            None => {
+                fields.insert("size_delta", "new".to_string());
+                fields.insert("bitrate_delta", "new".to_string());
+            }
+        }
+    }
+
+    Ok(fields)
+}
+
+/// Parses an audio bitrate column value ("320k") into kbps.
+fn parse_audio_bitrate(bitrate_str: &str) -> Option<f64> {
+    bitrate_str.trim_end_matches('k').parse::<f64>().ok()
+}
+
+fn parse_bitrate(bitrate_str: &str) -> Option<f64> {
+    bitrate_str
+        .split_whitespace()
+        .next()
+        .and_then(|s| s.parse::<f64>().ok())
+}
+
+/// Suffixes browsers, download managers and transfer tools add to a file
+/// until it is complete, e.g. `movie.mkv.part`
+const PARTIAL_SUFFIXES: [&str; 8] = [
+    "part",
+    "partial",
+    "crdownload",
+    "download",
+    "opdownload",
+    "filepart",
+    "!qb",
+    "!ut",
+];
+
+/// The lowercase extension a media file is recognized by. Cameras write
+/// `.MOV`/`.MTS`, files copied from some NAS shares keep trailing spaces or
+/// dots (`clip.mp4 `), and a partial download suffix is looked through, so
+/// `movie.mkv.part` is an "mkv".
+fn media_extension(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    let name = name.trim_end_matches([' ', '.']);
+    let (stem, ext) = name.rsplit_once('.')?;
+    if PARTIAL_SUFFIXES.contains(&ext) {
+        let (_, inner) = stem.rsplit_once('.')?;
+        return Some(inner.to_string());
+    }
+    Some(ext.to_string())
+}
+
+/// Files still being downloaded: named with a `PARTIAL_SUFFIXES` suffix, or
+/// with an aria2 control file (`movie.mkv.aria2`) next to them.
+fn is_partial_download(path: &Path) -> bool {
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy().to_lowercase()) else {
+        return false;
+    };
+    let suffix = name.trim_end_matches([' ', '.']).rsplit_once('.');
+    if suffix.is_some_and(|(_, ext)| PARTIAL_SUFFIXES.contains(&ext)) {
+        return true;
+    }
+    let mut control = path.as_os_str().to_owned();
+    control.push(".aria2");
+    Path::new(&control).exists()
+}
+
+fn is_skipped_partial(path: &Path, settings: &ScanSettings) -> bool {
+    !settings.include_partial && is_partial_download(path)
+}
+
+fn is_media_file(path: &Path) -> bool {
+    let media_extensions = [
+        "mp4", "mkv", "avi", "mov", "wmv", "flv", "webm", "m4v", "mpg", "mpeg", "m2v", "m4v",
+        "3gp", "3g2", "mxf", "ts", "mts", "m2ts", "vob", "ogv", "qt", "rm", "rmvb", "asf", "mp3",
+        "wav", "flac", "m4a", "aac", "ogg", "wma", "opus",
+    ];
+
+    media_extension(path).is_some_and(|ext| media_extensions.contains(&ext.as_str()))
+}
+
+fn parse_size(size_str: &str) -> u64 {
+    let parts: Vec<&str> = size_str.split_whitespace().collect();
+    if parts.len() != 2 {
+        return 0;
+    }
+
+    let value: f64 = parts[0].parse().unwrap_or(0.0);
+    match parts[1] {
+        "GB" => (value * 1024.0 * 1024.0 * 1024.0) as u64,
+        "MB" => (value * 1024.0 * 1024.0) as u64,
+        "KB" => (value * 1024.0) as u64,
+        "B" => value as u64,
+        _ => 0,
+    }
+}
+
+/// Size filter values: a number of bytes or a number with a unit (B, KB,
+/// MB, GB, TB; case-insensitive, the space and the trailing B are optional).
+fn parse_size_value(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let number: f64 = value[..split].trim().parse().ok()?;
+    let multiplier: u64 = match value[split..].to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1 << 10,
+        "m" | "mb" => 1 << 20,
+        "g" | "gb" => 1 << 30,
+        "t" | "tb" => 1 << 40,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+fn get_cached_files() -> Result<Vec<(PathBuf, FFProbeOutput)>> {
+    if let Some(conn) = &*SQLITE_CACHE.lock().unwrap() {
+        let mut statement = conn.prepare("SELECT path, probe_data FROM entries")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut files = Vec::new();
+        for row in rows {
+            let (path, probe_data) = row?;
+            files.push((
+                local_path(Path::new(&path)),
+                serde_json::from_str(&probe_data)?,
+            ));
+        }
+        return Ok(files);
+    }
+
+    debug!("Loading cache file...");
+    if let Some(cache) = &*CACHE.lock().unwrap() {
+        debug!("Found {} entries in cache", cache.entries.len());
+        return Ok(cache
+            .entries
+            .iter()
+            .map(|(path, entry)| (local_path(Path::new(path)), entry.probe_data.clone()))
+            .collect());
+    }
+
+    // Only the probe results are needed here, so stream them out of the file
+    // rather than loading the whole cache
+    let cache_path = get_cache_file()?;
+    debug!("Reading cache from: {}", cache_path.display());
+    let mut probes = match cache_path.exists() {
+        true => {
+            debug!(
+                "Cache file size: {} bytes",
+                fs::metadata(&cache_path)?.len()
+            );
+            read_cache_file::<CachedProbes>(&cache_path)
+                .map(|cached| cached.entries)
+                .unwrap_or_else(|e| {
+                    warn!("Failed to parse cache: {}", e);
+                    HashMap::new()
+                })
+        }
+        false => HashMap::new(),
+    };
+    for record in read_cache_journal()? {
+        let probe_data = record.entry.probe_data;
+        probes.insert(record.path, CachedProbe { probe_data });
+    }
+    debug!("Found {} entries in cache", probes.len());
+    Ok(probes
+        .into_iter()
+        .map(|(path, cached)| (local_path(Path::new(&path)), cached.probe_data))
+        .collect())
+}
+
+/// A row must match every `--filter` (AND) and, if any were given, at
+/// least one `--filter-any` (OR).
+fn should_include_row(
+    row: &ResultRow,
+    filters: &[String],
+    any_filters: &[String],
+    ignore_diacritics: bool,
+    audio_only: bool,
+) -> Result<bool> {
+    for filter in filters {
+        if !filter_matches(row, filter, ignore_diacritics, audio_only)? {
+            return Ok(false);
+        }
+    }
+    if any_filters.is_empty() {
+        return Ok(true);
+    }
+    for filter in any_filters {
+        if filter_matches(row, filter, ignore_diacritics, audio_only)? {
+            return Ok(true);
+        }
     }
+    Ok(false)
+}
 
-    // Print the table
-    table.printstd();
+/// Filter columns known before a file is probed, from its path and metadata
+const PRE_PROBE_FILTER_COLUMNS: [&str; 3] = ["filename", "size", "modified"];
 
-    Ok(())
-}
+/// Whether a file can be left out without probing it: it fails a `--filter`
+/// on a PRE_PROBE_FILTER_COLUMNS column, or every `--filter-any` is on such a
+/// column and none of them matches. Other filters are checked after probing.
+fn excluded_before_probe(
+    file: &Path,
+    filters: &[String],
+    any_filters: &[String],
+    ignore_diacritics: bool,
+) -> Result<bool> {
+    let pre_probe = |filter: &String| {
+        parse_filter_expr(filter)
+            .is_ok_and(|(_, column, _, _)| PRE_PROBE_FILTER_COLUMNS.contains(&column))
+    };
+    let filters: Vec<&String> = filters.iter().filter(|f| pre_probe(f)).collect();
+    let any_decidable = !any_filters.is_empty() && any_filters.iter().all(pre_probe);
+    if filters.is_empty() && !any_decidable {
+        return Ok(false);
+    }
+    let Ok(metadata) = fs::metadata(file) else {
+        // Let the probe report it
+        return Ok(false);
+    };
 
-fn get_file_signature(path: &PathBuf) -> Result<String> {
-    let metadata = fs::metadata(path)?;
     let size = metadata.len();
-    let modified = metadata
-        .modified()?
-        .duration_since(SystemTime::UNIX_EPOCH)?
-        .as_secs();
-    Ok(format!("{}-{}", size, modified))
+    let probe = FFProbeOutput {
+        streams: Vec::new(),
+        format: Format {
+            filename: file.to_string_lossy().to_string(),
+            size: size.to_string(),
+            duration: String::new(),
+            bit_rate: None,
+            tags: None,
+            extra: serde_json::Map::new(),
+        },
+    };
+    let filename = file.file_name().unwrap_or_default().to_string_lossy();
+    let mut values = HashMap::from([
+        ("filename", filename.to_string()),
+        ("size", format_size(&probe.format.size)),
+    ]);
+    let mut numbers = HashMap::from([("size", size as f64)]);
+    if let Some(modified) = file_modified_secs(file) {
+        values.insert("modified", format_date(Some(modified), "iso"));
+        numbers.insert("modified", modified as f64);
+    }
+    let row = ResultRow {
+        path: file.to_path_buf(),
+        probe,
+        values,
+        numbers,
+    };
+
+    for filter in filters {
+        if !filter_matches(&row, filter, ignore_diacritics, false)? {
+            return Ok(true);
+        }
+    }
+    if any_decidable {
+        for filter in any_filters {
+            if filter_matches(&row, filter, ignore_diacritics, false)? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+    Ok(false)
 }
 
-fn get_cache_dir() -> Result<PathBuf> {
-    let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not find home directory"))?;
-    let cache_dir = home.join(".mediainfo").join("cache");
-    fs::create_dir_all(&cache_dir)?;
-    Ok(cache_dir)
+fn filter_matches(
+    row: &ResultRow,
+    filter: &str,
+    ignore_diacritics: bool,
+    audio_only: bool,
+) -> Result<bool> {
+    let (negated, column, op, value) =
+        parse_filter_expr(filter).map_err(|e| anyhow!("Invalid filter '{}': {}", filter, e))?;
+    // In audio mode 'bitrate<200' means the audio stream in kbps
+    let column = match column {
+        "bitrate" if audio_only => "audio_bitrate",
+        column => column,
+    };
+    let field = row_value(row, column);
+    let number = row.numbers.get(column).copied();
+
+    let matches = match op {
+        "<" | ">" | "<=" | ">=" => match numeric_filter_values(column, number, field, value) {
+            Some((actual, threshold)) => match op {
+                "<" => actual < threshold,
+                ">" => actual > threshold,
+                "<=" => actual <= threshold,
+                _ => actual >= threshold,
+            },
+            None => true,
+        },
+        // One of the comma-separated items equals the value, e.g. audio_lang:has:jpn
+        "has" => value.split(',').any(|wanted| {
+            let wanted = wanted.trim().to_lowercase();
+            field
+                .split(',')
+                .any(|item| language_matches(&item.trim().to_lowercase(), &wanted))
+        }),
+        // A comma list matches any of its values, e.g. format=h264,mpeg4,vc1
+        _ => value
+            .split(',')
+            .any(|v| equals_filter_matches(column, number, field, v.trim(), ignore_diacritics)),
+    };
+    Ok(matches != negated)
 }
 
-fn get_path_hash(path: &str) -> String {
-    let mut hasher = XxHash64::default();
-    hasher.write(path.as_bytes());
-    format!("{:016x}", hasher.finish())
+const FILTER_OPERATORS: [&str; 7] = ["=", "!=", "<=", ">=", "<", ">", "has"];
+
+/// Filter columns with a numeric ordering, the only ones usable with `<` and `>`
+const NUMERIC_FILTER_COLUMNS: [&str; 20] = [
+    "events",
+    "aspect",
+    "created",
+    "modified",
+    "reencode",
+    "bpp",
+    "size",
+    "duration",
+    "fps",
+    "bitrate",
+    "audio_bitrate",
+    "resolution",
+    "depth",
+    "audio",
+    "audio_tracks",
+    "sample_rate",
+    "audio_depth",
+    "segments",
+    "size_delta",
+    "bitrate_delta",
+];
+
+/// Text columns listing several items, where `=` matches any part of the value
+const CONTAINS_FILTER_COLUMNS: [&str; 7] = [
+    "filename",
+    "audio_streams",
+    "audio_lang",
+    "subtitles",
+    "telemetry",
+    "encoder",
+    "title",
+];
+
+/// Whether any `--filter`, `--filter-any` or `--fail-if` filter is on `column`.
+fn filters_on(args: &Args, column: &str) -> bool {
+    args.filter
+        .iter()
+        .chain(&args.filter_any)
+        .chain(&args.fail_if)
+        .any(|filter| parse_filter_expr(filter).is_ok_and(|(_, name, _, _)| name == column))
 }
 
-fn load_cache() -> Result<Cache> {
-    let cache_path = get_cache_file()?;
-    eprintln!("Reading cache from: {}", cache_path.display());
-    if cache_path.exists() {
-        let content = fs::read_to_string(&cache_path)?;
-        eprintln!("Cache file size: {} bytes", content.len());
-        Ok(serde_json::from_str(&content).unwrap_or_else(|e| {
-            eprintln!("Error parsing cache: {}", e);
-            Cache {
-                entries: HashMap::new(),
-            }
-        }))
-    } else {
-        eprintln!("Cache file does not exist");
-        Ok(Cache {
-            entries: HashMap::new(),
-        })
-    }
+/// Parses a complete filter into (negated, column, operator, value), where
+/// a leading '!' or 'not:' negates the filter, and the `!=` operator becomes
+/// a negated `=`.
+fn parse_filter_expr(filter: &str) -> Result<(bool, &str, &str, &str)> {
+    let (negated, filter) = match filter.strip_prefix('!').or(filter.strip_prefix("not:")) {
+        Some(rest) => (true, rest),
+        None => (false, filter),
+    };
+    let (column, op, value) = parse_filter(filter)?;
+    let column = match column_def(resolve_column(column)) {
+        Some(def) => def.id,
+        None => {
+            return Err(anyhow!(
+                "unknown column '{}' (expected one of: {})",
+                column,
+                COLUMN_DEFS
+                    .iter()
+                    .map(|c| c.id)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        }
+    };
+    Ok(match op {
+        "!=" => (!negated, column, "=", value),
+        op => (negated, column, op, value),
+    })
 }
 
-fn save_cache(cache: &Cache) -> Result<()> {
-    let cache_path = get_cache_file()?;
-    let content = serde_json::to_string_pretty(cache)?;
-    fs::write(cache_path, content)?;
+/// Checks every filter up front, so a typo fails before a long scan instead
+/// of silently matching everything.
+fn validate_filters(filters: &[String]) -> Result<()> {
+    for filter in filters {
+        let invalid = |reason: String| anyhow!("Invalid filter '{}': {}", filter, reason);
+        let (_, column, op, value) =
+            parse_filter_expr(filter).map_err(|e| invalid(e.to_string()))?;
+        if column == "share" {
+            return Err(invalid(
+                "'share' depends on which files are displayed, so it can't be filtered on"
+                    .to_string(),
+            ));
+        }
+        if !matches!(op, "=" | "has") && !NUMERIC_FILTER_COLUMNS.contains(&column) {
+            return Err(invalid(format!(
+                "operator '{}' needs a numeric column ({}), not '{}'",
+                op,
+                NUMERIC_FILTER_COLUMNS.join(", "),
+                column
+            )));
+        }
+        // `has` compares list items as text, whatever the column
+        if op == "has" {
+            if value.split(',').any(|item| item.trim().is_empty()) {
+                return Err(invalid("missing value".to_string()));
+            }
+            continue;
+        }
+        let values: Vec<&str> = if op == "=" {
+            value.split(',').map(str::trim).collect()
+        } else {
+            vec![value.trim()]
+        };
+        for value in values {
+            // An empty text value matches files where the column is empty
+            if value.is_empty() && NUMERIC_FILTER_COLUMNS.contains(&column) {
+                return Err(invalid("missing value".to_string()));
+            }
+            let expected = match column {
+                "created" | "modified" => match parse_filter_date(value) {
+                    Some(_) => continue,
+                    None => "a date like 2024, 2024-05 or 2024-05-01",
+                },
+                "size" if parse_size_value(value).is_none() => "a size like 700MB or 2 GB",
+                "duration" if parse_filter_duration(value).is_none() => {
+                    "a duration like 90, 30min, 1h30m or 01:30:00"
+                }
+                "resolution" if parse_resolution(value).is_none() => "a resolution like 1920x1080",
+                "aspect" if parse_aspect(value).is_none() => "an aspect ratio like 16:9 or 2.39:1",
+                "fps" | "bitrate" | "audio_bitrate" if value.parse::<f64>().is_err() => "a number",
+                column
+                    if NUMERIC_FILTER_COLUMNS.contains(&column)
+                        && leading_number(value).is_none() =>
+                {
+                    "a number"
+                }
+                _ => continue,
+            };
+            return Err(invalid(format!("'{}' is not {}", value, expected)));
+        }
+    }
     Ok(())
 }
 
-fn get_cache_file() -> Result<PathBuf> {
-    let cache_dir = get_cache_dir()?;
-    Ok(cache_dir.join("cache.json"))
+/// Date filter values: a year, year-month or full date (UTC midnight).
+fn parse_filter_date(value: &str) -> Option<i64> {
+    let date = match value.len() {
+        4 => format!("{}-01-01", value),
+        7 => format!("{}-01", value),
+        _ => value.to_string(),
+    };
+    parse_iso_timestamp(&date)
 }
 
-fn get_cached_probe(file: &PathBuf) -> Result<Option<FFProbeOutput>> {
-    let canonical_path = file.canonicalize()?;
-    let path_str = canonical_path
-        .to_str()
-        .ok_or_else(|| anyhow!("Invalid file path"))?;
+/// Duration filter values: human (`1h30m`, `30min`), plain seconds, or `HH:MM:SS`.
+fn parse_filter_duration(value: &str) -> Option<f64> {
+    if value.contains(':') {
+        let parts: Vec<&str> = value.split(':').collect();
+        if parts.len() > 3 || parts.iter().any(|p| p.parse::<f64>().is_err()) {
+            return None;
+        }
+        return Some(parse_duration_to_secs(value));
+    }
+    parse_human_duration(value).or_else(|| value.parse::<f64>().ok())
+}
 
-    // Only load cache if it hasn't been loaded yet
-    let mut cache_guard = CACHE.lock().unwrap();
-    if cache_guard.is_none() {
-        // Load cache silently without progress indicators
-        let cache_path = get_cache_file()?;
-        if cache_path.exists() {
-            let content = fs::read_to_string(&cache_path)?;
-            *cache_guard = Some(serde_json::from_str(&content).unwrap_or_else(|_| Cache {
-                entries: HashMap::new(),
-            }));
-        } else {
-            *cache_guard = Some(Cache {
-                entries: HashMap::new(),
-            });
+/// Splits a filter into (column, operator, value). Both the
+/// `column:operator:value` form and the shorthands `column=value`,
+/// `column!=value`, `column<value`, `column>value` and `column:value`
+/// (equality) are accepted.
+fn parse_filter(filter: &str) -> Result<(&str, &str, &str)> {
+    let mut parts = filter.splitn(3, ':');
+    if let (Some(column), Some(op), Some(value)) = (parts.next(), parts.next(), parts.next()) {
+        if FILTER_OPERATORS.contains(&op) {
+            return Ok((column, op, value));
         }
     }
+    if let Some(index) = filter.find(['<', '>', '=']) {
+        // `<=` and `>=` take two characters
+        let end = match &filter[index..] {
+            rest if rest.starts_with("<=") || rest.starts_with(">=") => index + 2,
+            _ => index + 1,
+        };
+        let (column, value) = (&filter[..index], &filter[end..]);
+        return Ok(match column.strip_suffix('!') {
+            Some(column) if &filter[index..end] == "=" => (column, "!=", value),
+            _ => (column, &filter[index..end], value),
+        });
+    }
+    filter
+        .split_once(':')
+        .map(|(column, value)| (column, "=", value))
+        .ok_or_else(|| anyhow!("expected column:operator:value or column=value"))
+}
+
+/// Field and threshold of a numeric filter as comparable numbers, or None
+/// for columns that have no numeric ordering. The row's exact `number` is
+/// used when there is one, otherwise the display text is parsed. Both are
+/// in the unit of `numeric_values`, so `bitrate<3` (Mbps) compares against
+/// bits per second.
+fn numeric_filter_values(
+    column: &str,
+    number: Option<f64>,
+    field: &str,
+    value: &str,
+) -> Option<(f64, f64)> {
+    let (parsed, threshold) = match column {
+        "bitrate" => (
+            parse_bitrate(field).map(|b| b * 1_000_000.0),
+            value.parse::<f64>().ok().map(|b| b * 1_000_000.0),
+        ),
+        "duration" => (
+            Some(parse_duration_to_secs(field)),
+            parse_filter_duration(value),
+        ),
+        "fps" => (field.parse::<f64>().ok(), value.parse::<f64>().ok()),
+        "audio_bitrate" => (
+            parse_audio_bitrate(field).map(|b| b * 1000.0),
+            value.parse::<f64>().ok().map(|b| b * 1000.0),
+        ),
+        "size" => (
+            Some(parse_size(field) as f64),
+            parse_size_value(value).map(|s| s as f64),
+        ),
+        // Compared by pixel count
+        "resolution" => {
+            let pixels = |text: &str| parse_resolution(text).map(|(w, h)| (w * h) as f64);
+            (pixels(field), pixels(value))
+        }
+        "size_delta" | "bitrate_delta" => (Some(parse_delta_percent(field)), leading_number(value)),
+        "aspect" => (parse_aspect(field), parse_aspect(value)),
+        // Files without a creation_time (NaN) never pass a date comparison
+        "created" | "modified" => (
+            Some(f64::NAN),
+            parse_filter_date(value).map(|secs| secs as f64),
+        ),
+        column if NUMERIC_FILTER_COLUMNS.contains(&column) => {
+            (leading_number(field), leading_number(value))
+        }
+        _ => return None,
+    };
+    Some((number.or(parsed).unwrap_or(0.0), threshold.unwrap_or(0.0)))
+}
 
-    if let Some(cache) = &*cache_guard {
-        if let Some(entry) = cache.entries.get(path_str) {
-            let current_signature = get_file_signature(file)?;
-            if current_signature == entry.signature {
-                return Ok(Some(entry.probe_data.clone()));
+/// Width and height of a resolution like "1920x1080 (16:9)".
+fn parse_resolution(text: &str) -> Option<(u64, u64)> {
+    let dimensions = text.split_whitespace().next()?;
+    let (width, height) = dimensions.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// The number a value starts with: 10 for "10bit", 6 for "6CH 640k",
+/// 44.1 for "44.1 kHz".
+fn leading_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let end = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '+' || c == '-'))
+        .unwrap_or(text.len());
+    text[..end].parse::<f64>().ok()
+}
+
+/// `=` semantics per column: substring for filenames and list columns, a
+/// minimum (maximum for bitrates) for size, duration and fps, equality for
+/// other numeric columns, and case-insensitive equality for text columns.
+fn equals_filter_matches(
+    column: &str,
+    number: Option<f64>,
+    field: &str,
+    value: &str,
+    ignore_diacritics: bool,
+) -> bool {
+    match column {
+        "filename" => {
+            if ignore_diacritics {
+                fold_text(field).contains(&fold_text(value))
+            } else {
+                let filename = field.to_lowercase();
+                let pattern = value.to_lowercase();
+                filename.contains(&pattern)
             }
         }
+        "size" | "duration" | "fps" => numeric_filter_values(column, number, field, value)
+            .is_some_and(|(actual, threshold)| actual >= threshold),
+        "bitrate" | "audio_bitrate" => numeric_filter_values(column, number, field, value)
+            .is_some_and(|(actual, threshold)| actual <= threshold),
+        "resolution" => parse_resolution(field).is_some_and(|r| Some(r) == parse_resolution(value)),
+        // Within 1%, so `aspect=2.39:1` also matches a 1920x800 (2.40:1) encode
+        "aspect" => {
+            !field.is_empty()
+                && numeric_filter_values(column, number, field, value)
+                    .is_some_and(|(actual, ratio)| (actual - ratio).abs() <= ratio * 0.01)
+        }
+        // One of the tags, or untagged files for `tags=`
+        "tags" if value.is_empty() => field.is_empty(),
+        "tags" => field.split(',').any(|tag| tag.eq_ignore_ascii_case(value)),
+        // `created=2024` or `created=2024-05` matches that year or month
+        "created" | "modified" => {
+            number.is_some_and(|secs| format_iso_timestamp(secs as i64).starts_with(value))
+        }
+        column if CONTAINS_FILTER_COLUMNS.contains(&column) && value.is_empty() => field.is_empty(),
+        column if CONTAINS_FILTER_COLUMNS.contains(&column) => {
+            field.to_lowercase().contains(&value.to_lowercase())
+        }
+        column if NUMERIC_FILTER_COLUMNS.contains(&column) => {
+            numeric_filter_values(column, number, field, value).is_some_and(|(a, b)| a == b)
+        }
+        _ => field.eq_ignore_ascii_case(value),
     }
-
-    Ok(None)
 }
 
-fn save_to_cache(file: &PathBuf, probe_data: &FFProbeOutput) -> Result<()> {
-    let canonical_path = file.canonicalize()?;
-    let path_str = canonical_path
-        .to_str()
-        .ok_or_else(|| anyhow!("Invalid file path"))?;
+/// Lowercases text and strips accents and width variants via compatibility
+/// decomposition (NFKD), so "Amélie" and fullwidth "Ａｍｅｌｉｅ" fold to "amelie".
+fn fold_text(text: &str) -> String {
+    text.nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase()
+}
 
-    let mut cache_guard = CACHE.lock().unwrap();
-    if cache_guard.is_none() {
-        *cache_guard = Some(load_cache()?);
+fn default_device_format() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "avfoundation"
+    } else if cfg!(target_os = "windows") {
+        "dshow"
+    } else {
+        "v4l2"
     }
+}
 
-    if let Some(cache) = &mut *cache_guard {
-        cache.entries.insert(
-            path_str.to_string(),
-            CacheEntry {
-                signature: get_file_signature(file)?,
-                probe_data: probe_data.clone(),
-            },
-        );
-        save_cache(cache)?;
+fn print_device_modes(devices: &[PathBuf], device_format: Option<&str>) -> Result<()> {
+    let device_format = device_format.unwrap_or(default_device_format());
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.add_row(Row::new(vec![
+        Cell::new("Device").with_style(Attr::Bold),
+        Cell::new("Format").with_style(Attr::Bold),
+        Cell::new("Resolution").with_style(Attr::Bold),
+        Cell::new("FPS").with_style(Attr::Bold).style_spec("r"),
+    ]));
+
+    for device in devices {
+        let name = device.to_string_lossy();
+        match probe_device(&name, device_format) {
+            Ok(modes) if modes.is_empty() => {
+                warn!("No modes reported for device {}", name);
+            }
+            Ok(modes) => {
+                for mode in modes {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&name),
+                        Cell::new(&mode.pixel_format),
+                        Cell::new(&mode.resolution),
+                        Cell::new(&mode.fps).style_spec("r"),
+                    ]));
+                }
+            }
+            Err(e) => error!("Could not probe device {}: {}", name, e),
+        }
     }
 
+    table.printstd();
     Ok(())
 }
 
-fn format_duration(duration: &str) -> String {
-    if let Ok(secs) = duration.parse::<f64>() {
-        let hours = (secs / 3600.0).floor();
-        let minutes = ((secs % 3600.0) / 60.0).floor();
-        let seconds = secs % 60.0;
-
-        if hours > 0.0 {
-            format!(
-                "{:02}:{:02}:{:02}",
-                hours as u32, minutes as u32, seconds as u32
-            )
-        } else {
-            format!("{:02}:{:02}", minutes as u32, seconds as u32)
+fn probe_device(device: &str, device_format: &str) -> Result<Vec<DeviceMode>> {
+    match device_format {
+        "v4l2" => {
+            // v4l2-ctl reports frame intervals, ffmpeg only lists formats and sizes
+            if let Ok(output) = Command::new("v4l2-ctl")
+                .args(["--list-formats-ext", "-d", device])
+                .output()
+            {
+                if output.status.success() {
+                    return Ok(parse_v4l2_ctl_modes(&String::from_utf8_lossy(
+                        &output.stdout,
+                    )));
+                }
+            }
+            let stderr = run_ffmpeg_listing(&["-f", "v4l2", "-list_formats", "all", "-i", device])?;
+            Ok(parse_ffmpeg_v4l2_modes(&stderr))
         }
-    } else {
-        String::new()
+        "avfoundation" => {
+            // Requesting an impossible mode makes avfoundation print the supported ones
+            let stderr = run_ffmpeg_listing(&[
+                "-f",
+                "avfoundation",
+                "-framerate",
+                "1",
+                "-video_size",
+                "1x1",
+                "-i",
+                device,
+            ])?;
+            Ok(parse_avfoundation_modes(&stderr))
+        }
+        "dshow" => {
+            let input = if device.contains('=') {
+                device.to_string()
+            } else {
+                format!("video={}", device)
+            };
+            let stderr =
+                run_ffmpeg_listing(&["-f", "dshow", "-list_options", "true", "-i", &input])?;
+            Ok(parse_dshow_modes(&stderr))
+        }
+        _ => Err(anyhow!("Unsupported device format: {}", device_format)),
     }
 }
 
-fn format_size(size: &str) -> String {
-    if let Ok(bytes) = size.parse::<u64>() {
-        const KB: u64 = 1024;
-        const MB: u64 = KB * 1024;
-        const GB: u64 = MB * 1024;
+/// Runs ffmpeg in a device listing mode and returns its stderr.
+/// These invocations always exit with an error since no output is produced.
+fn run_ffmpeg_listing(args: &[&str]) -> Result<String> {
+    let output = Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .args(args)
+        .output()
+        .map_err(|e| anyhow!("Failed to run ffmpeg: {}", e))?;
+    Ok(String::from_utf8_lossy(&output.stderr).into_owned())
+}
 
-        if bytes >= GB {
-            format!("{:.2} GB", bytes as f64 / GB as f64)
-        } else if bytes >= MB {
-            format!("{:.2} MB", bytes as f64 / MB as f64)
-        } else if bytes >= KB {
-            format!("{:.2} KB", bytes as f64 / KB as f64)
-        } else {
-            format!("{} B", bytes)
+fn parse_v4l2_ctl_modes(output: &str) -> Vec<DeviceMode> {
+    let mut modes = Vec::new();
+    let mut pixel_format = String::new();
+    let mut resolution = String::new();
+
+    for line in output.lines().map(str::trim) {
+        if line.starts_with('[') {
+            // [0]: 'YUYV' (YUYV 4:2:2)
+            pixel_format = line.split('\'').nth(1).unwrap_or_default().to_string();
+        } else if let Some(size) = line.strip_prefix("Size:") {
+            // Size: Discrete 640x480
+            resolution = size
+                .split_whitespace()
+                .last()
+                .unwrap_or_default()
+                .to_string();
+        } else if line.starts_with("Interval:") {
+            // Interval: Discrete 0.033s (30.000 fps)
+            let fps = line
+                .split('(')
+                .nth(1)
+                .and_then(|s| s.split_whitespace().next())
+                .and_then(|s| s.parse::<f64>().ok())
+                .map(|f| format!("{:.2}", f))
+                .unwrap_or_default();
+            modes.push(DeviceMode {
+                pixel_format: pixel_format.clone(),
+                resolution: resolution.clone(),
+                fps,
+            });
         }
-    } else {
-        String::new()
     }
+    modes
 }
 
-fn get_bit_depth(pix_fmt: Option<&str>) -> String {
-    match pix_fmt {
-        Some(fmt) if fmt.contains("p10") => "10bit",
-        Some(fmt) if fmt.contains("p12") => "12bit",
-        _ => "8bit",
+fn parse_ffmpeg_v4l2_modes(output: &str) -> Vec<DeviceMode> {
+    let mut modes = Vec::new();
+    for line in output.lines() {
+        // [video4linux2,v4l2 @ 0x...] Raw       :     yuyv422 :           YUYV 4:2:2 : 640x480 320x240
+        let Some((_, rest)) = line.split_once("] ") else {
+            continue;
+        };
+        if !rest.starts_with("Raw") && !rest.starts_with("Compressed") {
+            continue;
+        }
+        let pixel_format = rest
+            .split(':')
+            .nth(1)
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        let sizes = rest.rsplit_once(" : ").map(|(_, s)| s).unwrap_or_default();
+        for size in sizes.split_whitespace() {
+            modes.push(DeviceMode {
+                pixel_format: pixel_format.clone(),
+                resolution: size.to_string(),
+                fps: String::new(),
+            });
+        }
     }
-    .to_string()
+    modes
 }
 
-fn format_elapsed(secs: f64) -> String {
-    if secs >= 60.0 {
-        let minutes = (secs / 60.0).floor();
-        let seconds = secs % 60.0;
-        format!("{}:{:02}", minutes as u32, seconds as u32)
-    } else {
-        format!("{}s", secs.round() as u32)
+fn parse_avfoundation_modes(output: &str) -> Vec<DeviceMode> {
+    let mut modes = Vec::new();
+    for line in output.lines() {
+        // [avfoundation @ 0x...]   1280x720@[1.000000 30.000000]fps
+        let Some((_, rest)) = line.split_once("] ") else {
+            continue;
+        };
+        let Some((resolution, rates)) = rest.trim().split_once("@[") else {
+            continue;
+        };
+        let fps = rates
+            .trim_end_matches("]fps")
+            .split_whitespace()
+            .filter_map(|r| r.parse::<f64>().ok())
+            .map(|r| format!("{:.2}", r))
+            .collect::<Vec<_>>()
+            .join("-");
+        modes.push(DeviceMode {
+            pixel_format: String::new(),
+            resolution: resolution.to_string(),
+            fps,
+        });
     }
+    modes
 }
 
-fn collect_media_files(paths: Vec<PathBuf>) -> Vec<PathBuf> {
-    let start_time = Instant::now();
-    let mut media_files = Vec::new();
-    let mut scanned = 0;
-    let mut found = 0;
-
-    // Clear line and show initial status
-    eprint!(
-        "\x1B[2K\rScanning: {} scanned, {} media files found ({})",
-        scanned,
-        found,
-        format_elapsed(start_time.elapsed().as_secs_f64())
-    );
-
-    for path in paths {
-        if path.is_file() {
-            scanned += 1;
-            if is_media_file(&path) {
-                found += 1;
-                eprint!(
-                    "\x1B[2K\rScanning: {} scanned, {} media files found ({})",
-                    scanned,
-                    found,
-                    format_elapsed(start_time.elapsed().as_secs_f64())
-                );
-                media_files.push(path);
-            }
-        } else if path.is_dir() {
-            for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-                let path = entry.path().to_path_buf();
-                if path.is_file() {
-                    scanned += 1;
-                    if is_media_file(&path) {
-                        found += 1;
-                        eprint!(
-                            "\x1B[2K\rScanning: {} scanned, {} media files found ({})",
-                            scanned,
-                            found,
-                            format_elapsed(start_time.elapsed().as_secs_f64())
-                        );
-                        media_files.push(path);
-                    }
+fn parse_dshow_modes(output: &str) -> Vec<DeviceMode> {
+    let mut modes = Vec::new();
+    for line in output.lines() {
+        // [dshow @ 0x...]   vcodec=mjpeg  min s=1280x720 fps=5 max s=1280x720 fps=30
+        let Some((_, rest)) = line.split_once("] ") else {
+            continue;
+        };
+        let mut pixel_format = String::new();
+        let mut resolution = String::new();
+        let mut rates = Vec::new();
+        for token in rest.split_whitespace() {
+            if let Some(v) = token
+                .strip_prefix("vcodec=")
+                .or_else(|| token.strip_prefix("pixel_format="))
+            {
+                pixel_format = v.to_string();
+            } else if let Some(v) = token.strip_prefix("s=") {
+                resolution = v.to_string();
+            } else if let Some(v) = token.strip_prefix("fps=") {
+                if let Ok(r) = v.parse::<f64>() {
+                    rates.push(format!("{:.2}", r));
                 }
             }
         }
+        if pixel_format.is_empty() || resolution.is_empty() {
+            continue;
+        }
+        rates.dedup();
+        modes.push(DeviceMode {
+            pixel_format,
+            resolution,
+            fps: rates.join("-"),
+        });
     }
-    let elapsed = start_time.elapsed().as_secs_f64();
-    eprintln!("\nScanning completed in {}", format_elapsed(elapsed));
-    media_files
+    modes
 }
 
-fn parse_duration_to_secs(duration: &str) -> f64 {
-    let parts: Vec<&str> = duration.split(':').collect();
-    match parts.len() {
-        2 => {
-            let mins: f64 = parts[0].parse().unwrap_or(0.0);
-            let secs: f64 = parts[1].parse().unwrap_or(0.0);
-            mins * 60.0 + secs
-        }
-        3 => {
-            let hours: f64 = parts[0].parse().unwrap_or(0.0);
-            let mins: f64 = parts[1].parse().unwrap_or(0.0);
-            let secs: f64 = parts[2].parse().unwrap_or(0.0);
-            hours * 3600.0 + mins * 60.0 + secs
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn file_modified_secs(path: &Path) -> Option<i64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(
+        modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()?
+            .as_secs() as i64,
+    )
+}
+
+/// Creation time recorded by the muxer/camera in the container tags.
+fn creation_secs(probe: &FFProbeOutput) -> Option<i64> {
+    probe
+        .format
+        .tags
+        .as_ref()?
+        .get("creation_time")
+        .and_then(|t| parse_iso_timestamp(t))
+}
+
+fn format_date(secs: Option<i64>, mode: &str) -> String {
+    let Some(secs) = secs else {
+        return String::new();
+    };
+    match mode {
+        "relative" => format_relative_time(now_secs() - secs),
+        _ => format_iso_timestamp(secs),
+    }
+}
+
+fn format_relative_time(elapsed: i64) -> String {
+    const UNITS: [(i64, &str); 6] = [
+        (365 * 86400, "year"),
+        (30 * 86400, "month"),
+        (7 * 86400, "week"),
+        (86400, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+    ];
+    if elapsed < 0 {
+        return "in the future".to_string();
+    }
+    for (unit_secs, name) in UNITS {
+        let count = elapsed / unit_secs;
+        if count >= 1 {
+            let plural = if count == 1 { "" } else { "s" };
+            return format!("{} {}{} ago", count, name, plural);
         }
-        _ => 0.0,
     }
+    "just now".to_string()
 }
 
-fn parse_human_duration(duration_str: &str) -> Option<f64> {
-    let mut total_seconds = 0.0;
-    let mut current_number = String::new();
-    let mut chars = duration_str.chars().peekable();
+fn format_iso_timestamp(secs: i64) -> String {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let time = secs.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        (time % 3600) / 60,
+        time % 60
+    )
+}
 
-    while let Some(c) = chars.next() {
-        if c.is_digit(10) {
-            current_number.push(c);
-        } else {
-            let number = current_number.parse::<f64>().ok()?;
-            current_number.clear();
+/// Parses the `YYYY-MM-DDTHH:MM:SS` prefix of an ISO 8601 timestamp as UTC.
+fn parse_iso_timestamp(timestamp: &str) -> Option<i64> {
+    let date = timestamp.get(0..10)?;
+    let mut parts = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    let time = timestamp.get(11..19).unwrap_or("00:00:00");
+    let mut parts = time.split(':').map(|p| p.parse::<i64>().ok());
+    let (hours, minutes, seconds) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds)
+}
 
-            match c {
-                'h' => total_seconds += number * 3600.0,
-                'm' => {
-                    if chars.peek() == Some(&'i') {
-                        chars.next(); // consume 'i'
-                        if chars.peek() == Some(&'n') {
-                            chars.next(); // consume 'n'
-                            total_seconds += number * 60.0;
-                        }
-                    } else {
-                        total_seconds += number * 60.0;
-                    }
-                }
-                's' => total_seconds += number,
-                _ => return None,
-            }
+// Conversions between days since the Unix epoch and proleptic Gregorian
+// dates, after Howard Hinnant's chrono-compatible algorithms.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Formats the change from `reference` to `current` as e.g. "-1.20 GB (-42.0%)".
+fn format_delta(
+    current: Option<f64>,
+    reference: Option<f64>,
+    format_amount: impl Fn(f64) -> String,
+) -> String {
+    match (current, reference) {
+        (Some(current), Some(reference)) if reference > 0.0 => {
+            let delta = current - reference;
+            format!(
+                "{} ({:+.1}%)",
+                format_amount(delta),
+                delta / reference * 100.0
+            )
         }
+        _ => String::new(),
     }
+}
 
-    // Handle case where there might be a trailing number without unit (assume seconds)
-    if !current_number.is_empty() {
-        if let Ok(number) = current_number.parse::<f64>() {
-            total_seconds += number;
+fn parse_delta_percent(delta: &str) -> f64 {
+    delta
+        .rsplit_once('(')
+        .and_then(|(_, p)| p.trim_end_matches("%)").parse::<f64>().ok())
+        .unwrap_or(0.0)
+}
+
+fn common_ancestor<'a>(mut paths: impl Iterator<Item = &'a Path>) -> PathBuf {
+    let Some(first) = paths.next() else {
+        return PathBuf::new();
+    };
+    let mut ancestor = first.parent().unwrap_or(Path::new("")).to_path_buf();
+    for path in paths {
+        while !path.starts_with(&ancestor) {
+            if !ancestor.pop() {
+                return PathBuf::new();
+            }
         }
     }
+    ancestor
+}
 
-    Some(total_seconds)
+fn relative_to(path: &Path, root: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
 }
 
-fn process_file(file: &PathBuf, filename_length: usize, skip_cache: bool) -> Result<FFProbeOutput> {
-    // Try to get from cache first, unless skip_cache is true
-    if !skip_cache {
-        if let Ok(Some(probe)) = get_cached_probe(file) {
-            return Ok(probe);
+/// Looks up a container tag by any of the given keys, ignoring case
+/// (MP4 and Matroska muxers disagree on capitalization).
+fn format_tag<'a>(probe: &'a FFProbeOutput, keys: &[&str]) -> Option<&'a str> {
+    let tags = probe.format.tags.as_ref()?;
+    keys.iter().find_map(|key| {
+        tags.iter()
+            .find(|(k, v)| k.eq_ignore_ascii_case(key) && !v.trim().is_empty())
+            .map(|(_, v)| v.trim())
+    })
+}
+
+/// Parental rating from iTunes (`iTunEXTC`, `rtng`), Matroska (`LAW_RATING`)
+/// or generic TV metadata tags.
+fn content_rating(probe: &FFProbeOutput) -> Option<String> {
+    // iTunEXTC looks like "mpaa|PG-13|300|"
+    if let Some(extc) = format_tag(probe, &["iTunEXTC"]) {
+        if let Some(rating) = extc.split('|').nth(1).filter(|r| !r.is_empty()) {
+            return Some(rating.to_string());
         }
     }
+    if let Some(rating) = format_tag(
+        probe,
+        &[
+            "LAW_RATING",
+            "content_rating",
+            "itunes_content_rating",
+            "RATING",
+        ],
+    ) {
+        return Some(rating.to_string());
+    }
+    // rtng is the iTunes advisory flag rather than an age rating
+    match format_tag(probe, &["rtng", "itunesadvisory"])? {
+        "1" | "4" => Some("Explicit".to_string()),
+        "2" => Some("Clean".to_string()),
+        _ => None,
+    }
+}
 
-    // If not in cache, cache is invalid, or skip_cache is true, run ffprobe
-    let output = Command::new("ffprobe")
-        .args([
-            "-v",
-            "quiet",
-            "-print_format",
-            "json",
-            "-show_format",
-            "-show_streams",
-            file.to_str().ok_or_else(|| anyhow!("Invalid file path"))?,
-        ])
-        .output()?;
+/// Detects stereoscopic 3D video from Matroska StereoMode, stereo side data
+/// (frame packing SEI, spherical/stereo boxes) or multiview codec profiles.
+fn stereo_mode(video: &Stream) -> Option<String> {
+    let label = |mode: &str| -> Option<String> {
+        let mode = mode.to_lowercase();
+        let label = match mode.as_str() {
+            "" | "mono" | "2d" => return None,
+            m if m.contains("left_right") || m.contains("right_left") => "SBS",
+            m if m.contains("side by side") => "SBS",
+            m if m.contains("top_bottom") || m.contains("bottom_top") => "TAB",
+            m if m.contains("top and bottom") => "TAB",
+            m if m.contains("anaglyph") => "Anaglyph",
+            m if m.contains("frame alternate") || m.contains("block") => "Frame-seq",
+            m if m.contains("checkerboard") => "Checkerboard",
+            m if m.contains("row") || m.contains("line") => "Interleaved",
+            m if m.contains("col") => "Interleaved",
+            _ => "3D",
+        };
+        Some(label.to_string())
+    };
 
-    if !output.status.success() {
-        return Err(anyhow!(
-            "ffprobe failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
+    if let Some(mode) = video
+        .tags
+        .as_ref()
+        .and_then(|t| t.get("stereo_mode").or_else(|| t.get("STEREO_MODE")))
+    {
+        return label(mode);
     }
+    for side_data in video.side_data_list.iter().flatten() {
+        if side_data.get("side_data_type").and_then(|t| t.as_str()) == Some("Stereo 3D") {
+            let kind = side_data.get("type").and_then(|t| t.as_str()).unwrap_or("");
+            if kind.is_empty() {
+                return Some("3D".to_string());
+            }
+            return label(kind);
+        }
+    }
+    match video.profile.as_deref() {
+        Some("Stereo High") | Some("Multiview High") => Some("MVC".to_string()),
+        Some(p) if p.contains("Multiview") => Some("MV-HEVC".to_string()),
+        _ => None,
+    }
+}
 
-    let probe: FFProbeOutput = serde_json::from_slice(&output.stdout)?;
-
-    // Always save to cache, even if we skipped reading from it
-    save_to_cache(file, &probe)?;
+fn stream_language(stream: &Stream) -> Option<&str> {
+    stream
+        .tags
+        .as_ref()?
+        .get("language")
+        .map(String::as_str)
+        .filter(|language| *language != "und")
+}
 
-    Ok(probe)
+fn stream_title(stream: &Stream) -> Option<&str> {
+    stream.tags.as_ref()?.get("title").map(String::as_str)
 }
 
-fn truncate_middle(s: &str, max_len: usize) -> String {
-    if s.chars().count() <= max_len {
-        return s.to_string();
-    }
+/// Compact audio stream description for the table, e.g. "eng ac3 5.1(side)".
+fn audio_stream_summary(stream: &Stream) -> String {
+    let channels = match (&stream.channel_layout, stream.channels) {
+        (Some(layout), _) => Some(layout.clone()),
+        (None, Some(channels)) => Some(format!("{}CH", channels)),
+        (None, None) => None,
+    };
+    [
+        stream_language(stream).map(str::to_string),
+        stream.codec_name.clone(),
+        channels,
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" ")
+}
 
-    let ellipsis = "...";
-    let side_len = (max_len - ellipsis.len()) / 2;
+/// Compact subtitle stream description for the table, e.g. "eng subrip forced".
+fn subtitle_stream_summary(stream: &Stream) -> String {
+    [
+        stream_language(stream).map(str::to_string),
+        stream.codec_name.clone(),
+        is_forced_subtitle(stream).then(|| "forced".to_string()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" ")
+}
 
-    let left: String = s.chars().take(side_len).collect();
-    let right: String = s
-        .chars()
-        .rev()
-        .take(side_len)
-        .collect::<String>()
-        .chars()
-        .rev()
-        .collect();
+fn audio_stream_details(stream: &Stream) -> serde_json::Value {
+    serde_json::json!({
+        "index": stream.index,
+        "codec": stream.codec_name,
+        "language": stream_language(stream),
+        "title": stream_title(stream),
+        "channels": stream.channels,
+        "channel_layout": stream.channel_layout,
+        "sample_rate": stream.sample_rate.as_deref().and_then(|r| r.parse::<u32>().ok()),
+        "bit_rate": stream.bit_rate.as_deref().and_then(|b| b.parse::<u64>().ok()),
+        "default": has_disposition(stream, "default"),
+    })
+}
 
-    format!("{}{}{}", left, ellipsis, right)
+fn subtitle_stream_details(stream: &Stream) -> serde_json::Value {
+    serde_json::json!({
+        "index": stream.index,
+        "codec": stream.codec_name,
+        "language": stream_language(stream),
+        "title": stream_title(stream),
+        "forced": is_forced_subtitle(stream),
+        "default": has_disposition(stream, "default"),
+    })
 }
 
-fn format_probe_output(
-    file: &PathBuf,
-    probe: &FFProbeOutput,
-    filename_length: usize,
-) -> Result<Vec<String>> {
-    let mut fields = Vec::new();
-
-    // Get filename
-    fields.push(truncate_middle(
-        file.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("Unknown"),
-        filename_length,
-    ));
+/// Forced subtitles cover only foreign-language segments. Besides the
+/// disposition flag, many Matroska releases only mark them in the track title.
+fn is_forced_subtitle(stream: &Stream) -> bool {
+    let flagged = has_disposition(stream, "forced");
+    let titled = stream
+        .tags
+        .as_ref()
+        .and_then(|t| t.get("title"))
+        .is_some_and(|title| title.to_lowercase().contains("forced"));
+    flagged || titled
+}
 
-    // Get duration
-    fields.push(format_duration(&probe.format.duration));
+/// Identifies a file as one part of a split recording, returning the key
+/// shared by all of its parts and the part's position. Recognized patterns:
+/// numbered parts (movie.001.mkv), DVD title sets (VTS_01_1.VOB) and GoPro
+/// chapters (GX010123.MP4, GX020123.MP4; GOPR0123.MP4, GP010123.MP4).
+fn segment_key(path: &Path) -> Option<(PathBuf, u32)> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let stem = path.file_stem()?.to_str()?;
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
 
-    // Find video stream
-    if let Some(video) = probe.streams.iter().find(|s| s.codec_type == "video") {
-        // Get FPS
-        let fps = video
-            .r_frame_rate
-            .as_deref()
-            .and_then(|r| {
-                let parts: Vec<&str> = r.split('/').collect();
-                if parts.len() == 2 {
-                    parts[0].parse::<f64>().ok().and_then(|num| {
-                        parts[1].parse::<f64>().ok().map(|den| {
-                            if den != 0.0 {
-                                format!("{:.2}", num / den)
-                            } else {
-                                String::new()
-                            }
-                        })
-                    })
-                } else {
-                    None
-                }
-            })
-            .unwrap_or_default();
-        fields.push(fps);
+    // movie.001.mkv
+    if let Some((base, part)) = stem.rsplit_once('.') {
+        if part.len() == 3 && digits(part) {
+            return Some((dir.join(format!("{}.{}", base, ext)), part.parse().ok()?));
+        }
+    }
 
-        // Get file size
-        fields.push(format_size(&probe.format.size));
+    let upper = stem.to_uppercase();
+    // VTS_01_1.VOB (VTS_01_0 is the menu, not part of the title)
+    if ext == "vob" && upper.len() == 8 && upper.starts_with("VTS_") {
+        let (title, part) = (&upper[4..6], &upper[7..8]);
+        if digits(title) && digits(part) && part != "0" {
+            return Some((dir.join(format!("VTS_{}.vob", title)), part.parse().ok()?));
+        }
+    }
 
-        // Get bitrate from format (more reliable than video stream bitrate)
-        let bitrate = probe
-            .format
-            .bit_rate
-            .as_deref()
-            .and_then(|b| b.parse::<f64>().ok())
-            .map(|b| format!("{:.2} Mbps", b / 1_000_000.0))
-            .unwrap_or_default();
-        fields.push(bitrate);
+    // GoPro: G[XHP]<chapter><file number>, or GOPR<file number> for the first chapter
+    if upper.len() == 8 && upper.is_ascii() {
+        if let Some(number) = upper.strip_prefix("GOPR").filter(|n| digits(n)) {
+            return Some((dir.join(format!("GOPRO{}.{}", number, ext)), 0));
+        }
+        let (prefix, chapter, number) = (&upper[0..2], &upper[2..4], &upper[4..8]);
+        if matches!(prefix, "GX" | "GH" | "GP") && digits(chapter) && digits(number) {
+            let prefix = if prefix == "GP" { "GOPRO" } else { prefix };
+            return Some((
+                dir.join(format!("{}{}.{}", prefix, number, ext)),
+                chapter.parse().ok()?,
+            ));
+        }
+    }
 
-        // Get resolution
-        let width = video.width.unwrap_or(0);
-        let height = video.height.unwrap_or(0);
-        let aspect = video.display_aspect_ratio.as_deref().unwrap_or("");
-        let resolution = if aspect.is_empty() {
-            format!("{}x{}", width, height)
-        } else {
-            format!("{}x{} ({})", width, height, aspect)
-        };
-        fields.push(resolution);
+    None
+}
 
-        // Get codec name
-        fields.push(video.codec_name.clone().unwrap_or_default());
+/// Merges split recordings into a single entry per group, with combined
+/// size and duration. The first part stands in for the group; the number of
+/// parts is recorded in `segment_counts`.
+fn stitch_segments(
+    files: Vec<(PathBuf, FFProbeOutput)>,
+    segment_counts: &mut HashMap<PathBuf, usize>,
+) -> Vec<(PathBuf, FFProbeOutput)> {
+    let mut groups: HashMap<PathBuf, Vec<(u32, PathBuf, FFProbeOutput)>> = HashMap::new();
+    let mut result = Vec::new();
+    for (path, probe) in files {
+        match segment_key(&path) {
+            Some((key, index)) => groups.entry(key).or_default().push((index, path, probe)),
+            None => result.push((path, probe)),
+        }
+    }
 
-        // Get profile
-        fields.push(video.profile.clone().unwrap_or_default());
+    for (_, mut parts) in groups {
+        if parts.len() == 1 {
+            let (_, path, probe) = parts.remove(0);
+            result.push((path, probe));
+            continue;
+        }
+        parts.sort_by_key(|(index, _, _)| *index);
+        let count = parts.len();
+        let size: u64 = parts
+            .iter()
+            .filter_map(|(_, _, p)| p.format.size.parse::<u64>().ok())
+            .sum();
+        let duration: f64 = parts
+            .iter()
+            .filter_map(|(_, _, p)| p.format.duration.parse::<f64>().ok())
+            .sum();
 
-        // Get bit depth
-        fields.push(get_bit_depth(video.pix_fmt.as_deref()));
+        let (_, path, mut probe) = parts.remove(0);
+        probe.format.size = size.to_string();
+        probe.format.duration = duration.to_string();
+        if duration > 0.0 {
+            probe.format.bit_rate = Some(((size as f64 * 8.0 / duration) as u64).to_string());
+        }
+        segment_counts.insert(path.clone(), count);
+        result.push((path, probe));
+    }
+    result
+}
 
-        // Get color info
-        let color_info = match (video.color_space.as_deref(), video.color_range.as_deref()) {
-            (Some(space), Some(range)) => format!("{} {}", space, range),
-            (Some(space), None) => space.to_string(),
-            (None, Some(range)) => range.to_string(),
-            (None, None) => String::new(),
-        };
-        fields.push(color_info);
+/// Container and video stream durations, if they differ by more than 2
+/// seconds and 1%. Matroska stores the stream duration as a `DURATION` tag.
+fn duration_mismatch(probe: &FFProbeOutput) -> Option<(f64, f64)> {
+    let container = probe.format.duration.parse::<f64>().ok()?;
+    let video = primary_video(probe)?;
+    let stream = match &video.duration {
+        Some(duration) => duration.parse::<f64>().ok()?,
+        None => {
+            let tags = video.tags.as_ref()?;
+            let (_, duration) = tags
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case("DURATION"))?;
+            parse_duration_to_secs(duration)
+        }
+    };
+    let difference = (container - stream).abs();
+    if stream > 0.0 && difference > 2.0 && difference > container * 0.01 {
+        Some((container, stream))
     } else {
-        // No video stream found, add empty fields
-        fields.extend(vec!["".to_string(); 7]);
+        None
     }
+}
 
-    // Find audio stream
-    if let Some(audio) = probe.streams.iter().find(|s| s.codec_type == "audio") {
-        let channels = format!("{}CH", audio.channels.unwrap_or(0));
-        let bitrate = audio
-            .bit_rate
-            .as_deref()
-            .and_then(|b| b.parse::<f64>().ok())
-            .map(|b| format!(" {:.0}k", b / 1000.0))
-            .unwrap_or_default();
-        fields.push(format!("{}{}", channels, bitrate));
-    } else {
-        fields.push("".to_string());
+/// Containers by the names ffprobe (or an imported MediaInfo export) reports,
+/// with a display name and the extensions they go by, the usual one first
+const CONTAINER_EXTENSIONS: [(&[&str], &str, &[&str]); 15] = [
+    (
+        &["matroska", "webm"],
+        "Matroska",
+        &["mkv", "mka", "mk3d", "webm"],
+    ),
+    (
+        &["mov", "mp4", "mpeg-4", "quicktime"],
+        "MP4/QuickTime",
+        &["mp4", "m4v", "m4a", "mov", "qt", "3gp", "3g2", "mj2"],
+    ),
+    (&["avi"], "AVI", &["avi"]),
+    (
+        &["mpegts", "mpeg-ts", "bdav"],
+        "MPEG-TS",
+        &["ts", "mts", "m2ts"],
+    ),
+    (&["mpeg", "mpeg-ps"], "MPEG-PS", &["mpg", "mpeg", "vob"]),
+    (&["mpegvideo", "mpeg video"], "MPEG video", &["m2v", "mpv"]),
+    (&["flv"], "FLV", &["flv"]),
+    (&["asf", "windows media"], "ASF", &["wmv", "asf", "wma"]),
+    (&["ogg"], "Ogg", &["ogg", "ogv", "oga", "opus"]),
+    (&["mxf"], "MXF", &["mxf"]),
+    (&["rm", "realmedia"], "RealMedia", &["rm", "rmvb"]),
+    (&["mp3", "mpeg audio"], "MP3", &["mp3"]),
+    (&["wav", "wave"], "WAV", &["wav"]),
+    (&["flac"], "FLAC", &["flac"]),
+    (&["aac", "adts"], "AAC", &["aac"]),
+];
+
+/// The actual container's display name, its usual extension and the file's
+/// extension, when the extension belongs to a different container than the
+/// probed data, such as Matroska renamed to .mp4. Unknown containers and
+/// extensions are not reported, nor are streams, which have no extension.
+fn extension_mismatch(
+    path: &Path,
+    probe: &FFProbeOutput,
+) -> Option<(&'static str, &'static str, String)> {
+    if is_stream_input(path) {
+        return None;
+    }
+    let extension = media_extension(path)?;
+    let format_name = probe.format.extra.get("format_name")?.as_str()?;
+    let names: Vec<&str> = format_name.split(',').map(str::trim).collect();
+    let (_, container, extensions) = CONTAINER_EXTENSIONS.iter().find(|(formats, _, _)| {
+        names
+            .iter()
+            .any(|name| formats.contains(&name.to_lowercase().as_str()))
+    })?;
+    let known = CONTAINER_EXTENSIONS
+        .iter()
+        .any(|(_, _, extensions)| extensions.contains(&extension.as_str()));
+    match known && !extensions.contains(&extension.as_str()) {
+        true => Some((container, extensions[0], extension)),
+        false => None,
     }
+}
 
-    Ok(fields)
+/// Names the telemetry/metadata tracks embedded by action cameras and drones
+/// (GoPro GPMF, DJI, Camera Motion Metadata), which transcoders often drop.
+fn telemetry_streams(probe: &FFProbeOutput) -> Vec<&'static str> {
+    let mut found = Vec::new();
+    for stream in &probe.streams {
+        let tag = stream.codec_tag_string.as_deref().unwrap_or("");
+        let handler = stream
+            .tags
+            .as_ref()
+            .and_then(|t| t.get("handler_name"))
+            .map(|h| h.to_lowercase())
+            .unwrap_or_default();
+        let kind = if tag == "gpmd" || handler.contains("gopro met") {
+            "GPMF"
+        } else if tag == "djmd" || tag == "dbgi" || handler.contains("dji meta") {
+            "DJI"
+        } else if tag == "camm" || handler.contains("camera motion") {
+            "CAMM"
+        } else {
+            continue;
+        };
+        if !found.contains(&kind) {
+            found.push(kind);
+        }
+    }
+    found
 }
 
-fn parse_bitrate(bitrate_str: &str) -> Option<f64> {
-    bitrate_str
-        .split_whitespace()
-        .next()
-        .and_then(|s| s.parse::<f64>().ok())
+/// Start timecode as written by professional cameras, either on a tmcd
+/// track, the video stream or the container.
+fn start_timecode(probe: &FFProbeOutput) -> Option<String> {
+    let from_stream = |stream: &Stream| stream.tags.as_ref()?.get("timecode").cloned();
+    probe
+        .streams
+        .iter()
+        .filter(|s| s.codec_tag_string.as_deref() == Some("tmcd"))
+        .chain(primary_video(probe))
+        .find_map(from_stream)
+        .or_else(|| format_tag(probe, &["timecode"]).map(str::to_string))
 }
 
-fn is_media_file(path: &Path) -> bool {
-    let media_extensions = [
-        "mp4", "mkv", "avi", "mov", "wmv", "flv", "webm", "m4v", "mpg", "mpeg", "m2v", "m4v",
-        "3gp", "3g2", "mxf", "ts", "mts", "m2ts", "vob", "ogv", "qt", "rm", "rmvb", "asf", "mp3",
-        "wav", "flac", "m4a", "aac", "ogg", "wma", "opus",
-    ];
+/// Identifies the camera or software that wrote the file, to tell camera
+/// originals from re-encodes: camera make/model, GoPro firmware, or the
+/// encoder tag of the container or video stream.
+fn encoder_name(probe: &FFProbeOutput) -> Option<String> {
+    let make = format_tag(probe, &["com.apple.quicktime.make", "make"]);
+    let model = format_tag(probe, &["com.apple.quicktime.model", "model"]);
+    match (make, model) {
+        (Some(make), Some(model)) if model.starts_with(make) => return Some(model.to_string()),
+        (Some(make), Some(model)) => return Some(format!("{} {}", make, model)),
+        (None, Some(model)) | (Some(model), None) => return Some(model.to_string()),
+        (None, None) => {}
+    }
 
-    path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| media_extensions.contains(&ext.to_lowercase().as_str()))
-        .unwrap_or(false)
+    let gopro = probe.streams.iter().any(|s| {
+        s.tags
+            .as_ref()
+            .and_then(|t| t.get("handler_name"))
+            .is_some_and(|h| h.contains("GoPro"))
+    });
+    if gopro {
+        return Some(match format_tag(probe, &["firmware"]) {
+            Some(firmware) => format!("GoPro ({})", firmware),
+            None => "GoPro".to_string(),
+        });
+    }
+
+    let video_encoder = || {
+        probe
+            .streams
+            .iter()
+            .filter(|s| s.codec_type == "video" && !is_attached_pic(s))
+            .find_map(|s| {
+                let tags = s.tags.as_ref()?;
+                tags.get("encoder").or_else(|| tags.get("ENCODER")).cloned()
+            })
+    };
+    let encoder = format_tag(probe, &["encoder", "encoded_by", "writing_application"])
+        .map(str::to_string)
+        .or_else(video_encoder)?;
+    // HandBrake appends a build date: "HandBrake 1.6.1 2023012300"
+    let words: Vec<&str> = encoder.split_whitespace().collect();
+    match words.as_slice() {
+        [name @ .., build] if build.len() == 10 && build.chars().all(|c| c.is_ascii_digit()) => {
+            Some(name.join(" "))
+        }
+        _ => Some(encoder),
+    }
 }
 
-fn parse_size(size_str: &str) -> u64 {
-    let parts: Vec<&str> = size_str.split_whitespace().collect();
-    if parts.len() != 2 {
-        return 0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan_settings(extra: &[&str]) -> ScanSettings {
+        let args = Args::parse_from(["mediainfo", "."].iter().chain(extra));
+        resolve_scan_settings(Path::new("."), &args, &HashMap::new()).unwrap()
     }
 
-    let value: f64 = parts[0].parse().unwrap_or(0.0);
-    match parts[1] {
-        "GB" => (value * 1024.0 * 1024.0 * 1024.0) as u64,
-        "MB" => (value * 1024.0 * 1024.0) as u64,
-        "KB" => (value * 1024.0) as u64,
-        "B" => value as u64,
-        _ => 0,
+    #[test]
+    fn stats_takes_out_file() {
+        let args =
+            Args::try_parse_from(["mediainfo", "stats", "--out-file", "stats.txt", "."]).unwrap();
+        match args.command {
+            Some(Commands::Stats { out_file, .. }) => {
+                assert_eq!(out_file, Some(PathBuf::from("stats.txt")))
+            }
+            _ => panic!("expected the stats subcommand"),
+        }
     }
-}
 
-fn get_cached_files() -> Result<Vec<(PathBuf, FFProbeOutput)>> {
-    eprintln!("Loading cache file...");
-    let mut cache_guard = CACHE.lock().unwrap();
-    if cache_guard.is_none() {
-        eprintln!("Cache not loaded, loading from disk...");
-        *cache_guard = Some(load_cache()?);
+    #[test]
+    fn media_extension_is_case_insensitive() {
+        assert_eq!(
+            media_extension(Path::new("clip.MOV")).as_deref(),
+            Some("mov")
+        );
+        assert!(is_media_file(Path::new("clip.MOV")));
+    }
+
+    #[test]
+    fn media_extension_ignores_trailing_spaces_and_dots() {
+        assert_eq!(
+            media_extension(Path::new("clip.mp4 ")).as_deref(),
+            Some("mp4")
+        );
+        assert_eq!(
+            media_extension(Path::new("clip.mp4.")).as_deref(),
+            Some("mp4")
+        );
     }
 
-    let mut files = Vec::new();
-    if let Some(cache) = &*cache_guard {
-        eprintln!("Found {} entries in cache", cache.entries.len());
-        for (path_str, entry) in &cache.entries {
-            let path = PathBuf::from(path_str);
-            files.push((path, entry.probe_data.clone()));
+    #[test]
+    fn media_extension_looks_through_partial_suffixes() {
+        for name in ["movie.mkv.part", "movie.mkv.!qB", "movie.mkv.crdownload"] {
+            assert_eq!(
+                media_extension(Path::new(name)).as_deref(),
+                Some("mkv"),
+                "{}",
+                name
+            );
+            assert!(is_partial_download(Path::new(name)), "{}", name);
         }
-        eprintln!("Loaded {} entries", files.len());
-    } else {
-        eprintln!("No cache entries found");
+        assert!(!is_partial_download(Path::new("movie.mkv")));
     }
-    Ok(files)
-}
 
-fn should_include_row(fields: &[String], filters: &[String]) -> Result<bool> {
-    // Define valid column names
-    const VALID_COLUMNS: [&str; 7] = [
-        "filename",
-        "size",
-        "duration",
-        "fps",
-        "bitrate",
-        "resolution",
-        "audio",
-    ];
+    #[test]
+    fn aria2_control_file_marks_partial_download() {
+        let dir = std::env::temp_dir().join(format!("mediainfo-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let movie = dir.join("movie.mkv");
+        fs::write(&movie, b"").unwrap();
+        assert!(!is_partial_download(&movie));
+        fs::write(dir.join("movie.mkv.aria2"), b"").unwrap();
+        assert!(is_partial_download(&movie));
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-    // If no filters, include all rows
-    if filters.is_empty() {
-        return Ok(true);
+    #[test]
+    fn include_partial_keeps_partial_downloads() {
+        let partial = Path::new("movie.mkv.part");
+        assert!(is_skipped_partial(partial, &scan_settings(&[])));
+        assert!(!is_skipped_partial(
+            partial,
+            &scan_settings(&["--include-partial"])
+        ));
+        assert!(!is_skipped_partial(
+            Path::new("movie.mkv"),
+            &scan_settings(&[])
+        ));
     }
 
-    // Row must match all filters (AND logic)
-    for filter in filters {
-        // First try to split by '<' or '>'
-        if let Some((column, value)) = filter.split_once('<') {
-            if !VALID_COLUMNS.contains(&column) {
-                return Err(anyhow!("Invalid column name in filter: {}", column));
-            }
-            match column {
-                "bitrate" => {
-                    let field_bitrate = parse_bitrate(&fields[4]).unwrap_or(0.0);
-                    let threshold = value.parse::<f64>().unwrap_or(0.0);
-                    if field_bitrate > threshold {
-                        return Ok(false);
-                    }
-                }
-                "duration" => {
-                    let field_duration = parse_duration_to_secs(&fields[2]);
-                    let threshold = parse_human_duration(value)
-                        .unwrap_or_else(|| value.parse::<f64>().unwrap_or(0.0));
-                    if field_duration > threshold {
-                        return Ok(false);
-                    }
-                }
-                _ => {}
-            }
-            continue;
+    fn filter_row(values: &[(&'static str, &str)], numbers: &[(&'static str, f64)]) -> ResultRow {
+        ResultRow {
+            path: PathBuf::from("movie.mkv"),
+            probe: serde_json::from_value(serde_json::json!({
+                "streams": [],
+                "format": { "filename": "movie.mkv" },
+            }))
+            .unwrap(),
+            values: values
+                .iter()
+                .map(|(column, value)| (*column, value.to_string()))
+                .collect(),
+            numbers: numbers.iter().copied().collect(),
         }
+    }
 
-        if let Some((column, value)) = filter.split_once('>') {
-            if !VALID_COLUMNS.contains(&column) {
-                return Err(anyhow!("Invalid column name in filter: {}", column));
-            }
-            match column {
-                "bitrate" => {
-                    let field_bitrate = parse_bitrate(&fields[4]).unwrap_or(0.0);
-                    let threshold = value.parse::<f64>().unwrap_or(0.0);
-                    if field_bitrate < threshold {
-                        return Ok(false);
-                    }
-                }
-                "duration" => {
-                    let field_duration = parse_duration_to_secs(&fields[2]);
-                    let threshold = parse_human_duration(value)
-                        .unwrap_or_else(|| value.parse::<f64>().unwrap_or(0.0));
-                    if field_duration < threshold {
-                        return Ok(false);
-                    }
-                }
-                _ => {}
-            }
-            continue;
+    #[test]
+    fn parse_filter_expr_forms() {
+        let cases = [
+            ("format=hevc", (false, "format", "=", "hevc")),
+            ("format:hevc", (false, "format", "=", "hevc")),
+            ("format:=:hevc", (false, "format", "=", "hevc")),
+            ("codec=hevc", (false, "format", "=", "hevc")),
+            ("format!=hevc", (true, "format", "=", "hevc")),
+            ("format:!=:hevc", (true, "format", "=", "hevc")),
+            ("!format=hevc", (true, "format", "=", "hevc")),
+            ("not:format=hevc", (true, "format", "=", "hevc")),
+            ("!format!=hevc", (false, "format", "=", "hevc")),
+            ("format=h264,hevc", (false, "format", "=", "h264,hevc")),
+            ("size>=2GB", (false, "size", ">=", "2GB")),
+            ("size<=2GB", (false, "size", "<=", "2GB")),
+            ("bitrate:<:5", (false, "bitrate", "<", "5")),
+            ("duration>01:30:00", (false, "duration", ">", "01:30:00")),
+            ("duration:>:01:30:00", (false, "duration", ">", "01:30:00")),
+            (
+                "audio_lang:has:jpn,eng",
+                (false, "audio_lang", "has", "jpn,eng"),
+            ),
+        ];
+        for (filter, expected) in cases {
+            assert_eq!(parse_filter_expr(filter).unwrap(), expected, "{}", filter);
         }
+        assert!(parse_filter_expr("bogus=1").is_err());
+        assert!(parse_filter_expr("hevc").is_err());
+    }
 
-        // If no < or >, then use the equals format
-        let parts: Vec<&str> = filter.split('=').collect();
-        if parts.len() != 2 {
-            continue;
+    #[test]
+    fn validate_filters_rejects_bad_values() {
+        for filter in [
+            "size>2GB",
+            "duration>01:30:00",
+            "duration<90min",
+            "created<2024-05",
+            "resolution>=1280x720",
+            "aspect=2.39:1",
+            "format=h264,hevc",
+            "filename=",
+            "audio_lang:has:jpn",
+        ] {
+            assert!(
+                validate_filters(&[filter.to_string()]).is_ok(),
+                "{}",
+                filter
+            );
+        }
+        for filter in [
+            "bogus=1",
+            "format>hevc",
+            "share>10",
+            "size>big",
+            "size>",
+            "duration>1:2:3:4",
+            "created<yesterday",
+            "fps=fast",
+            "audio_lang:has:jpn,",
+        ] {
+            assert!(
+                validate_filters(&[filter.to_string()]).is_err(),
+                "{}",
+                filter
+            );
         }
+    }
 
-        let (column, value) = (parts[0], parts[1]);
-        if !VALID_COLUMNS.contains(&column) {
-            return Err(anyhow!("Invalid column name in filter: {}", column));
+    #[test]
+    fn filter_matches_rows() {
+        // 2024-05-01T00:00:00Z
+        let created = 1_714_521_600.0;
+        let row = filter_row(
+            &[
+                ("filename", "Amélie.mkv"),
+                ("format", "hevc"),
+                ("size", "1.86 GB"),
+                ("audio_lang", "eng,jpn"),
+                ("aspect", "2.40:1"),
+            ],
+            &[
+                ("size", 2e9),
+                ("bitrate", 4e6),
+                ("duration", 6000.0),
+                ("resolution", 1920.0 * 800.0),
+                ("aspect", 2.4),
+                ("created", created),
+            ],
+        );
+        let cases = [
+            ("format=hevc", true),
+            ("format=HEVC", true),
+            ("format=h264,hevc", true),
+            ("format=av1", false),
+            ("format!=hevc", false),
+            ("!format=av1", true),
+            ("not:format=hevc", false),
+            ("!format!=hevc", true),
+            ("filename=amélie", true),
+            ("filename=amelie", false),
+            // `=` is a minimum for sizes and a maximum for bitrates
+            ("size=1GB", true),
+            ("size=3GB", false),
+            ("size<1GB", false),
+            ("bitrate=5", true),
+            ("bitrate>5", false),
+            ("duration>01:30:00", true),
+            ("duration<90min", false),
+            ("resolution>1280x720", true),
+            ("aspect=2.39:1", true),
+            ("aspect=16:9", false),
+            ("audio_lang:has:jpn", true),
+            ("audio_lang:has:fre,ger", false),
+            ("created=2024", true),
+            ("created=2024-05", true),
+            ("created<2024", false),
+            ("created>=2024-05-01", true),
+        ];
+        for (filter, expected) in cases {
+            assert_eq!(
+                filter_matches(&row, filter, false, false).unwrap(),
+                expected,
+                "{}",
+                filter
+            );
         }
+        assert!(filter_matches(&row, "filename=amelie", true, false).unwrap());
+    }
 
-        let matches = match column {
-            "filename" => {
-                let filename = &fields[0].to_lowercase();
-                let pattern = value.to_lowercase();
-                filename.contains(&pattern)
-            }
-            "size" => {
-                let field_size = parse_size(&fields[1]) as f64;
-                let threshold = parse_size(value) as f64;
-                field_size >= threshold
-            }
-            "duration" => {
-                let field_duration = parse_duration_to_secs(&fields[2]);
-                let threshold = parse_human_duration(value)
-                    .unwrap_or_else(|| value.parse::<f64>().unwrap_or(0.0));
-                field_duration >= threshold
-            }
-            "fps" => {
-                let field_fps = fields[3].parse::<f64>().unwrap_or(0.0);
-                let threshold = value.parse::<f64>().unwrap_or(0.0);
-                field_fps >= threshold
-            }
-            "bitrate" => {
-                let field_bitrate = parse_bitrate(&fields[4]).unwrap_or(0.0);
-                let threshold = value.parse::<f64>().unwrap_or(0.0);
-                field_bitrate <= threshold
-            }
-            "resolution" => {
-                let field_res = &fields[5];
-                field_res == value
-            }
-            _ => true,
+    #[test]
+    fn missing_dates_never_pass_a_date_comparison() {
+        let row = filter_row(&[], &[]);
+        assert!(!filter_matches(&row, "created<2020", false, false).unwrap());
+        assert!(!filter_matches(&row, "created>2020", false, false).unwrap());
+        assert!(!filter_matches(&row, "created=2020", false, false).unwrap());
+        // Negated, files without a date are the ones that pass
+        assert!(filter_matches(&row, "!created<2020", false, false).unwrap());
+        assert!(filter_matches(&row, "not:created>2020", false, false).unwrap());
+    }
+
+    #[test]
+    fn text_subtitle_events_counts_cues() {
+        let srt = "\u{feff}1\n00:00:01,000 --> 00:00:04,000\nHello\n\n2\n00:00:05,000 --> 00:01:02,500\nWorld\n";
+        assert_eq!(text_subtitle_events(srt, "srt"), (2, 62.5));
+        let vtt = "WEBVTT\n\n00:01.000 --> 00:04.000 position:10% align:start\nHi\n\n1:00:00.000 --> 1:00:02.250 line:0\nBye\n";
+        assert_eq!(text_subtitle_events(vtt, "vtt"), (2, 3602.25));
+        let ass = "[Events]\n\
+            Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+            Dialogue: 0,0:00:01.00,0:00:04.50,Default,,0,0,0,,Hello, world\n\
+            Comment: 0,0:00:05.00,0:00:09.00,Default,,0,0,0,,note\n";
+        assert_eq!(text_subtitle_events(ass, "ass"), (1, 4.5));
+    }
+
+    #[test]
+    fn parse_subtitle_timestamp_formats() {
+        assert_eq!(parse_subtitle_timestamp("00:01:02,500"), Some(62.5));
+        assert_eq!(parse_subtitle_timestamp("1:00:02.25"), Some(3602.25));
+        assert_eq!(parse_subtitle_timestamp("02:03.5"), Some(123.5));
+        assert_eq!(parse_subtitle_timestamp("soon"), None);
+    }
+
+    #[test]
+    fn pgs_events_stops_at_truncated_segment() {
+        let segment = |pts: u32, segment_type: u8, payload: &[u8], size: u16| {
+            let mut bytes = b"PG".to_vec();
+            bytes.extend(pts.to_be_bytes());
+            bytes.extend(0u32.to_be_bytes());
+            bytes.push(segment_type);
+            bytes.extend(size.to_be_bytes());
+            bytes.extend(payload);
+            bytes
         };
+        // Composition segment showing one object, then one clearing the screen
+        let mut shown = [0u8; 11];
+        shown[10] = 1;
+        let mut data = segment(90_000, 0x16, &shown, 11);
+        data.extend(segment(180_000, 0x16, &[0; 11], 11));
+        data.extend(segment(0, 0x80, &[], 0));
+        assert_eq!(pgs_events(&data), (1, 2.0));
+        // Cut off inside the payload: the composition can't be read
+        data.extend(segment(270_000, 0x16, &shown[..5], 11));
+        assert_eq!(pgs_events(&data).0, 1);
+        // Cut off inside the header
+        data.extend(b"PG\x00");
+        assert_eq!(pgs_events(&data).0, 1);
+    }
 
-        if !matches {
-            return Ok(false);
-        }
+    #[test]
+    fn subtitle_name_hints_reads_language_and_flags() {
+        let hints = |name: &str| subtitle_name_hints(Path::new(name));
+        assert_eq!(
+            hints("Movie.pt-BR.forced.srt"),
+            (Some("pt-BR".to_string()), vec!["forced"])
+        );
+        assert_eq!(
+            hints("Movie (2020).en.sdh.srt"),
+            (Some("en".to_string()), vec!["sdh"])
+        );
+        assert_eq!(hints("Movie.fre.srt"), (Some("fre".to_string()), vec![]));
+        // Title words aren't mistaken for languages
+        assert_eq!(hints("The.Movie.2020.srt"), (None, vec![]));
+        assert_eq!(hints("Movie.dts.srt"), (None, vec![]));
+    }
+
+    #[test]
+    fn parse_mediainfo_export_xml() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<MediaInfo xmlns="https://mediaarea.net/mediainfo" version="2.0">
+  <media ref="/movies/a.mkv">
+    <track type="General"><Format>Matroska</Format><FileSize>1000</FileSize></track>
+    <track type="Video"><Format>HEVC</Format><Width>1920</Width></track>
+  </media>
+  <media ref="/movies/b.mp4">
+    <track type="General"><Format>MPEG-4</Format></track>
+  </media>
+</MediaInfo>"#;
+        let media = parse_mediainfo_export(xml).unwrap();
+        assert_eq!(media.len(), 2);
+        assert_eq!(media[0]["@ref"], "/movies/a.mkv");
+        assert_eq!(media[0]["track"][0]["@type"], "General");
+        assert_eq!(media[0]["track"][0]["FileSize"], "1000");
+        assert_eq!(media[0]["track"][1]["Width"], "1920");
+        assert_eq!(media[1]["track"][0]["Format"], "MPEG-4");
     }
 
-    Ok(true)
+    #[test]
+    fn parse_mediainfo_export_json() {
+        let single = r#"{"media": {"@ref": "/movies/a.mkv", "track": [{"@type": "General"}]}}"#;
+        let media = parse_mediainfo_export(single).unwrap();
+        assert_eq!(media.len(), 1);
+        assert_eq!(media[0]["@ref"], "/movies/a.mkv");
+
+        let several = r#"[
+            {"media": {"@ref": "/movies/a.mkv", "track": []}},
+            {"media": [{"@ref": "/movies/b.mkv"}, {"@ref": "/movies/c.mkv"}]}
+        ]"#;
+        let refs: Vec<_> = parse_mediainfo_export(several)
+            .unwrap()
+            .iter()
+            .map(|media| media["@ref"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(refs, ["/movies/a.mkv", "/movies/b.mkv", "/movies/c.mkv"]);
+
+        assert!(parse_mediainfo_export(r#"{"streams": []}"#).is_err());
+        assert!(parse_mediainfo_export("<MediaInfo>").is_err());
+    }
+
+    #[test]
+    fn segment_key_patterns() {
+        let key = |name: &str| segment_key(Path::new(name));
+        let part = |name: &str, index| Some((PathBuf::from(name), index));
+        assert_eq!(key("rec/movie.001.mkv"), part("rec/movie.mkv", 1));
+        assert_eq!(key("rec/movie.002.MKV"), part("rec/movie.mkv", 2));
+        assert_eq!(key("VIDEO_TS/VTS_01_1.VOB"), part("VIDEO_TS/VTS_01.vob", 1));
+        assert_eq!(key("VIDEO_TS/VTS_01_0.VOB"), None);
+        assert_eq!(key("GX010123.MP4"), part("GX0123.mp4", 1));
+        assert_eq!(key("GX020123.MP4"), part("GX0123.mp4", 2));
+        assert_eq!(key("GOPR0123.MP4"), part("GOPRO0123.mp4", 0));
+        assert_eq!(key("GP010123.MP4"), part("GOPRO0123.mp4", 1));
+        assert_eq!(key("movie.2020.mkv"), None);
+        assert_eq!(key("movie.mkv"), None);
+    }
+
+    #[test]
+    fn stitch_segments_merges_parts() {
+        let probe = |size: u64, duration: f64| -> FFProbeOutput {
+            serde_json::from_value(serde_json::json!({
+                "streams": [],
+                "format": {
+                    "filename": "",
+                    "size": size.to_string(),
+                    "duration": duration.to_string(),
+                },
+            }))
+            .unwrap()
+        };
+        let files = vec![
+            (PathBuf::from("GX020123.MP4"), probe(3_000, 30.0)),
+            (PathBuf::from("GX010123.MP4"), probe(1_000, 10.0)),
+            (PathBuf::from("other.mkv"), probe(500, 5.0)),
+            (PathBuf::from("single.001.mkv"), probe(700, 7.0)),
+        ];
+        let mut counts = HashMap::new();
+        let mut stitched = stitch_segments(files, &mut counts);
+        stitched.sort_by(|a, b| a.0.cmp(&b.0));
+        let paths: Vec<_> = stitched
+            .iter()
+            .map(|(path, _)| path.to_str().unwrap())
+            .collect();
+        assert_eq!(paths, ["GX010123.MP4", "other.mkv", "single.001.mkv"]);
+        // The first part stands in for the group, with the combined numbers
+        let format = &stitched[0].1.format;
+        assert_eq!(format.size, "4000");
+        assert_eq!(format.duration, "40");
+        assert_eq!(format.bit_rate.as_deref(), Some("800"));
+        assert_eq!(counts, HashMap::from([(PathBuf::from("GX010123.MP4"), 2)]));
+    }
 }